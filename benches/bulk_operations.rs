@@ -0,0 +1,68 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rust_doc_db::doc_db::{
+    clear_db, insert_entities_to_db, make_sure_db_exists, model::DocDbEntry, update_entities_in_db,
+    DbConfig,
+};
+use rust_doc_db::example_domains::pim::fake_data_generator::generate_people;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const DOCUMENTS_COUNT: u32 = 100_000;
+
+fn get_bench_config() -> DbConfig {
+    DbConfig {
+        sqlite_db_full_filename: "tmp/bench_db/data.db".to_string(),
+        text_db_path: "tmp/bench_db/files/".to_string(),
+        ..Default::default()
+    }
+}
+
+fn generate_documents() -> Vec<Value> {
+    generate_people(DOCUMENTS_COUNT)
+        .iter()
+        .map(|person| json!(person))
+        .collect()
+}
+
+fn bulk_operations(c: &mut Criterion) {
+    let db_config = get_bench_config();
+    make_sure_db_exists(&db_config).unwrap();
+    let documents = generate_documents();
+
+    let mut group = c.benchmark_group("bulk_operations");
+    // single iteration writes 100k YAML files, so it takes seconds rather than milliseconds
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(300));
+    group.throughput(Throughput::Elements(DOCUMENTS_COUNT as u64));
+
+    group.bench_function("insert_many_100k", |b| {
+        b.iter_batched(
+            || clear_db(&db_config).unwrap(),
+            |_| insert_entities_to_db(&documents, &db_config).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.bench_function("update_many_100k", |b| {
+        b.iter_batched(
+            || {
+                clear_db(&db_config).unwrap();
+                let ids = insert_entities_to_db(&documents, &db_config).unwrap();
+                ids.into_iter()
+                    .map(|id| DocDbEntry {
+                        id,
+                        entity: json!({ "tags": ["benchmark"] }),
+                    })
+                    .collect::<Vec<_>>()
+            },
+            |entries| update_entities_in_db(&entries, &db_config).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+
+    clear_db(&db_config).unwrap();
+}
+
+criterion_group!(benches, bulk_operations);
+criterion_main!(benches);
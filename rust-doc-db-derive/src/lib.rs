@@ -0,0 +1,238 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    ext::IdentExt, meta::ParseNestedMeta, parse_macro_input, Attribute, Data, DeriveInput, Expr,
+    Fields, GenericArgument, Ident, LitInt, LitStr, PathArguments, Token, Type,
+};
+
+/// Derives `DocDbSchema` and `DocDbDocument` for a domain document type
+///
+/// Struct attributes: `#[doc_db(collection = "people", schema_version = 1)]`
+/// Field attributes: `#[doc_db(indexed)]`, `#[doc_db(owned)]`, `#[doc_db(reference)]` (field holds
+/// ULID(s) of other documents)
+///
+/// Fields are named as serialized, honouring `#[serde(rename = "...")]`, while their `DocField`
+/// constants are named by the Rust field (e.g. `r#type` as `TYPE`).
+#[proc_macro_derive(DocDbDocument, attributes(doc_db))]
+pub fn derive_doc_db_document(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, true)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `DocDbSchema` for a type embedded in documents (e.g. an address of a person)
+#[proc_macro_derive(DocDbSchema, attributes(doc_db))]
+pub fn derive_doc_db_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, false)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct DocumentField {
+    /// Name in stored documents
+    name: String,
+    /// Rust field name without `r#` prefix
+    ident: Ident,
+    ty: Type,
+    indexed: bool,
+    owned: bool,
+    reference: bool,
+}
+
+fn expand(input: &DeriveInput, is_document: bool) -> syn::Result<proc_macro2::TokenStream> {
+    let ident = &input.ident;
+    let fields = parse_fields(input)?;
+
+    if serde_attribute(&input.attrs, "rename_all")?.is_some() {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "serde rename_all is not supported by doc_db, rename fields one by one",
+        ));
+    }
+
+    let mut collection: Option<String> = None;
+    let mut schema_version: u32 = 1;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("doc_db")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("collection") {
+                collection = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else if meta.path.is_ident("schema_version") {
+                schema_version = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported doc_db struct attribute"))
+            }
+        })?;
+    }
+
+    let title = ident.to_string();
+    let properties = fields.iter().map(|field| {
+        let name = &field.name;
+        let ty = &field.ty;
+        let required = !is_option(ty);
+        quote! {
+            (#name, <#ty as ::rust_doc_db::doc_db::document::DocDbSchema>::json_schema(), #required)
+        }
+    });
+    let version = if is_document {
+        quote! { Some(#schema_version) }
+    } else {
+        quote! { None }
+    };
+    let schema_impl = quote! {
+        impl ::rust_doc_db::doc_db::document::DocDbSchema for #ident {
+            fn json_schema() -> ::serde_json::Value {
+                ::rust_doc_db::doc_db::document::object_schema(
+                    #title,
+                    #version,
+                    vec![#(#properties),*],
+                )
+            }
+        }
+    };
+    if !is_document {
+        return Ok(schema_impl);
+    }
+
+    let collection = match collection {
+        Some(name) => quote! { Some(#name) },
+        None => quote! { None },
+    };
+    let indexed_fields = fields.iter().filter(|f| f.indexed).map(|f| &f.name);
+    let owned_fields = fields.iter().filter(|f| f.owned).map(|f| &f.name);
+    let reference_fields = fields.iter().filter(|f| f.reference).map(|f| &f.name);
+    let field_accessors = fields.iter().map(|field| {
+        let name = &field.name;
+        let const_ident = Ident::new(&field.ident.to_string().to_uppercase(), Span::call_site());
+        quote! {
+            pub const #const_ident: ::rust_doc_db::doc_db::document::DocField =
+                ::rust_doc_db::doc_db::document::DocField::new(#name);
+        }
+    });
+
+    Ok(quote! {
+        #schema_impl
+
+        impl ::rust_doc_db::doc_db::document::DocDbDocument for #ident {
+            const COLLECTION: Option<&'static str> = #collection;
+            const SCHEMA_VERSION: u32 = #schema_version;
+            const INDEXED_FIELDS: &'static [&'static str] = &[#(#indexed_fields),*];
+            const OWNED_FIELDS: &'static [&'static str] = &[#(#owned_fields),*];
+            const REFERENCE_FIELDS: &'static [&'static str] = &[#(#reference_fields),*];
+        }
+
+        impl #ident {
+            #(#field_accessors)*
+        }
+    })
+}
+
+fn parse_fields(input: &DeriveInput) -> syn::Result<Vec<DocumentField>> {
+    let named_fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(named) => &named.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "doc_db documents must have named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "doc_db documents must be structs",
+            ))
+        }
+    };
+
+    let mut fields = Vec::new();
+    for field in named_fields {
+        let ident = field.ident.as_ref().unwrap().unraw();
+        let name = match serde_attribute(&field.attrs, "rename")? {
+            Some(name) => name,
+            None => ident.to_string(),
+        };
+        let mut document_field = DocumentField {
+            name,
+            ident,
+            ty: field.ty.clone(),
+            indexed: false,
+            owned: false,
+            reference: false,
+        };
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("doc_db")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("indexed") {
+                    document_field.indexed = true;
+                    Ok(())
+                } else if meta.path.is_ident("owned") {
+                    document_field.owned = true;
+                    Ok(())
+                } else if meta.path.is_ident("reference") {
+                    document_field.reference = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported doc_db field attribute"))
+                }
+            })?;
+        }
+        fields.push(document_field);
+    }
+    Ok(fields)
+}
+
+/// Value of `#[serde(<name> = "...")]` attribute, serialized one of
+/// `#[serde(<name>(serialize = "...", deserialize = "..."))]`
+fn serde_attribute(attrs: &[Attribute], name: &str) -> syn::Result<Option<String>> {
+    let mut value: Option<String> = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident(name) {
+                return skip_meta(meta);
+            }
+            if meta.input.peek(Token![=]) {
+                value = Some(meta.value()?.parse::<LitStr>()?.value());
+                return Ok(());
+            }
+            meta.parse_nested_meta(|direction| {
+                if direction.path.is_ident("serialize") {
+                    value = Some(direction.value()?.parse::<LitStr>()?.value());
+                    Ok(())
+                } else {
+                    skip_meta(direction)
+                }
+            })
+        })?;
+    }
+    Ok(value)
+}
+
+/// Skips serde attribute which doesn't affect field names, e.g. `default` or `with = "..."`
+fn skip_meta(meta: ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<Expr>()?;
+        Ok(())
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(skip_meta)
+    } else {
+        Ok(())
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    if let Type::Path(type_path) = ty {
+        if let Some(segment) = type_path.path.segments.last() {
+            if segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    return matches!(args.args.first(), Some(GenericArgument::Type(_)));
+                }
+            }
+        }
+    }
+    false
+}
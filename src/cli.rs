@@ -1,4 +1,7 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
+use serde_json::Value;
 
 #[derive(Debug, Parser)]
 #[command(arg_required_else_help(true))]
@@ -12,4 +15,110 @@ pub enum Commands {
     VerifyDb {},
     ClearDb {},
     GenerateData {},
+    /// Adds tag to an entity, or to all entities matching filters
+    #[command(allow_missing_positional = true)]
+    Tag {
+        #[arg(required_unless_present = "filters", conflicts_with = "filters")]
+        entity_id: Option<String>,
+        tag: String,
+        /// `<FIELD>=<VALUE>` condition of tagged entities (value is JSON, e.g. `30` or `true`, or a
+        /// string), all have to match when repeated
+        #[arg(long = "filter", value_name = "FIELD=VALUE", value_parser = parse_filter)]
+        filters: Vec<(String, Value)>,
+    },
+    /// Removes tag from an entity, or from all entities matching filters
+    #[command(allow_missing_positional = true)]
+    Untag {
+        #[arg(required_unless_present = "filters", conflicts_with = "filters")]
+        entity_id: Option<String>,
+        tag: String,
+        /// `<FIELD>=<VALUE>` condition of untagged entities (value is JSON, e.g. `30` or `true`, or a
+        /// string), all have to match when repeated
+        #[arg(long = "filter", value_name = "FIELD=VALUE", value_parser = parse_filter)]
+        filters: Vec<(String, Value)>,
+    },
+    /// Attaches a file to an entity, replacing attachment with the same name
+    Attach {
+        entity_id: String,
+        file: PathBuf,
+        /// Name of the attachment, name of the file when not set
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Writes content of an entity attachment to a file
+    Extract {
+        entity_id: String,
+        name: String,
+        /// File to write, attachment name in the current directory when not set
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Lists attachments of an entity with their sizes
+    Attachments {
+        entity_id: String,
+    },
+    /// Lists links going out of and pointing at an entity
+    Links {
+        entity_id: String,
+    },
+    /// Imports YAML files changed outside of the DB (e.g. pulled with Git) into SQLite
+    SyncFromFiles {},
+    /// Keeps importing YAML files edited by hand into SQLite, watching them with inotify
+    WatchFiles {
+        /// How long changes have to settle before they are imported
+        #[arg(long, default_value_t = 300)]
+        debounce_ms: u64,
+    },
+    /// Moves YAML files to directories of the configured layout
+    Relayout {},
+    /// Lists Git commits changing an entity, newest first
+    GitLog {
+        entity_id: String,
+    },
+    /// Tails the change feed, printing changes as JSON lines
+    Watch {
+        /// Sequence number to print changes after, only new changes are printed when not set
+        #[arg(long)]
+        since: Option<u64>,
+        /// How often the feed is polled
+        #[arg(long, default_value_t = 1000)]
+        interval_ms: u64,
+    },
+    /// Deletes expired entities, repeatedly when interval is set
+    SweepExpired {
+        /// How often the sweep runs in the background, it runs once when not set
+        #[arg(long)]
+        interval_secs: Option<u64>,
+    },
+    /// Lists tags with number of tagged entities
+    Tags {
+        #[command(subcommand)]
+        command: Option<TagsCommands>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TagsCommands {
+    /// Lists tags with number of tagged entities
+    List {},
+    /// Lists entities having any (or all) of the tags
+    Find {
+        tags: Vec<String>,
+        #[arg(long)]
+        all: bool,
+    },
+    /// Renames tag in all entities, merging it with existing one
+    Rename { old_tag: String, new_tag: String },
+}
+
+/// Value is parsed as JSON, so that numbers and booleans match fields of their type, other values
+/// (e.g. `people`) are strings
+fn parse_filter(filter: &str) -> Result<(String, Value), String> {
+    match filter.split_once('=') {
+        Some((field_name, value)) if !field_name.is_empty() => {
+            let value = serde_json::from_str(value).unwrap_or(Value::String(value.to_string()));
+            Ok((field_name.to_string(), value))
+        }
+        _ => Err(format!("{:?} is not <FIELD>=<VALUE>", filter)),
+    }
 }
@@ -1,2 +1,8 @@
+use crate::doc_db::layout::FileLayout;
+
 pub const SQLITE_DB_FULL_FILENAME: &str = "db/data.db";
 pub const YAML_FILES_ROOT_PATH: &str = "db/files/";
+/// Commits YAML files to a Git repository in `YAML_FILES_ROOT_PATH` (runs `git` on every write)
+pub const GIT_VERSIONING_ENABLED: bool = false;
+/// Directories of YAML files, run `relayout` after changing it to move existing files
+pub const FILE_LAYOUT: FileLayout = FileLayout::Flat;
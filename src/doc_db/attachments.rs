@@ -0,0 +1,197 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+use ulid::Ulid;
+
+use super::{
+    errors::DocDbError, file_storage::write_file_atomically, git, model::DocDbAttachment,
+    sql_storage::*, DbConfig, DocDbResult,
+};
+
+/// Directory of attachment blobs in `text_db_path`, hidden so that it isn't scanned for entity
+/// files (but versioned with them when Git is enabled)
+pub const ATTACHMENTS_DIR_NAME: &str = ".attachments";
+
+/// Attaches content to the entity under the name (e.g. `photo.jpg`), replacing attachment with the
+/// same name
+///
+/// Content is stored once per SHA-256 hash in `.attachments/<ab>/<hash>`, however many entities
+/// it is attached to.
+pub fn put_attachment(
+    entity_id: &Ulid,
+    name: &str,
+    content: &[u8],
+    db_config: &DbConfig,
+) -> DocDbResult<DocDbAttachment> {
+    log::info!("Attaching {} to entity {}", name, entity_id);
+    validate_attachment_name(name)?;
+    let attachment = DocDbAttachment {
+        entity_id: *entity_id,
+        name: name.to_string(),
+        hash: format!("{:x}", Sha256::digest(content)),
+        size: content.len() as u64,
+    };
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    connection.execute("BEGIN IMMEDIATE")?;
+    let result = select_entry_from_sqlite(&connection, entity_id)
+        .and_then(|entry| entry.ok_or_else(|| DocDbError::not_found(entity_id)))
+        .and_then(|_| store_blob(&attachment.hash, content, db_config))
+        .and_then(|_| upsert_attachment_in_sqlite(&connection, &attachment))
+        .and_then(|previous_hash| {
+            connection.execute("COMMIT")?;
+            Ok(previous_hash)
+        });
+    let previous_hash = match result {
+        Ok(previous_hash) => previous_hash,
+        Err(err) => {
+            connection.execute("ROLLBACK")?;
+            remove_unused_blobs(&HashSet::from([attachment.hash]), db_config)?;
+            return Err(err);
+        }
+    };
+    // attachment is committed, so cleanup failures are only logged
+    if let Err(err) = remove_unused_blobs(&previous_hash.into_iter().collect(), db_config) {
+        log::error!("Unable to remove replaced attachment: {}", err);
+    }
+    commit_blobs_to_git(&format!("attach {} to {}", name, entity_id), db_config);
+    Ok(attachment)
+}
+
+/// Content of the attachment, `None` when the entity has no attachment with the name
+pub fn get_attachment(
+    entity_id: &Ulid,
+    name: &str,
+    db_config: &DbConfig,
+) -> DocDbResult<Option<Vec<u8>>> {
+    log::info!("Obtaining attachment {} of entity {}", name, entity_id);
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    let attachment = match select_attachment_from_sqlite(&connection, entity_id, name)? {
+        Some(attachment) => attachment,
+        None => return Ok(None),
+    };
+    let blob_path = blob_path(&attachment.hash, db_config);
+    let content = fs::read(&blob_path)
+        .map_err(|err| DocDbError::corrupted(blob_path.display().to_string(), err))?;
+    if format!("{:x}", Sha256::digest(&content)) != attachment.hash {
+        return Err(DocDbError::corrupted(
+            blob_path.display().to_string(),
+            "content doesn't match its hash",
+        ));
+    }
+    Ok(Some(content))
+}
+
+/// Attachments of the entity ordered by name
+pub fn list_attachments(
+    entity_id: &Ulid,
+    db_config: &DbConfig,
+) -> DocDbResult<Vec<DocDbAttachment>> {
+    log::info!("Listing attachments of entity {}", entity_id);
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    select_attachments_from_sqlite(&connection, entity_id)
+}
+
+/// Removes the attachment, returns whether the entity had it
+pub fn delete_attachment(entity_id: &Ulid, name: &str, db_config: &DbConfig) -> DocDbResult<bool> {
+    log::info!("Removing attachment {} of entity {}", name, entity_id);
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    let hash = match delete_attachment_from_sqlite(&connection, entity_id, name)? {
+        Some(hash) => hash,
+        None => return Ok(false),
+    };
+    // attachment is deleted, so cleanup failures are only logged
+    if let Err(err) = remove_unused_blobs(&HashSet::from([hash]), db_config) {
+        log::error!("Unable to remove detached attachment: {}", err);
+    }
+    commit_blobs_to_git(&format!("detach {} from {}", name, entity_id), db_config);
+    Ok(true)
+}
+
+/// Removes blobs with the hashes which are no longer content of any attachment, e.g. of deleted
+/// entities
+pub(super) fn remove_unused_blobs(
+    hashes: &HashSet<String>,
+    db_config: &DbConfig,
+) -> DocDbResult<()> {
+    if hashes.is_empty() {
+        return Ok(());
+    }
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    for hash in hashes {
+        let blob_path = blob_path(hash, db_config);
+        if is_attachment_hash_used_in_sqlite(&connection, hash)? || !blob_path.exists() {
+            continue;
+        }
+        log::debug!("Removing attachment blob {}", hash);
+        fs::remove_file(&blob_path)?;
+        if let Some(dir) = blob_path.parent() {
+            // fails when other blobs are left in it
+            let _ = fs::remove_dir(dir);
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn remove_all_blobs(db_config: &DbConfig) -> DocDbResult<()> {
+    let attachments_dir = attachments_dir(db_config);
+    if attachments_dir.exists() {
+        log::info!("Removing all attachment blobs");
+        fs::remove_dir_all(attachments_dir)?;
+    }
+    Ok(())
+}
+
+/// Blobs left uncommitted when Git fails are added by the next commit
+fn commit_blobs_to_git(message: &str, db_config: &DbConfig) {
+    if let Err(err) = git::commit_files(&[], Some(message), db_config) {
+        log::error!("Unable to commit attachments to Git: {}", err);
+    }
+}
+
+/// Names are file names, so that attachments can be extracted under them
+fn validate_attachment_name(name: &str) -> DocDbResult<()> {
+    if name.trim() != name || name.is_empty() || name == "." || name == ".." {
+        return Err(DocDbError::validation(format!(
+            "Attachment name {:?} has to be non empty and trimmed",
+            name
+        )));
+    }
+    if name.contains(['/', '\\']) {
+        return Err(DocDbError::validation(format!(
+            "Attachment name {:?} can't contain path separators",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Writes blob unless content with the same hash is already stored
+fn store_blob(hash: &str, content: &[u8], db_config: &DbConfig) -> DocDbResult<()> {
+    let blob_path = blob_path(hash, db_config);
+    if blob_path.exists() {
+        return Ok(());
+    }
+    if let Some(dir) = blob_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_file_atomically(&blob_path, content)
+}
+
+/// Whether the file is named like a blob, by SHA-256 hash of its content
+pub(super) fn is_blob_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.len() == 64 && name.bytes().all(|byte| byte.is_ascii_hexdigit()))
+}
+
+fn attachments_dir(db_config: &DbConfig) -> PathBuf {
+    Path::new(&db_config.text_db_path).join(ATTACHMENTS_DIR_NAME)
+}
+
+fn blob_path(hash: &str, db_config: &DbConfig) -> PathBuf {
+    attachments_dir(db_config).join(&hash[0..2]).join(hash)
+}
@@ -0,0 +1,85 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use super::{
+    model::DocDbChange,
+    sql_storage::{
+        get_sqlite_connection, select_changes_since_from_sqlite, select_last_change_seq_from_sqlite,
+    },
+    DbConfig, DocDbResult,
+};
+
+type ChangeCallback = Box<dyn Fn(&DocDbChange) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+/// In-process callbacks notified about changes after every committed transaction
+///
+/// Callbacks run on the committing thread, while the registry is locked, so they must not
+/// subscribe or unsubscribe.
+#[derive(Default)]
+pub struct ChangeSubscribers {
+    next_id: AtomicU64,
+    callbacks: Mutex<Vec<(SubscriptionId, ChangeCallback)>>,
+}
+
+impl ChangeSubscribers {
+    pub fn subscribe(
+        &self,
+        callback: impl Fn(&DocDbChange) + Send + Sync + 'static,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.callbacks
+            .lock()
+            .unwrap()
+            .push((id, Box::new(callback)));
+        id
+    }
+
+    pub fn unsubscribe(&self, id: SubscriptionId) {
+        self.callbacks
+            .lock()
+            .unwrap()
+            .retain(|(subscription_id, _)| *subscription_id != id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callbacks.lock().unwrap().is_empty()
+    }
+
+    pub(super) fn notify(&self, changes: &[DocDbChange]) {
+        let callbacks = self.callbacks.lock().unwrap();
+        for change in changes {
+            for (_, callback) in callbacks.iter() {
+                callback(change);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ChangeSubscribers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChangeSubscribers")
+            .field("count", &self.callbacks.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// Changes with sequence number greater than `seq`, oldest first (0 returns the whole feed)
+pub fn changes_since(seq: u64, db_config: &DbConfig) -> DocDbResult<Vec<DocDbChange>> {
+    log::info!("Obtaining changes since {}", seq);
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    select_changes_since_from_sqlite(&connection, seq)
+}
+
+/// Sequence number of the latest change, to start following the feed from
+pub fn last_change_seq(db_config: &DbConfig) -> DocDbResult<u64> {
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    select_last_change_seq_from_sqlite(&connection)
+}
@@ -0,0 +1,250 @@
+use std::{fmt, sync::Mutex};
+
+use serde_json::{json, Value};
+use ulid::Ulid;
+
+use super::{
+    document::{DocQuery, COLLECTION_FIELD_NAME},
+    errors::DocDbError,
+    sql_storage::{
+        record_unique_constraint_in_sqlite, replace_unique_values_in_sqlite,
+        select_unique_constraint_definition_from_sqlite, select_unique_value_owner_from_sqlite,
+    },
+    transaction::Transaction,
+    DocDbResult,
+};
+
+/// Suffix of a field path matching every element of an array field, e.g. `phones[*]`
+pub const ARRAY_ELEMENTS_SUFFIX: &str = "[*]";
+
+/// Combination of field values which no two documents can share
+///
+/// Fields are dot separated paths, e.g. `email` or `address.city`. Array field with `[*]` suffix
+/// is matched per element, so `phones[*]` makes every phone number unique. Documents missing any
+/// of the fields (or having it `null`) are not constrained, like `NULL` in SQL unique indexes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniqueConstraint {
+    pub name: String,
+    pub field_paths: Vec<String>,
+    /// Constraint applies only to documents of the collection, to all documents when not set
+    pub collection: Option<String>,
+}
+
+impl UniqueConstraint {
+    pub fn new(name: &str, field_paths: &[&str]) -> Self {
+        UniqueConstraint {
+            name: name.to_string(),
+            field_paths: field_paths.iter().map(|path| path.to_string()).collect(),
+            collection: None,
+        }
+    }
+
+    pub fn in_collection(mut self, collection: &str) -> Self {
+        self.collection = Some(collection.to_string());
+        self
+    }
+
+    fn applies_to(&self, entity: &Value) -> bool {
+        match &self.collection {
+            Some(collection) => entity[COLLECTION_FIELD_NAME].as_str() == Some(collection),
+            None => true,
+        }
+    }
+
+    /// Definition the stored values were built for, they are rebuilt when it changes
+    fn definition(&self) -> String {
+        json!({ "fieldPaths": self.field_paths, "collection": self.collection }).to_string()
+    }
+
+    /// Keys of every combination of the entity's values, empty when the constraint doesn't
+    /// apply to the entity or some field is missing
+    fn value_keys(&self, entity: &Value) -> Vec<String> {
+        if !self.applies_to(entity) {
+            return Vec::new();
+        }
+        let mut field_values: Vec<Vec<String>> = Vec::new();
+        for field_path in &self.field_paths {
+            let values: Vec<&Value> = match field_path.strip_suffix(ARRAY_ELEMENTS_SUFFIX) {
+                Some(array_path) => field_value(entity, array_path)
+                    .and_then(Value::as_array)
+                    .map(|elements| elements.iter().collect())
+                    .unwrap_or_default(),
+                None => field_value(entity, field_path).into_iter().collect(),
+            };
+            let values: Vec<String> = values
+                .into_iter()
+                .filter(|value| !value.is_null())
+                .map(Value::to_string)
+                .collect();
+            if values.is_empty() {
+                return Vec::new();
+            }
+            field_values.push(values);
+        }
+        value_combinations(&field_values)
+            .into_iter()
+            .map(|values| format!("[{}]", values.join(",")))
+            .collect()
+    }
+
+    /// Returns ID of another document having the same values as the entity, if any
+    ///
+    /// Stored owner of the values is checked to still have them, so that values of expired
+    /// documents can be taken over.
+    fn find_conflict(
+        &self,
+        entity_id: &Ulid,
+        value_keys: &[String],
+        transaction: &Transaction,
+    ) -> DocDbResult<Option<Ulid>> {
+        for value_key in value_keys {
+            let owner_id = match select_unique_value_owner_from_sqlite(
+                transaction.connection(),
+                &self.name,
+                value_key,
+            )? {
+                Some(owner_id) if owner_id != *entity_id => owner_id,
+                _ => continue,
+            };
+            if let Some(owner) = transaction.get(&owner_id)? {
+                if self.value_keys(&owner.entity).contains(value_key) {
+                    return Ok(Some(owner_id));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Builds stored values from existing documents, when the constraint is new or changed
+    fn build_values(&self, transaction: &Transaction) -> DocDbResult<()> {
+        let connection = transaction.connection();
+        let definition = self.definition();
+        if select_unique_constraint_definition_from_sqlite(connection, &self.name)?.as_ref()
+            == Some(&definition)
+        {
+            return Ok(());
+        }
+        log::info!("Building values of unique constraint {}", self.name);
+        record_unique_constraint_in_sqlite(connection, &self.name, &definition)?;
+        let query = match &self.collection {
+            Some(collection) => DocQuery::field_eq(COLLECTION_FIELD_NAME, collection.as_str()),
+            None => DocQuery::condition_with_values("1".to_string(), Vec::new()),
+        };
+        for entry in transaction.query(&query)? {
+            // documents already violating the constraint keep it held by the first of them
+            let mut free_value_keys: Vec<String> = Vec::new();
+            for value_key in self.value_keys(&entry.entity) {
+                if select_unique_value_owner_from_sqlite(connection, &self.name, &value_key)?
+                    .is_none()
+                {
+                    free_value_keys.push(value_key);
+                }
+            }
+            replace_unique_values_in_sqlite(connection, &self.name, &entry.id, &free_value_keys)?;
+        }
+        Ok(())
+    }
+}
+
+fn field_value<'a>(entity: &'a Value, field_path: &str) -> Option<&'a Value> {
+    field_path
+        .split('.')
+        .try_fold(entity, |value, field_name| value.get(field_name))
+}
+
+/// Every combination taking one value of each field
+fn value_combinations(field_values: &[Vec<String>]) -> Vec<Vec<String>> {
+    field_values
+        .iter()
+        .fold(vec![Vec::new()], |combinations, values| {
+            combinations
+                .iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push(value.clone());
+                        combination
+                    })
+                })
+                .collect()
+        })
+}
+
+/// Unique constraints checked on every insert and update, inside the write transaction
+///
+/// Values of documents are kept in the keyed `unique_values` SQLite table, so a check is a lookup
+/// per value. They are built from stored documents when a constraint is new or its definition
+/// changed, documents written by processes not declaring the constraint are missed until then.
+#[derive(Default)]
+pub struct UniqueConstraints {
+    constraints: Mutex<Vec<UniqueConstraint>>,
+}
+
+impl UniqueConstraints {
+    pub fn declare(&self, constraint: UniqueConstraint) {
+        log::info!(
+            "Declaring unique constraint {} on {:?}",
+            constraint.name,
+            constraint.field_paths
+        );
+        self.constraints.lock().unwrap().push(constraint);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constraints.lock().unwrap().is_empty()
+    }
+
+    /// Builds values of constraints declared since the DB was last written, called at the start
+    /// of every transaction
+    pub(super) fn build_values(&self, transaction: &Transaction) -> DocDbResult<()> {
+        for constraint in self.constraints.lock().unwrap().iter() {
+            constraint.build_values(transaction)?;
+        }
+        Ok(())
+    }
+
+    /// Fails if another document (including ones written earlier in the transaction) has the
+    /// same values of constrained fields as the stored entity, records the entity's values
+    /// otherwise
+    pub(super) fn check(
+        &self,
+        entity_id: &Ulid,
+        entity: &Value,
+        transaction: &Transaction,
+    ) -> DocDbResult<()> {
+        for constraint in self.constraints.lock().unwrap().iter() {
+            let value_keys = constraint.value_keys(entity);
+            if let Some(conflicting_id) =
+                constraint.find_conflict(entity_id, &value_keys, transaction)?
+            {
+                return Err(DocDbError::UniqueConstraint {
+                    constraint: constraint.name.clone(),
+                    entity_id: entity_id.to_string(),
+                    conflicting_id: conflicting_id.to_string(),
+                });
+            }
+            replace_unique_values_in_sqlite(
+                transaction.connection(),
+                &constraint.name,
+                entity_id,
+                &value_keys,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for UniqueConstraints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self
+            .constraints
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|constraint| constraint.name.clone())
+            .collect();
+        f.debug_struct("UniqueConstraints")
+            .field("constraints", &names)
+            .finish()
+    }
+}
@@ -0,0 +1,173 @@
+use std::{collections::HashMap, fmt, sync::Mutex};
+
+use serde_json::Value;
+
+use super::{
+    document::{IndexDefinition, COLLECTION_FIELD_NAME},
+    domain::DomainView,
+    errors::DocDbError,
+    sql_storage::prepare_query,
+    DocDbResult,
+};
+
+type ComputeFn = Box<dyn Fn(&Value) -> Value + Send + Sync>;
+
+enum DerivedFieldExpression {
+    Computed(ComputeFn),
+    /// SQLite expression referring to the document as `content`
+    Sql(String),
+}
+
+/// Top-level field computed from other fields of the document on every write
+pub struct DerivedField {
+    pub name: String,
+    pub indexed: bool,
+    expression: DerivedFieldExpression,
+}
+
+impl DerivedField {
+    pub fn computed(name: &str, compute: impl Fn(&Value) -> Value + Send + Sync + 'static) -> Self {
+        DerivedField {
+            name: name.to_string(),
+            indexed: false,
+            expression: DerivedFieldExpression::Computed(Box::new(compute)),
+        }
+    }
+
+    /// Field computed by SQLite, e.g. `json_array_length(content, '$.phones')`
+    pub fn sql(name: &str, sql_expression: &str) -> Self {
+        DerivedField {
+            name: name.to_string(),
+            indexed: false,
+            expression: DerivedFieldExpression::Sql(sql_expression.to_string()),
+        }
+    }
+
+    pub fn indexed(mut self) -> Self {
+        self.indexed = true;
+        self
+    }
+
+    fn compute(&self, entity: &Value, connection: &sqlite::Connection) -> DocDbResult<Value> {
+        match &self.expression {
+            DerivedFieldExpression::Computed(compute) => Ok(compute(entity)),
+            DerivedFieldExpression::Sql(sql_expression) => {
+                let mut statement = prepare_query(
+                    connection,
+                    format!(
+                        "SELECT {} FROM (SELECT :content AS content)",
+                        sql_expression
+                    ),
+                )?;
+                statement.bind((":content", entity.to_string().as_str()))?;
+                statement.next()?;
+                match statement.read::<sqlite::Value, _>(0)? {
+                    sqlite::Value::Null => Ok(Value::Null),
+                    sqlite::Value::Integer(value) => Ok(Value::from(value)),
+                    sqlite::Value::Float(value) => Ok(Value::from(value)),
+                    sqlite::Value::String(value) => Ok(Value::String(value)),
+                    sqlite::Value::Binary(_) => Err(DocDbError::validation(format!(
+                        "Derived field {} can't be binary",
+                        self.name
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+/// Derived fields per collection, documents are matched by their `_collection` field
+#[derive(Default)]
+pub struct DerivedFields {
+    fields: Mutex<HashMap<String, Vec<DerivedField>>>,
+}
+
+impl DerivedFields {
+    pub fn define(&self, collection: &str, field: DerivedField) {
+        log::info!("Defining derived field {} in {}", field.name, collection);
+        self.fields
+            .lock()
+            .unwrap()
+            .entry(collection.to_string())
+            .or_default()
+            .push(field);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.lock().unwrap().is_empty()
+    }
+
+    pub fn collections(&self) -> Vec<String> {
+        self.fields.lock().unwrap().keys().cloned().collect()
+    }
+
+    pub fn index_definitions(&self) -> Vec<IndexDefinition> {
+        let mut index_definitions: Vec<IndexDefinition> = Vec::new();
+        for field in self.fields.lock().unwrap().values().flatten() {
+            let index_definition = IndexDefinition::new(&field.name);
+            if field.indexed && !index_definitions.contains(&index_definition) {
+                index_definitions.push(index_definition);
+            }
+        }
+        index_definitions
+    }
+
+    /// Derived fields are maintained by the engine, so no domain can own them
+    pub fn check_not_owned_by(&self, domain: &DomainView) -> DocDbResult<()> {
+        let fields = self.fields.lock().unwrap();
+        let collection_fields = match &domain.collection {
+            Some(collection) => fields.get(collection),
+            None => None,
+        };
+        match collection_fields
+            .into_iter()
+            .flatten()
+            .find(|field| domain.owns(&field.name))
+        {
+            Some(field) => Err(DocDbError::DomainViolation {
+                domain: domain.name.clone(),
+                field_path: field.name.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Recomputes derived fields of the entity, if it belongs to a collection having any
+    pub(super) fn apply(
+        &self,
+        entity: &mut Value,
+        connection: &sqlite::Connection,
+    ) -> DocDbResult<()> {
+        let fields = self.fields.lock().unwrap();
+        let collection_fields = match entity[COLLECTION_FIELD_NAME]
+            .as_str()
+            .and_then(|collection| fields.get(collection))
+        {
+            Some(collection_fields) => collection_fields,
+            None => return Ok(()),
+        };
+        for field in collection_fields {
+            let value = field.compute(entity, connection)?;
+            entity[&field.name] = value;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DerivedFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = self.fields.lock().unwrap();
+        let mut names: Vec<String> = fields
+            .iter()
+            .flat_map(|(collection, fields)| {
+                fields
+                    .iter()
+                    .map(move |field| format!("{}.{}", collection, field.name))
+            })
+            .collect();
+        names.sort();
+        f.debug_struct("DerivedFields")
+            .field("fields", &names)
+            .finish()
+    }
+}
@@ -0,0 +1,311 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use ulid::Ulid;
+
+use super::sql_storage::expired_condition;
+
+pub use rust_doc_db_derive::{DocDbDocument, DocDbSchema};
+
+/// Collection of documents stored by a domain, e.g. `{"_collection": "people"}`
+pub const COLLECTION_FIELD_NAME: &str = "_collection";
+
+/// Type which can describe itself as JSON Schema
+pub trait DocDbSchema {
+    fn json_schema() -> Value;
+}
+
+/// Domain type stored as a document in DB (usually derived with `#[derive(DocDbDocument)]`)
+pub trait DocDbDocument: DocDbSchema + Serialize + DeserializeOwned {
+    const COLLECTION: Option<&'static str>;
+    const SCHEMA_VERSION: u32;
+    const INDEXED_FIELDS: &'static [&'static str];
+    const OWNED_FIELDS: &'static [&'static str];
+    /// Fields holding ULID (or array of ULIDs) of other documents
+    const REFERENCE_FIELDS: &'static [&'static str];
+
+    fn index_definitions() -> Vec<IndexDefinition> {
+        Self::INDEXED_FIELDS
+            .iter()
+            .map(|field_name| IndexDefinition::new(field_name))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexDefinition {
+    pub name: String,
+    pub json_path: String,
+}
+
+impl IndexDefinition {
+    pub fn new(field_name: &str) -> Self {
+        IndexDefinition {
+            name: format!("idx_entities_{}", field_name.replace('.', "_")),
+            json_path: format!("$.{}", field_name),
+        }
+    }
+
+    pub fn to_sql(&self) -> String {
+        format!(
+            "CREATE INDEX IF NOT EXISTS `{}` ON entities (json_extract(content, '{}'))",
+            self.name, self.json_path
+        )
+    }
+}
+
+/// Placeholder of a value bound as JSON text, so that it's compared with field values of its type
+/// (e.g. `5` matches numbers and `"5"` strings), `null` doesn't match any value
+const JSON_VALUE: &str = "json_extract({}, '$')";
+
+/// Typed accessor of a top-level document field, e.g. `Person::FIRSTNAME`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocField {
+    name: &'static str,
+}
+
+impl DocField {
+    pub const fn new(name: &'static str) -> Self {
+        DocField { name }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn json_path(&self) -> String {
+        format!("$.{}", self.name)
+    }
+
+    /// Field value equals given value (e.g. `"Piotr"`, `5` or `true`)
+    pub fn eq(&self, value: impl Into<Value>) -> DocQuery {
+        DocQuery::condition(
+            format!(
+                "json_extract(content, '{}') = {}",
+                self.json_path(),
+                JSON_VALUE
+            ),
+            value.into(),
+        )
+    }
+
+    /// Field value differs from given value
+    pub fn ne(&self, value: impl Into<Value>) -> DocQuery {
+        DocQuery::condition(
+            format!(
+                "json_extract(content, '{}') <> {}",
+                self.json_path(),
+                JSON_VALUE
+            ),
+            value.into(),
+        )
+    }
+
+    /// Array field contains given value
+    pub fn contains(&self, value: impl Into<Value>) -> DocQuery {
+        DocQuery::condition(
+            format!(
+                "EXISTS (SELECT 1 FROM json_each(content, '{}') WHERE json_each.value = {})",
+                self.json_path(),
+                JSON_VALUE
+            ),
+            value.into(),
+        )
+    }
+
+    /// Field of document(s) referenced by ULID(s) stored in this field, e.g. city of employer
+    pub fn referenced(&self, name: &'static str) -> ReferencedField {
+        ReferencedField {
+            reference: *self,
+            name,
+        }
+    }
+}
+
+/// Typed accessor of a field of referenced document, e.g. `Person::EMPLOYER_ID.referenced("city")`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReferencedField {
+    reference: DocField,
+    name: &'static str,
+}
+
+impl ReferencedField {
+    /// Any of referenced documents has field value equal to given value
+    pub fn eq(&self, value: impl Into<Value>) -> DocQuery {
+        DocQuery::condition(self.referenced_condition("="), value.into())
+    }
+
+    /// Any of referenced documents has field value different from given value
+    pub fn ne(&self, value: impl Into<Value>) -> DocQuery {
+        DocQuery::condition(self.referenced_condition("<>"), value.into())
+    }
+
+    fn referenced_condition(&self, operator: &str) -> String {
+        // referencing document is available as `entities`, as referenced one is aliased
+        format!(
+            "EXISTS (SELECT 1 FROM entities AS referenced \
+            WHERE referenced.id IN (SELECT value FROM json_each(entities.content, '{}')) \
+            AND NOT {} AND json_extract(referenced.content, '$.{}') {} {})",
+            self.reference.json_path(),
+            expired_condition("referenced"),
+            self.name,
+            operator,
+            JSON_VALUE
+        )
+    }
+}
+
+/// Where clause built from typed field conditions
+///
+/// Each condition keeps `{}` in place of its parameters, named parameters are assigned on rendering
+/// so conditions can be freely combined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocQuery {
+    conditions: Vec<(String, Vec<String>)>,
+}
+
+impl DocQuery {
+    /// Condition with `JSON_VALUE` placeholder for the value
+    fn condition(sql_template: String, value: Value) -> Self {
+        DocQuery::condition_with_values(sql_template, vec![value.to_string()])
+    }
+
+    /// Condition with `{}` placeholder for every value, in order
+    pub fn condition_with_values(sql_template: String, values: Vec<String>) -> Self {
+        DocQuery {
+            conditions: vec![(sql_template, values)],
+        }
+    }
+
+    /// Field value equals given value, for field names known only at runtime (e.g. CLI filters)
+    pub fn field_eq(field_name: &str, value: impl Into<Value>) -> Self {
+        DocQuery::condition_with_values(
+            format!("json_extract(content, {{}}) = {}", JSON_VALUE),
+            vec![format!("$.{}", field_name), value.into().to_string()],
+        )
+    }
+
+    /// Matches single entity
+    pub fn with_id(entity_id: &Ulid) -> Self {
+        DocQuery::condition_with_values("id = {}".to_string(), vec![entity_id.to_string()])
+    }
+
+    pub fn and(mut self, other: DocQuery) -> Self {
+        self.conditions.extend(other.conditions);
+        self
+    }
+
+    /// Returns where clause with named parameters (`:p0`, `:p1`, ...) and values to bind
+    pub fn to_where_clause(&self) -> (String, Vec<(String, String)>) {
+        let mut sql_conditions: Vec<String> = Vec::new();
+        let mut params: Vec<(String, String)> = Vec::new();
+        for (sql_template, values) in &self.conditions {
+            let mut sql_condition = sql_template.clone();
+            for value in values {
+                let param_name = format!("p{}", params.len());
+                sql_condition = sql_condition.replacen("{}", &format!(":{}", param_name), 1);
+                params.push((param_name, value.clone()));
+            }
+            sql_conditions.push(sql_condition);
+        }
+        (sql_conditions.join(" AND "), params)
+    }
+}
+
+/// Builds JSON Schema of an object from `(field name, field schema, is required)` triples
+pub fn object_schema(
+    title: &str,
+    schema_version: Option<u32>,
+    properties: Vec<(&str, Value, bool)>,
+) -> Value {
+    let mut json_properties = serde_json::Map::new();
+    let mut required: Vec<Value> = Vec::new();
+    for (field_name, field_schema, is_required) in properties {
+        json_properties.insert(field_name.to_string(), field_schema);
+        if is_required {
+            required.push(Value::String(field_name.to_string()));
+        }
+    }
+    let mut schema = json!({
+        "title": title,
+        "type": "object",
+        "properties": json_properties,
+        "required": required,
+    });
+    if let Some(version) = schema_version {
+        schema["$schema"] = json!("https://json-schema.org/draft/2020-12/schema");
+        schema["version"] = json!(version);
+    }
+    schema
+}
+
+macro_rules! impl_primitive_schema {
+    ($json_type:literal: $($t:ty),*) => {
+        $(
+            impl DocDbSchema for $t {
+                fn json_schema() -> Value {
+                    json!({ "type": $json_type })
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive_schema!("string": String, &str, char);
+impl_primitive_schema!("integer": i8, i16, i32, i64, u8, u16, u32, u64, usize, isize);
+impl_primitive_schema!("number": f32, f64);
+impl_primitive_schema!("boolean": bool);
+
+impl<T: DocDbSchema> DocDbSchema for Vec<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: DocDbSchema> DocDbSchema for Option<T> {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+}
+
+impl DocDbSchema for Value {
+    fn json_schema() -> Value {
+        json!({})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DocField, IndexDefinition};
+
+    #[test]
+    fn can_combine_field_conditions() {
+        const FIRSTNAME: DocField = DocField::new("firstname");
+        const PHONES: DocField = DocField::new("phones");
+
+        let (where_clause, params) = FIRSTNAME
+            .eq("Piotr")
+            .and(PHONES.contains("+48 123 456 789"))
+            .to_where_clause();
+        assert_eq!(
+            where_clause,
+            "json_extract(content, '$.firstname') = json_extract(:p0, '$') AND EXISTS (SELECT 1 FROM json_each(content, '$.phones') WHERE json_each.value = json_extract(:p1, '$'))"
+        );
+        // values are bound as JSON, so that they are compared with values of the same type
+        assert_eq!(
+            params,
+            vec![
+                ("p0".to_string(), "\"Piotr\"".to_string()),
+                ("p1".to_string(), "\"+48 123 456 789\"".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn can_build_index_definition() {
+        let index = IndexDefinition::new("firstname");
+        assert_eq!(
+            index.to_sql(),
+            "CREATE INDEX IF NOT EXISTS `idx_entities_firstname` ON entities (json_extract(content, '$.firstname'))"
+        );
+    }
+}
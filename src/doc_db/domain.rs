@@ -0,0 +1,283 @@
+use serde_json::{Map, Value};
+
+use super::{document::DocDbDocument, errors::DocDbError, DocDbResult};
+
+/// Fields of documents owned (writable) and readable by a single domain
+///
+/// Field paths are dot separated (e.g. `address.street`), owning / reading a field means owning /
+/// reading all of its nested fields.
+#[derive(Debug, Clone)]
+pub struct DomainView {
+    pub name: String,
+    /// Collection documents inserted in this domain are stamped with
+    pub collection: Option<String>,
+    pub owned_fields: Vec<String>,
+    pub readable_fields: Vec<String>,
+}
+
+impl DomainView {
+    pub fn new(name: &str) -> Self {
+        DomainView {
+            name: name.to_string(),
+            collection: None,
+            owned_fields: Vec::new(),
+            readable_fields: Vec::new(),
+        }
+    }
+
+    pub fn owning(mut self, field_paths: &[&str]) -> Self {
+        self.owned_fields
+            .extend(field_paths.iter().map(|path| path.to_string()));
+        self
+    }
+
+    pub fn in_collection(mut self, collection: &str) -> Self {
+        self.collection = Some(collection.to_string());
+        self
+    }
+
+    pub fn owning_document<T: DocDbDocument>(self) -> Self {
+        let domain = self.owning(T::OWNED_FIELDS);
+        match T::COLLECTION {
+            Some(collection) => domain.in_collection(collection),
+            None => domain,
+        }
+    }
+
+    pub fn reading(mut self, field_paths: &[&str]) -> Self {
+        self.readable_fields
+            .extend(field_paths.iter().map(|path| path.to_string()));
+        self
+    }
+
+    pub fn owns(&self, field_path: &str) -> bool {
+        self.owned_fields
+            .iter()
+            .any(|owned| covers(owned, field_path))
+    }
+
+    pub fn can_read(&self, field_path: &str) -> bool {
+        self.owns(field_path)
+            || self
+                .readable_fields
+                .iter()
+                .any(|readable| covers(readable, field_path))
+    }
+
+    /// Returns copy of the entity limited to fields visible in this domain
+    pub fn project(&self, entity: &Value) -> Value {
+        self.project_object(entity, "")
+    }
+
+    fn project_object(&self, entity: &Value, prefix: &str) -> Value {
+        let mut projected = Map::new();
+        if let Some(object) = entity.as_object() {
+            for (key, value) in object {
+                let field_path = join_path(prefix, key);
+                if self.can_read(&field_path) {
+                    projected.insert(key.clone(), value.clone());
+                } else if self.has_visible_descendants(&field_path) && value.is_object() {
+                    projected.insert(key.clone(), self.project_object(value, &field_path));
+                }
+            }
+        }
+        Value::Object(projected)
+    }
+
+    fn has_visible_descendants(&self, field_path: &str) -> bool {
+        let nested_prefix = format!("{}.", field_path);
+        self.owned_fields
+            .iter()
+            .chain(self.readable_fields.iter())
+            .any(|path| path.starts_with(&nested_prefix))
+    }
+
+    /// Applies fields owned by this domain on top of the existing entity
+    ///
+    /// Owned fields missing in the new entity are removed, not owned fields have to be either
+    /// missing or left unchanged.
+    pub fn apply_update(&self, existing_entity: &Value, new_entity: &Value) -> DocDbResult<Value> {
+        let mut merged_entity = existing_entity.clone();
+        self.apply_object_update(existing_entity, new_entity, &mut merged_entity, "")?;
+        Ok(merged_entity)
+    }
+
+    fn apply_object_update(
+        &self,
+        existing_entity: &Value,
+        new_entity: &Value,
+        merged_entity: &mut Value,
+        prefix: &str,
+    ) -> DocDbResult<()> {
+        let new_object = new_entity.as_object().ok_or_else(|| {
+            DocDbError::validation(format!("Domain {} can only store objects", self.name))
+        })?;
+
+        for (key, value) in new_object {
+            let field_path = join_path(prefix, key);
+            let existing_value = existing_entity.get(key).unwrap_or(&Value::Null);
+            if self.owns(&field_path) {
+                merged_entity[key] = value.clone();
+            } else if self.has_owned_descendants(&field_path) && value.is_object() {
+                if !merged_entity[key].is_object() {
+                    merged_entity[key] = Value::Object(Map::new());
+                }
+                self.apply_object_update(
+                    existing_value,
+                    value,
+                    &mut merged_entity[key],
+                    &field_path,
+                )?;
+            } else if existing_value != value {
+                return Err(DocDbError::DomainViolation {
+                    domain: self.name.clone(),
+                    field_path,
+                });
+            }
+        }
+
+        if let Some(merged_object) = merged_entity.as_object_mut() {
+            merged_object.retain(|key, _| {
+                let field_path = join_path(prefix, key);
+                !self.owns(&field_path) || new_object.contains_key(key)
+            });
+        }
+        Ok(())
+    }
+
+    fn conflicting_owned_field(&self, other: &DomainView) -> Option<&String> {
+        self.owned_fields.iter().find(|field_path| {
+            other.owns(field_path)
+                || other
+                    .owned_fields
+                    .iter()
+                    .any(|other_path| covers(field_path, other_path))
+        })
+    }
+
+    fn has_owned_descendants(&self, field_path: &str) -> bool {
+        let nested_prefix = format!("{}.", field_path);
+        self.owned_fields
+            .iter()
+            .any(|path| path.starts_with(&nested_prefix))
+    }
+}
+
+/// Domains known to the application, each document field can be owned by one domain only
+#[derive(Debug, Default)]
+pub struct DomainRegistry {
+    domains: Vec<DomainView>,
+}
+
+impl DomainRegistry {
+    pub fn new() -> Self {
+        DomainRegistry::default()
+    }
+
+    pub fn register(&mut self, domain: DomainView) -> DocDbResult<()> {
+        for registered in &self.domains {
+            if registered.name == domain.name {
+                return Err(DocDbError::Conflict {
+                    reason: format!("Domain {} already registered", domain.name),
+                });
+            }
+            if let Some(field_path) = domain.conflicting_owned_field(registered) {
+                return Err(DocDbError::DomainViolation {
+                    domain: domain.name.clone(),
+                    field_path: field_path.clone(),
+                });
+            }
+        }
+        log::info!(
+            "Registering domain {} owning {:?}",
+            domain.name,
+            domain.owned_fields
+        );
+        self.domains.push(domain);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DomainView> {
+        self.domains.iter().find(|domain| domain.name == name)
+    }
+
+    pub fn owner_of(&self, field_path: &str) -> Option<&DomainView> {
+        self.domains.iter().find(|domain| domain.owns(field_path))
+    }
+}
+
+fn covers(field_path: &str, checked_path: &str) -> bool {
+    checked_path == field_path || checked_path.starts_with(&format!("{}.", field_path))
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{DomainRegistry, DomainView};
+
+    #[test]
+    fn domain_update_only_touches_owned_fields() {
+        let domain = DomainView::new("admin")
+            .owning(&["tags", "meta.reviewed"])
+            .reading(&["firstname"]);
+        let existing_entity = json!({
+            "firstname": "John",
+            "tags": ["tag1"],
+            "meta": { "reviewed": false, "source": "import" },
+        });
+
+        let merged_entity = domain
+            .apply_update(
+                &existing_entity,
+                &json!({ "firstname": "John", "meta": { "reviewed": true } }),
+            )
+            .unwrap();
+        assert_eq!(
+            merged_entity,
+            json!({
+                "firstname": "John",
+                "meta": { "reviewed": true, "source": "import" },
+            })
+        );
+
+        let forbidden_update =
+            domain.apply_update(&existing_entity, &json!({ "firstname": "Mark" }));
+        assert!(forbidden_update.is_err());
+    }
+
+    #[test]
+    fn domain_view_hides_not_visible_fields() {
+        let domain = DomainView::new("admin")
+            .owning(&["tags"])
+            .reading(&["meta.source"]);
+        let entity = json!({
+            "firstname": "John",
+            "tags": ["tag1"],
+            "meta": { "reviewed": false, "source": "import" },
+        });
+        assert_eq!(
+            domain.project(&entity),
+            json!({ "tags": ["tag1"], "meta": { "source": "import" } })
+        );
+    }
+
+    #[test]
+    fn field_can_have_single_owner() {
+        let mut registry = DomainRegistry::new();
+        registry
+            .register(DomainView::new("pim").owning(&["addresses"]))
+            .unwrap();
+        assert!(registry
+            .register(DomainView::new("post").owning(&["addresses.street"]))
+            .is_err());
+    }
+}
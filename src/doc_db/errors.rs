@@ -1,31 +1,101 @@
 use thiserror::Error;
 
-#[derive(Error, Debug, Clone)]
+type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Error, Debug)]
 pub enum DocDbError {
-    #[error("InternalError: [{inner_type_name:?}]: {message:?}")]
-    Internal {
-        message: String,
-        inner_type_name: String,
-    },
-    #[error("FileStorageError: [{inner_type_name:?}]: {message:?}")]
+    /// Broken assumption of the DB itself, not caused by the caller
+    #[error("InternalError: {message}")]
+    Internal { message: String },
+    #[error("FileStorageError: {message}")]
     FileStorage {
         message: String,
-        inner_type_name: String,
+        #[source]
+        source: BoxedError,
     },
-    #[error("SqlStorageError: [{inner_type_name:?}]: {message:?}")]
+    #[error("SqlStorageError: {source}")]
     SqlStorage {
-        message: String,
-        inner_type_name: String,
+        #[source]
+        source: sqlite::Error,
+    },
+    /// Entity required by the operation does not exist (or is expired)
+    #[error("NotFoundError: entity {id:?} not found")]
+    NotFound { id: String },
+    /// Operation clashes with the current state, e.g. registering the same domain twice
+    #[error("ConflictError: {reason}")]
+    Conflict { reason: String },
+    /// Input rejected by the DB, e.g. entity which is not a JSON object
+    #[error("ValidationError: {reason}")]
+    Validation {
+        reason: String,
+        #[source]
+        source: Option<BoxedError>,
+    },
+    /// SQL built from caller's where clause, query or expression can't be run
+    #[error("InvalidQueryError: {source} in {query:?}")]
+    InvalidQuery {
+        query: String,
+        #[source]
+        source: sqlite::Error,
     },
+    /// Stored content can't be read back, `path` is a file or SQLite row (`entities/<ULID>`)
+    #[error("CorruptionError: {path:?} can't be read: {source}")]
+    Corruption {
+        path: String,
+        #[source]
+        source: BoxedError,
+    },
+    #[error("DomainViolationError: domain {domain:?} does not own field {field_path:?}")]
+    DomainViolation { domain: String, field_path: String },
+    #[error("InvalidTagError: tag {tag:?} {reason}")]
+    InvalidTag { tag: String, reason: String },
+    #[error(
+        "RestrictedDeleteError: entity {entity_id:?} is linked as {link_type:?} by {source_id:?}"
+    )]
+    RestrictedDelete {
+        entity_id: String,
+        link_type: String,
+        source_id: String,
+    },
+    #[error("WriteHookError: hook {hook:?} failed for entity {entity_id:?}: {reason}")]
+    WriteHook {
+        hook: String,
+        entity_id: String,
+        reason: String,
+    },
+    #[error("UniqueConstraintError: entity {entity_id:?} has the same {constraint:?} as {conflicting_id:?}")]
+    UniqueConstraint {
+        constraint: String,
+        entity_id: String,
+        conflicting_id: String,
+    },
+}
+
+impl DocDbError {
+    pub fn not_found(entity_id: &ulid::Ulid) -> Self {
+        DocDbError::NotFound {
+            id: entity_id.to_string(),
+        }
+    }
+
+    pub fn validation(reason: impl Into<String>) -> Self {
+        DocDbError::Validation {
+            reason: reason.into(),
+            source: None,
+        }
+    }
+
+    pub fn corrupted(path: impl Into<String>, source: impl Into<BoxedError>) -> Self {
+        DocDbError::Corruption {
+            path: path.into(),
+            source: source.into(),
+        }
+    }
 }
 
-// TODO: is it idiomatic to implement From for all errors?
 impl std::convert::From<sqlite::Error> for DocDbError {
     fn from(err: sqlite::Error) -> Self {
-        DocDbError::SqlStorage {
-            message: err.to_string(),
-            inner_type_name: std::any::type_name::<sqlite::Error>().to_string(),
-        }
+        DocDbError::SqlStorage { source: err }
     }
 }
 
@@ -33,7 +103,7 @@ impl std::convert::From<std::io::Error> for DocDbError {
     fn from(err: std::io::Error) -> Self {
         DocDbError::FileStorage {
             message: err.to_string(),
-            inner_type_name: std::any::type_name::<std::io::Error>().to_string(),
+            source: Box::new(err),
         }
     }
 }
@@ -42,7 +112,7 @@ impl std::convert::From<glob::GlobError> for DocDbError {
     fn from(err: glob::GlobError) -> Self {
         DocDbError::FileStorage {
             message: err.to_string(),
-            inner_type_name: std::any::type_name::<glob::GlobError>().to_string(),
+            source: Box::new(err),
         }
     }
 }
@@ -51,25 +121,26 @@ impl std::convert::From<glob::PatternError> for DocDbError {
     fn from(err: glob::PatternError) -> Self {
         DocDbError::FileStorage {
             message: err.to_string(),
-            inner_type_name: std::any::type_name::<glob::PatternError>().to_string(),
+            source: Box::new(err),
         }
     }
 }
 
+/// JSON not matching expected shape, e.g. document not deserializable into domain type
 impl std::convert::From<serde_json::Error> for DocDbError {
     fn from(err: serde_json::Error) -> Self {
-        DocDbError::Internal {
-            message: err.to_string(),
-            inner_type_name: std::any::type_name::<serde_json::Error>().to_string(),
+        DocDbError::Validation {
+            reason: err.to_string(),
+            source: Some(Box::new(err)),
         }
     }
 }
 
 impl std::convert::From<ulid::DecodeError> for DocDbError {
     fn from(err: ulid::DecodeError) -> Self {
-        DocDbError::Internal {
-            message: err.to_string(),
-            inner_type_name: std::any::type_name::<ulid::DecodeError>().to_string(),
+        DocDbError::Validation {
+            reason: err.to_string(),
+            source: Some(Box::new(err)),
         }
     }
 }
@@ -78,7 +149,7 @@ impl std::convert::From<serde_yaml::Error> for DocDbError {
     fn from(err: serde_yaml::Error) -> Self {
         DocDbError::FileStorage {
             message: err.to_string(),
-            inner_type_name: std::any::type_name::<serde_yaml::Error>().to_string(),
+            source: Box::new(err),
         }
     }
 }
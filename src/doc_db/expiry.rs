@@ -0,0 +1,114 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ulid::Ulid;
+
+use super::{
+    errors::DocDbError,
+    sql_storage::{get_sqlite_connection, select_expired_entity_ids_from_sqlite},
+    DbConfig, DocDbResult,
+};
+
+/// UTC timestamp in RFC 3339 format after which the document is invisible and can be swept,
+/// e.g. `{"_expires_at": "2026-10-19T12:00:00Z"}`
+pub const EXPIRES_AT_FIELD_NAME: &str = "_expires_at";
+
+/// Sets (or with `None` clears) expiry of an entity which is not expired yet
+pub fn set_entity_expiry(
+    entity_id: &Ulid,
+    expires_at: Option<SystemTime>,
+    db_config: &DbConfig,
+) -> DocDbResult<()> {
+    log::info!("Setting expiry of entity {} to {:?}", entity_id, expires_at);
+    db_config.transaction(|tx| {
+        let mut db_entry = tx
+            .get(entity_id)?
+            .ok_or_else(|| DocDbError::not_found(entity_id))?;
+        match expires_at {
+            Some(expires_at) => db_entry
+                .set_field_value(EXPIRES_AT_FIELD_NAME, format_rfc3339(expires_at).into())?,
+            None => {
+                if let Some(entity) = db_entry.entity.as_object_mut() {
+                    entity.remove(EXPIRES_AT_FIELD_NAME);
+                }
+            }
+        }
+        tx.replace(entity_id, &db_entry.entity)
+    })
+}
+
+/// Deletes expired entities from SQLite and their YAML files, returns number of deleted entities
+///
+/// Every entity is deleted in its own transaction (applying link delete rules and hooks), so
+/// the one which can't be deleted is logged and skipped without stopping the sweep.
+pub fn sweep_expired(db_config: &DbConfig) -> DocDbResult<usize> {
+    log::info!("Sweeping expired entities");
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    let expired_ids = select_expired_entity_ids_from_sqlite(&connection)?;
+    drop(connection);
+    let mut deleted_count = 0;
+    for expired_id in expired_ids {
+        match db_config.transaction(|tx| tx.delete(&expired_id)) {
+            Ok(()) => deleted_count += 1,
+            Err(e) => log::error!("Unable to sweep expired entity {}: {}", expired_id, e),
+        }
+    }
+    log::info!("Swept {} expired entities", deleted_count);
+    Ok(deleted_count)
+}
+
+/// Formats time as `YYYY-MM-DDTHH:MM:SSZ`, times before 1970 are formatted as the epoch
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let seconds_of_day = seconds % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Gregorian date of a day counted from 1970-01-01 (Howard Hinnant's `civil_from_days`)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    pub fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_rfc3339(UNIX_EPOCH + Duration::from_secs(951_827_696)),
+            "2000-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            format_rfc3339(UNIX_EPOCH + Duration::from_secs(1_792_454_399)),
+            "2026-10-19T23:59:59Z"
+        );
+    }
+}
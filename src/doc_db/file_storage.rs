@@ -1,12 +1,51 @@
 #![allow(dead_code)]
 
-use glob::glob;
+use glob::{glob_with, MatchOptions};
 use std::fs::File;
 use std::io::prelude::*;
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
 use ulid::Ulid;
 
-use super::{DbConfig, DocDbResult};
+use super::{
+    attachments::{is_blob_file, ATTACHMENTS_DIR_NAME},
+    errors::DocDbError,
+    formats::TextFormat,
+    slugs::entity_id_of_file_stem,
+    DbConfig, DocDbResult,
+};
+
+/// Modification time and size of entity file, recorded to detect files changed outside of the DB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileState {
+    /// Nanoseconds since Unix epoch
+    pub modified_ns: i64,
+    pub size: i64,
+}
+
+impl FileState {
+    fn of(metadata: &fs::Metadata) -> DocDbResult<Self> {
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(FileState {
+            modified_ns: modified.as_nanos() as i64,
+            size: metadata.len() as i64,
+        })
+    }
+}
+
+/// Entity file as read before changing it, to be put back on rollback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawEntityFile {
+    pub path: PathBuf,
+    pub content: String,
+}
 
 pub fn create_text_db_if_not_exists(db_config: &DbConfig) -> DocDbResult<()> {
     let path = Path::new(&db_config.text_db_path);
@@ -18,36 +57,398 @@ pub fn create_text_db_if_not_exists(db_config: &DbConfig) -> DocDbResult<()> {
     Ok(())
 }
 
-pub fn store_entity_in_yaml_file(
+pub fn store_entity_in_file(
     entity_id: &Ulid,
     entity: &serde_json::Value,
     db_config: &DbConfig,
 ) -> DocDbResult<()> {
-    let yaml_str = serde_yaml::to_string(&entity)?;
-    let filename = format!("{}{}.yaml", db_config.text_db_path, entity_id.to_string());
-    log::info!("Saving entity {} text DB as {}", entity_id, filename);
-    let mut file = File::create(filename)?;
-    file.write_all(yaml_str.as_bytes())?;
+    let format = db_config.file_formats.for_entity(entity);
+    let filename = entity_file_path(entity_id, entity, db_config);
+    log::info!(
+        "Saving entity {} text DB as {}",
+        entity_id,
+        filename.display()
+    );
+    // path could change with content, e.g. when entity is moved to other collection or renamed
+    let previous_filename = find_entity_file(entity_id, db_config)?;
+    let previous_text = if previous_filename.exists()
+        && previous_filename
+            .extension()
+            .and_then(|extension| extension.to_str())
+            == Some(format.extension())
+    {
+        Some(fs::read_to_string(&previous_filename)?)
+    } else {
+        None
+    };
+    let text = match &previous_text {
+        // unchanged document keeps its text, however it was formatted by hand
+        Some(previous_text)
+            if format
+                .deserialize(previous_text)
+                .is_ok_and(|previous| &previous == entity) =>
+        {
+            previous_text.clone()
+        }
+        Some(previous_text) => format.serialize_over(entity, previous_text)?,
+        None => format.serialize(entity)?,
+    };
+    if previous_text.as_ref() != Some(&text) || previous_filename != filename {
+        create_parent_dir(&filename)?;
+        write_file_atomically(&filename, text.as_bytes())?;
+    }
+    if previous_filename != filename && previous_filename.exists() {
+        fs::remove_file(&previous_filename)?;
+        remove_empty_dirs(&previous_filename, db_config);
+    }
+    db_config.file_index.set(entity_id, Some(filename));
     Ok(())
 }
 
-pub fn delete_yaml_file(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<()> {
-    let filename = format!("{}{}.yaml", db_config.text_db_path, entity_id.to_string());
-    log::info!("Removing entity {} from text DB in {}", entity_id, filename);
+pub fn delete_entity_file(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<()> {
+    let filename = find_entity_file(entity_id, db_config)?;
+    log::info!(
+        "Removing entity {} from text DB in {}",
+        entity_id,
+        filename.display()
+    );
     fs::remove_file(&filename)?;
+    remove_empty_dirs(&filename, db_config);
+    db_config.file_index.set(entity_id, None);
     Ok(())
 }
 
-pub fn remove_all_entity_yaml_files(db_config: &DbConfig) -> DocDbResult<()> {
-    let filemask = format!("{}*.yaml", db_config.text_db_path);
+/// Raw content of entity file, `None` when there is no file for the entity
+pub fn read_entity_file(
+    entity_id: &Ulid,
+    db_config: &DbConfig,
+) -> DocDbResult<Option<RawEntityFile>> {
+    let path = find_entity_file(entity_id, db_config)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    Ok(Some(RawEntityFile { path, content }))
+}
+
+/// Puts back entity file obtained by `read_entity_file`
+pub fn restore_entity_file(
+    entity_id: &Ulid,
+    raw_file: Option<&RawEntityFile>,
+    db_config: &DbConfig,
+) -> DocDbResult<()> {
+    let current_filename = find_entity_file(entity_id, db_config)?;
     log::info!(
-        "Removing all entity files from text DB with mask {}",
-        filemask
+        "Restoring entity {} in text DB as {}",
+        entity_id,
+        current_filename.display()
     );
-    for filename_result in glob(&filemask)? {
-        let filename = filename_result?;
+    if current_filename.exists() {
+        fs::remove_file(&current_filename)?;
+        db_config.file_index.set(entity_id, None);
+    }
+    if let Some(raw_file) = raw_file {
+        create_parent_dir(&raw_file.path)?;
+        write_file_atomically(&raw_file.path, raw_file.content.as_bytes())?;
+        db_config
+            .file_index
+            .set(entity_id, Some(raw_file.path.clone()));
+    }
+    Ok(())
+}
+
+pub fn remove_all_entity_files(db_config: &DbConfig) -> DocDbResult<()> {
+    log::info!(
+        "Removing all entity files from text DB in {}",
+        db_config.text_db_path
+    );
+    for (_, filename) in list_entity_file_paths(db_config)? {
         log::debug!("Removing entity {}", filename.display());
+        fs::remove_file(&filename)?;
+        remove_empty_dirs(&filename, db_config);
+    }
+    Ok(())
+}
+
+/// State of entity file, `None` when there is no file for the entity
+pub fn get_entity_file_state(
+    entity_id: &Ulid,
+    db_config: &DbConfig,
+) -> DocDbResult<Option<FileState>> {
+    match fs::metadata(find_entity_file(entity_id, db_config)?) {
+        Ok(metadata) => Ok(Some(FileState::of(&metadata)?)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// States of all entity files, files not named by ULID are skipped
+pub fn list_entity_files(db_config: &DbConfig) -> DocDbResult<Vec<(Ulid, FileState)>> {
+    let mut file_states: Vec<(Ulid, FileState)> = Vec::new();
+    for (entity_id, filename) in list_entity_file_paths(db_config)? {
+        file_states.push((entity_id, FileState::of(&fs::metadata(&filename)?)?));
+    }
+    Ok(file_states)
+}
+
+/// Paths of all entity files in any directory (but hidden ones), files not named by ULID are
+/// skipped
+pub fn list_entity_file_paths(db_config: &DbConfig) -> DocDbResult<Vec<(Ulid, PathBuf)>> {
+    let file_paths = scan_entity_file_paths(db_config)?;
+    db_config
+        .file_index
+        .replace(file_paths.iter().cloned().collect());
+    Ok(file_paths)
+}
+
+fn scan_entity_file_paths(db_config: &DbConfig) -> DocDbResult<Vec<(Ulid, PathBuf)>> {
+    let filemask = format!("{}**/*.*", db_config.text_db_path);
+    let options = MatchOptions {
+        require_literal_leading_dot: true,
+        ..Default::default()
+    };
+    let mut file_paths: Vec<(Ulid, PathBuf)> = Vec::new();
+    for filename_result in glob_with(&filemask, options)? {
+        let filename = filename_result?;
+        if !filename.is_file() || format_of_file(&filename, db_config).is_none() {
+            continue;
+        }
+        let entity_id = match entity_id_of_file(&filename, db_config) {
+            Some(entity_id) => entity_id,
+            None => {
+                log::warn!("Skipping file {} not named by ULID", filename.display());
+                continue;
+            }
+        };
+        file_paths.push((entity_id, filename));
+    }
+    Ok(file_paths)
+}
+
+/// ULID of the entity stored in the file, `None` for other files
+pub fn entity_id_of_file(filename: &Path, db_config: &DbConfig) -> Option<Ulid> {
+    format_of_file(filename, db_config)?;
+    entity_id_of_file_stem(filename.file_stem()?.to_str()?)
+}
+
+/// Format reading the file, by its extension
+fn format_of_file(filename: &Path, db_config: &DbConfig) -> Option<Arc<dyn TextFormat>> {
+    db_config
+        .file_formats
+        .for_extension(filename.extension()?.to_str()?)
+}
+
+/// Parses entity file, which could have been edited outside of the DB
+pub fn load_entity_from_file(
+    entity_id: &Ulid,
+    db_config: &DbConfig,
+) -> DocDbResult<serde_json::Value> {
+    load_entity_from_path(&find_entity_file(entity_id, db_config)?, db_config)
+}
+
+/// Parses file in the format matching its extension
+pub fn load_entity_from_path(
+    filename: &Path,
+    db_config: &DbConfig,
+) -> DocDbResult<serde_json::Value> {
+    let path = filename.display().to_string();
+    let format = format_of_file(filename, db_config)
+        .ok_or_else(|| DocDbError::corrupted(path.clone(), "unknown file format"))?;
+    let text = fs::read_to_string(filename)?;
+    format
+        .deserialize(&text)
+        .map_err(|err| DocDbError::corrupted(path, err))
+}
+
+/// Moves entity file to its path in the configured layout (rewriting it when the configured format
+/// differs), returns whether it was moved
+pub fn move_entity_file(
+    entity_id: &Ulid,
+    filename: &Path,
+    entity: &serde_json::Value,
+    db_config: &DbConfig,
+) -> DocDbResult<bool> {
+    let new_filename = entity_file_path(entity_id, entity, db_config);
+    if new_filename == filename {
+        return Ok(false);
+    }
+    log::debug!(
+        "Moving entity {} file to {}",
+        entity_id,
+        new_filename.display()
+    );
+    create_parent_dir(&new_filename)?;
+    if new_filename.extension() == filename.extension() {
+        fs::rename(filename, &new_filename)?;
+    } else {
+        let format = db_config.file_formats.for_entity(entity);
+        write_file_atomically(&new_filename, format.serialize(entity)?.as_bytes())?;
         fs::remove_file(filename)?;
     }
+    remove_empty_dirs(filename, db_config);
+    db_config.file_index.set(entity_id, Some(new_filename));
+    Ok(true)
+}
+
+/// Updates `DbConfig.file_index` with entity file changed outside of the DB, e.g. renamed by hand
+pub fn index_changed_entity_file(entity_id: &Ulid, filename: &Path, db_config: &DbConfig) {
+    if filename.exists() {
+        db_config
+            .file_index
+            .set(entity_id, Some(filename.to_path_buf()));
+    } else {
+        db_config.file_index.remove_path(entity_id, filename);
+    }
+}
+
+/// Path of entity file relative to `text_db_path`, e.g. for Git
+pub fn relative_entity_file_path(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<PathBuf> {
+    let filename = find_entity_file(entity_id, db_config)?;
+    Ok(filename
+        .strip_prefix(&db_config.text_db_path)
+        .map(Path::to_path_buf)
+        .unwrap_or(filename))
+}
+
+/// Whether path of entity file depends on its content, not only on its ULID
+pub fn paths_depend_on_content(db_config: &DbConfig) -> bool {
+    db_config.file_layout.depends_on_content()
+        || !db_config.file_slugs.is_empty()
+        || db_config.file_formats.is_customized()
+}
+
+/// Path of entity file in the configured layout, named by the file name template, with extension
+/// of the configured format
+fn entity_file_path(entity_id: &Ulid, entity: &serde_json::Value, db_config: &DbConfig) -> PathBuf {
+    Path::new(&db_config.text_db_path)
+        .join(db_config.file_layout.entity_dir(entity_id, entity))
+        .join(format!(
+            "{}.{}",
+            db_config.file_slugs.file_stem(entity_id, entity),
+            db_config.file_formats.for_entity(entity).extension()
+        ))
+}
+
+/// Path of existing entity file, or where it would be when there is none
+///
+/// When paths depend on content, or there is no file at the layout path (e.g. it was stored with
+/// other layout and not moved by `relayout_files` yet), the file is looked up in the
+/// `DbConfig.file_index`.
+fn find_entity_file(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<PathBuf> {
+    let default_filename = entity_file_path(entity_id, &serde_json::Value::Null, db_config);
+    if !paths_depend_on_content(db_config) && default_filename.exists() {
+        return Ok(default_filename);
+    }
+    let indexed_filename = db_config.file_index.get(entity_id, || {
+        Ok(scan_entity_file_paths(db_config)?.into_iter().collect())
+    })?;
+    Ok(indexed_filename.unwrap_or(default_filename))
+}
+
+/// Writes the file through a temporary file in the same directory renamed over it, so a crash
+/// leaves either the old or the new content, never a truncated file
+pub fn write_file_atomically(filename: &Path, content: &[u8]) -> DocDbResult<()> {
+    let temp_filename = temp_file_path(filename);
+    let result = File::create(&temp_filename)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_filename, filename));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_filename);
+        return Err(err.into());
+    }
+    // makes the rename durable
+    let dir = filename
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Hidden `.<file name>.tmp` next to the file, skipped by scans and the file watcher
+fn temp_file_path(filename: &Path) -> PathBuf {
+    let file_name = filename
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    filename.with_file_name(format!(".{}{}", file_name, TEMP_FILE_SUFFIX))
+}
+
+const TEMP_FILE_SUFFIX: &str = ".tmp";
+
+/// Removes temporary files of entity files and attachment blobs left by writes interrupted by a
+/// crash, returns their number (written files themselves are intact, see `write_file_atomically`)
+pub fn remove_temp_files(db_config: &DbConfig) -> DocDbResult<usize> {
+    let root = Path::new(&db_config.text_db_path);
+    let mut temp_filenames: Vec<PathBuf> = Vec::new();
+    find_temp_files(
+        root,
+        &|path| entity_id_of_file(path, db_config).is_some(),
+        &mut temp_filenames,
+    )?;
+    let attachments_dir = root.join(ATTACHMENTS_DIR_NAME);
+    if attachments_dir.is_dir() {
+        find_temp_files(&attachments_dir, &is_blob_file, &mut temp_filenames)?;
+    }
+    for temp_filename in &temp_filenames {
+        log::warn!(
+            "Removing {} left by interrupted write",
+            temp_filename.display()
+        );
+        fs::remove_file(temp_filename)?;
+    }
+    Ok(temp_filenames.len())
+}
+
+/// Searches the directory and its subdirectories (but hidden ones, e.g. `.git`) for temporary
+/// files of files accepted by `is_written_file`
+fn find_temp_files(
+    dir: &Path,
+    is_written_file: &dyn Fn(&Path) -> bool,
+    temp_filenames: &mut Vec<PathBuf>,
+) -> DocDbResult<()> {
+    for dir_entry in fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+        let written_file_name = file_name
+            .strip_prefix('.')
+            .and_then(|name| name.strip_suffix(TEMP_FILE_SUFFIX));
+        if path.is_dir() {
+            if !file_name.starts_with('.') {
+                find_temp_files(&path, is_written_file, temp_filenames)?;
+            }
+        } else if written_file_name.is_some_and(|name| is_written_file(&path.with_file_name(name)))
+        {
+            temp_filenames.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn create_parent_dir(filename: &Path) -> DocDbResult<()> {
+    if let Some(dir) = filename.parent() {
+        fs::create_dir_all(dir)?;
+    }
     Ok(())
 }
+
+/// Removes directories of removed file left empty, up to `text_db_path`
+fn remove_empty_dirs(filename: &Path, db_config: &DbConfig) {
+    let root = Path::new(&db_config.text_db_path);
+    let mut dir = filename.parent();
+    while let Some(current_dir) = dir {
+        if current_dir == root
+            || !current_dir.starts_with(root)
+            || fs::remove_dir(current_dir).is_err()
+        {
+            break;
+        }
+        dir = current_dir.parent();
+    }
+}
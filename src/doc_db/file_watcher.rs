@@ -0,0 +1,155 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    ffi::OsString,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use ulid::Ulid;
+
+use super::{
+    errors::DocDbError,
+    file_storage::{entity_id_of_file, index_changed_entity_file},
+    sync::{sync_entity_files, sync_from_files, SyncReport},
+    DbConfig, DocDbResult,
+};
+
+/// Watches YAML files directory with inotify, importing files edited by hand into SQLite
+///
+/// Files created, written, moved or deleted in the directory (or its subdirectories of sharded
+/// layouts) are collected until no more events come within the debounce time, then imported with
+/// `sync_entity_files`. Files written by the DB itself match their recorded state and are skipped.
+pub struct FileWatcher<'a> {
+    db_config: &'a DbConfig,
+    inotify: Inotify,
+    watched_dirs: HashMap<WatchDescriptor, PathBuf>,
+    buffer: Vec<u8>,
+}
+
+impl<'a> FileWatcher<'a> {
+    /// Starts watching, changes made from now on are reported by `sync_next_changes`
+    pub fn new(db_config: &'a DbConfig) -> DocDbResult<Self> {
+        let mut watcher = FileWatcher {
+            db_config,
+            inotify: Inotify::init()?,
+            watched_dirs: HashMap::new(),
+            buffer: vec![0; 4096],
+        };
+        watcher.watch_dir(Path::new(&db_config.text_db_path))?;
+        Ok(watcher)
+    }
+
+    /// Blocks until entity files change, returns their ULIDs once changes settle for `debounce`
+    pub fn wait_for_changes(&mut self, debounce: Duration) -> DocDbResult<Vec<Ulid>> {
+        let mut entity_ids: BTreeSet<Ulid> = BTreeSet::new();
+        while entity_ids.is_empty() {
+            self.read_events(true, &mut entity_ids)?;
+        }
+        loop {
+            thread::sleep(debounce);
+            if !self.read_events(false, &mut entity_ids)? {
+                break;
+            }
+        }
+        Ok(entity_ids.into_iter().collect())
+    }
+
+    /// Waits for changes and imports them, returning files which couldn't be imported
+    pub fn sync_next_changes(
+        &mut self,
+        debounce: Duration,
+    ) -> DocDbResult<(SyncReport, Vec<(Ulid, DocDbError)>)> {
+        let entity_ids = self.wait_for_changes(debounce)?;
+        log::info!("Syncing {} changed entity files", entity_ids.len());
+        Ok(sync_entity_files(&entity_ids, self.db_config))
+    }
+
+    /// Adds ULIDs of changed files to `entity_ids`, returns whether there were any events
+    fn read_events(
+        &mut self,
+        blocking: bool,
+        entity_ids: &mut BTreeSet<Ulid>,
+    ) -> DocDbResult<bool> {
+        let events = if blocking {
+            self.inotify.read_events_blocking(&mut self.buffer)
+        } else {
+            self.inotify.read_events(&mut self.buffer)
+        };
+        let events: Vec<(WatchDescriptor, EventMask, OsString)> = match events {
+            Ok(events) => events
+                .filter_map(|event| Some((event.wd, event.mask, event.name?.to_os_string())))
+                .collect(),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        for (wd, mask, name) in events {
+            let path = match self.watched_dirs.get(&wd) {
+                Some(dir) => dir.join(name),
+                None => continue,
+            };
+            if mask.contains(EventMask::ISDIR) {
+                if mask.contains(EventMask::CREATE) || mask.contains(EventMask::MOVED_TO) {
+                    // files could have been written to the directory before it was watched
+                    entity_ids.extend(self.watch_dir(&path)?);
+                }
+            } else if let Some(entity_id) = entity_id_of_file(&path, self.db_config) {
+                index_changed_entity_file(&entity_id, &path, self.db_config);
+                entity_ids.insert(entity_id);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Watches the directory and its subdirectories (but hidden ones), returns ULIDs of files in them
+    fn watch_dir(&mut self, dir: &Path) -> DocDbResult<Vec<Ulid>> {
+        let wd = self.inotify.watches().add(
+            dir,
+            WatchMask::CREATE
+                | WatchMask::CLOSE_WRITE
+                | WatchMask::MOVED_TO
+                | WatchMask::MOVED_FROM
+                | WatchMask::DELETE,
+        )?;
+        self.watched_dirs.insert(wd, dir.to_path_buf());
+        let mut entity_ids: Vec<Ulid> = Vec::new();
+        for dir_entry in fs::read_dir(dir)? {
+            let path = dir_entry?.path();
+            let hidden = path
+                .file_name()
+                .is_none_or(|name| name.to_string_lossy().starts_with('.'));
+            if path.is_dir() && !hidden {
+                entity_ids.extend(self.watch_dir(&path)?);
+            } else if let Some(entity_id) = entity_id_of_file(&path, self.db_config) {
+                index_changed_entity_file(&entity_id, &path, self.db_config);
+                entity_ids.push(entity_id);
+            }
+        }
+        Ok(entity_ids)
+    }
+}
+
+/// Keeps importing changed files until watching fails, files which can't be imported are logged
+///
+/// Starts with `sync_from_files`, so files changed while nothing was watching are imported too
+/// (unless any of them can't be, then only changed files are imported).
+pub fn watch_files(db_config: &DbConfig, debounce: Duration) -> DocDbResult<()> {
+    log::info!("Watching files in {}", db_config.text_db_path);
+    let mut watcher = FileWatcher::new(db_config)?;
+    if let Err(err) = sync_from_files(db_config) {
+        log::error!(
+            "Unable to sync all files, watching changed ones only: {}",
+            err
+        );
+    }
+    loop {
+        let (report, failures) = watcher.sync_next_changes(debounce)?;
+        for (entity_id, err) in failures {
+            log::error!("Unable to import file of entity {}: {}", entity_id, err);
+        }
+        log::info!("Synced entities from changed files: {:?}", report);
+    }
+}
@@ -0,0 +1,285 @@
+use std::{collections::HashMap, fmt, sync::Arc, sync::Mutex};
+
+use serde_json::{Map, Value};
+
+use super::{
+    document::COLLECTION_FIELD_NAME,
+    errors::DocDbError,
+    yaml_formatter::{format_yaml, KeyOrder},
+    DocDbResult,
+};
+
+/// Text serialization of documents in files, recognised by file extension
+pub trait TextFormat: Send + Sync {
+    /// File extension without the dot, e.g. `yaml`
+    fn extension(&self) -> &str;
+
+    fn serialize(&self, entity: &Value) -> DocDbResult<String>;
+
+    /// Serializes entity replacing text of its file, formats keeping comments and formatting of
+    /// unchanged fields override it
+    fn serialize_over(&self, entity: &Value, _previous_text: &str) -> DocDbResult<String> {
+        self.serialize(entity)
+    }
+
+    fn deserialize(&self, text: &str) -> DocDbResult<Value>;
+}
+
+/// Default format, `<ULID>.yaml`, see `format_yaml`
+#[derive(Debug, Default)]
+pub struct YamlFormat {
+    pub key_order: KeyOrder,
+}
+
+impl YamlFormat {
+    pub fn new(key_order: KeyOrder) -> Self {
+        YamlFormat { key_order }
+    }
+}
+
+impl TextFormat for YamlFormat {
+    fn extension(&self) -> &str {
+        "yaml"
+    }
+
+    fn serialize(&self, entity: &Value) -> DocDbResult<String> {
+        format_yaml(entity, &self.key_order, None)
+    }
+
+    fn serialize_over(&self, entity: &Value, previous_text: &str) -> DocDbResult<String> {
+        format_yaml(entity, &self.key_order, Some(previous_text))
+    }
+
+    fn deserialize(&self, text: &str) -> DocDbResult<Value> {
+        Ok(serde_yaml::from_str(text)?)
+    }
+}
+
+/// Pretty printed JSON, `<ULID>.json`
+pub struct JsonFormat;
+
+impl TextFormat for JsonFormat {
+    fn extension(&self) -> &str {
+        "json"
+    }
+
+    fn serialize(&self, entity: &Value) -> DocDbResult<String> {
+        Ok(serde_json::to_string_pretty(entity)? + "\n")
+    }
+
+    fn deserialize(&self, text: &str) -> DocDbResult<Value> {
+        Ok(serde_json::from_str(text)?)
+    }
+}
+
+/// TOML, `<ULID>.toml`
+///
+/// TOML has no `null`, so `null` fields are not written (like missing fields they are not
+/// overridden when merging entities), documents with `null` array elements can't be stored.
+pub struct TomlFormat;
+
+impl TextFormat for TomlFormat {
+    fn extension(&self) -> &str {
+        "toml"
+    }
+
+    fn serialize(&self, entity: &Value) -> DocDbResult<String> {
+        toml::to_string(&without_null_fields(entity)).map_err(|err| DocDbError::Validation {
+            reason: format!("Unable to write document as TOML: {}", err),
+            source: Some(err.into()),
+        })
+    }
+
+    fn deserialize(&self, text: &str) -> DocDbResult<Value> {
+        toml::from_str(text).map_err(|err| DocDbError::Validation {
+            reason: format!("Unable to read TOML document: {}", err),
+            source: Some(err.into()),
+        })
+    }
+}
+
+fn without_null_fields(value: &Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .filter(|(_, field_value)| !field_value.is_null())
+                .map(|(key, field_value)| (key.clone(), without_null_fields(field_value)))
+                .collect(),
+        ),
+        Value::Array(elements) => Value::Array(elements.iter().map(without_null_fields).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Markdown with YAML front matter, `<ULID>.md`, the body field (e.g. `description` of a diary
+/// entry) becomes the Markdown body
+///
+/// ```text
+/// ---
+/// title: My day
+/// ---
+///
+/// It was *sunny*.
+/// ```
+///
+/// Body field which isn't a string stays in the front matter.
+pub struct MarkdownFormat {
+    pub body_field: String,
+}
+
+impl MarkdownFormat {
+    pub fn new(body_field: &str) -> Self {
+        MarkdownFormat {
+            body_field: body_field.to_string(),
+        }
+    }
+}
+
+const FRONT_MATTER_DELIMITER: &str = "---\n";
+
+impl TextFormat for MarkdownFormat {
+    fn extension(&self) -> &str {
+        "md"
+    }
+
+    fn serialize(&self, entity: &Value) -> DocDbResult<String> {
+        let mut front_matter = entity.clone();
+        let body = match front_matter
+            .as_object_mut()
+            .and_then(|fields| fields.remove(&self.body_field))
+        {
+            Some(Value::String(body)) => body,
+            Some(other) => {
+                front_matter[&self.body_field] = other;
+                String::new()
+            }
+            None => String::new(),
+        };
+        let mut text = format!(
+            "{}{}{}",
+            FRONT_MATTER_DELIMITER,
+            serde_yaml::to_string(&front_matter)?,
+            FRONT_MATTER_DELIMITER
+        );
+        if !body.is_empty() {
+            text.push('\n');
+            text.push_str(&body);
+        }
+        Ok(text)
+    }
+
+    fn deserialize(&self, text: &str) -> DocDbResult<Value> {
+        let (front_matter, body) = text
+            .strip_prefix(FRONT_MATTER_DELIMITER)
+            .and_then(|rest| {
+                rest.split_once(&format!("\n{}", FRONT_MATTER_DELIMITER))
+                    .map(|(front_matter, body)| (format!("{}\n", front_matter), body))
+                    .or_else(|| {
+                        rest.strip_prefix(FRONT_MATTER_DELIMITER)
+                            .map(|body| (String::new(), body))
+                    })
+            })
+            .unwrap_or_else(|| (String::new(), text));
+        let mut entity: Value = serde_yaml::from_str(&front_matter)?;
+        if entity.is_null() {
+            entity = Value::Object(Map::new());
+        }
+        let body = body.strip_prefix('\n').unwrap_or(body);
+        if !body.is_empty() {
+            if let Some(fields) = entity.as_object_mut() {
+                fields.insert(self.body_field.clone(), Value::String(body.to_string()));
+            }
+        }
+        Ok(entity)
+    }
+}
+
+/// Formats of entity files, the default one and overrides per collection
+///
+/// Files are read with the format matching their extension, so changing formats doesn't make
+/// existing files unreadable (`relayout_files` rewrites them in the configured format).
+pub struct FileFormats {
+    default: Mutex<Arc<dyn TextFormat>>,
+    collections: Mutex<HashMap<String, Arc<dyn TextFormat>>>,
+}
+
+impl Default for FileFormats {
+    fn default() -> Self {
+        FileFormats {
+            default: Mutex::new(Arc::new(YamlFormat::default())),
+            collections: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl FileFormats {
+    pub fn set_default(&self, format: impl TextFormat + 'static) {
+        log::info!("Storing documents as {} files", format.extension());
+        *self.default.lock().unwrap() = Arc::new(format);
+    }
+
+    pub fn set_for_collection(&self, collection: &str, format: impl TextFormat + 'static) {
+        log::info!(
+            "Storing documents of collection {} as {} files",
+            collection,
+            format.extension()
+        );
+        self.collections
+            .lock()
+            .unwrap()
+            .insert(collection.to_string(), Arc::new(format));
+    }
+
+    /// Whether files are stored in other formats than the default YAML
+    pub fn is_customized(&self) -> bool {
+        self.default.lock().unwrap().extension() != YamlFormat::default().extension()
+            || !self.collections.lock().unwrap().is_empty()
+    }
+
+    /// Format of the entity file, by `_collection` field
+    pub fn for_entity(&self, entity: &Value) -> Arc<dyn TextFormat> {
+        let collection_format = entity[COLLECTION_FIELD_NAME]
+            .as_str()
+            .and_then(|collection| self.collections.lock().unwrap().get(collection).cloned());
+        collection_format.unwrap_or_else(|| self.default.lock().unwrap().clone())
+    }
+
+    /// Format reading files with the extension, configured ones first (e.g. with their Markdown
+    /// body field), then the built-in ones
+    pub fn for_extension(&self, extension: &str) -> Option<Arc<dyn TextFormat>> {
+        let default = self.default.lock().unwrap().clone();
+        let configured = std::iter::once(default)
+            .chain(self.collections.lock().unwrap().values().cloned())
+            .find(|format| format.extension() == extension);
+        configured.or_else(|| {
+            let built_in: Arc<dyn TextFormat> = match extension {
+                "yaml" => Arc::new(YamlFormat::default()),
+                "json" => Arc::new(JsonFormat),
+                "toml" => Arc::new(TomlFormat),
+                "md" => Arc::new(MarkdownFormat::new(DEFAULT_MARKDOWN_BODY_FIELD)),
+                _ => return None,
+            };
+            Some(built_in)
+        })
+    }
+}
+
+/// Body field of Markdown files read without a configured Markdown format
+pub const DEFAULT_MARKDOWN_BODY_FIELD: &str = "body";
+
+impl fmt::Debug for FileFormats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let collections: HashMap<String, String> = self
+            .collections
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(collection, format)| (collection.clone(), format.extension().to_string()))
+            .collect();
+        f.debug_struct("FileFormats")
+            .field("default", &self.default.lock().unwrap().extension())
+            .field("collections", &collections)
+            .finish()
+    }
+}
@@ -0,0 +1,193 @@
+use std::{fs, path::Path, process::Command};
+
+use ulid::Ulid;
+
+use super::{
+    errors::DocDbError, file_storage::relative_entity_file_path, model::ChangeOperation, DbConfig,
+    DocDbResult,
+};
+
+const MERGE_DRIVER_NAME: &str = "rust-doc-db";
+const MERGE_DRIVER_BINARY: &str = "rust_doc_db_merge";
+
+/// Versioning of YAML files in a Git repository initialised in `text_db_path`
+///
+/// Every committed transaction becomes a Git commit naming changed entities, e.g.
+/// `update 01H5...`. The `git` executable has to be available on `PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSettings {
+    pub author_name: String,
+    pub author_email: String,
+}
+
+impl Default for GitSettings {
+    fn default() -> Self {
+        GitSettings {
+            author_name: "rust-doc-db".to_string(),
+            author_email: "rust-doc-db@localhost".to_string(),
+        }
+    }
+}
+
+/// Commit changing the entity file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitRevision {
+    pub hash: String,
+    /// Author date in ISO 8601 format
+    pub date: String,
+    pub subject: String,
+}
+
+/// History of the entity file, newest commit first (empty when Git is not enabled)
+pub fn get_entity_history(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<Vec<GitRevision>> {
+    if db_config.git.is_none() || !is_repository(db_config) {
+        return Ok(Vec::new());
+    }
+    let output = run_git(
+        &[
+            "log",
+            "--follow",
+            "--format=%H%x09%aI%x09%s",
+            "--",
+            &relative_entity_file_path(entity_id, db_config)?.to_string_lossy(),
+        ],
+        db_config,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some(GitRevision {
+                hash: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+pub(super) fn init_repository_if_not_exists(db_config: &DbConfig) -> DocDbResult<()> {
+    if db_config.git.is_none() || is_repository(db_config) {
+        return Ok(());
+    }
+    log::info!("Initialising Git repository in {}", db_config.text_db_path);
+    run_git(&["init", "--quiet"], db_config)?;
+    register_merge_driver(db_config)
+}
+
+/// Assigns the `rust_doc_db_merge` driver to YAML files in `.gitattributes`
+///
+/// Driver command is repository config (not versioned), it's set only when the driver binary is
+/// installed next to the current executable, otherwise clones have to set it themselves.
+fn register_merge_driver(db_config: &DbConfig) -> DocDbResult<()> {
+    let attributes_path = Path::new(&db_config.text_db_path).join(".gitattributes");
+    if !attributes_path.exists() {
+        fs::write(
+            &attributes_path,
+            format!("*.yaml merge={}\n", MERGE_DRIVER_NAME),
+        )?;
+    }
+    let driver_path = std::env::current_exe()?.with_file_name(MERGE_DRIVER_BINARY);
+    if driver_path.exists() {
+        run_git(
+            &[
+                "config",
+                &format!("merge.{}.driver", MERGE_DRIVER_NAME),
+                &format!("\"{}\" %O %A %B %L", driver_path.display()),
+            ],
+            db_config,
+        )?;
+    }
+    Ok(())
+}
+
+/// Commits all changes of files, does nothing when Git is not enabled or nothing changed
+pub(super) fn commit_files(
+    changes: &[(ChangeOperation, Ulid)],
+    message: Option<&str>,
+    db_config: &DbConfig,
+) -> DocDbResult<()> {
+    let settings = match &db_config.git {
+        Some(settings) => settings,
+        None => return Ok(()),
+    };
+    init_repository_if_not_exists(db_config)?;
+    run_git(&["add", "--all"], db_config)?;
+    let status = git_command(db_config)
+        .args(["diff", "--cached", "--quiet"])
+        .status()?;
+    if status.success() {
+        log::info!("No file changes to commit to Git");
+        return Ok(());
+    }
+    let message = match message {
+        Some(message) => message.to_string(),
+        None => commit_message(changes),
+    };
+    log::info!(
+        "Committing files to Git: {}",
+        message.lines().next().unwrap_or("")
+    );
+    run_git(
+        &[
+            "-c",
+            &format!("user.name={}", settings.author_name),
+            "-c",
+            &format!("user.email={}", settings.author_email),
+            "commit",
+            "--quiet",
+            "--message",
+            &message,
+        ],
+        db_config,
+    )?;
+    Ok(())
+}
+
+/// Subject names all changes unless there are too many, e.g. `insert 01H5..., delete 01H6...`
+fn commit_message(changes: &[(ChangeOperation, Ulid)]) -> String {
+    const MAX_SUBJECT_CHANGES: usize = 3;
+    let change_lines: Vec<String> = changes
+        .iter()
+        .map(|(operation, entity_id)| format!("{} {}", operation_name(*operation), entity_id))
+        .collect();
+    if change_lines.len() <= MAX_SUBJECT_CHANGES {
+        return change_lines.join(", ");
+    }
+    format!(
+        "change {} entities\n\n{}",
+        change_lines.len(),
+        change_lines.join("\n")
+    )
+}
+
+fn operation_name(operation: ChangeOperation) -> &'static str {
+    match operation {
+        ChangeOperation::Insert => "insert",
+        ChangeOperation::Update => "update",
+        ChangeOperation::Delete => "delete",
+    }
+}
+
+fn is_repository(db_config: &DbConfig) -> bool {
+    Path::new(&db_config.text_db_path).join(".git").exists()
+}
+
+fn git_command(db_config: &DbConfig) -> Command {
+    let mut command = Command::new("git");
+    command.current_dir(&db_config.text_db_path);
+    command
+}
+
+/// Runs git in the text DB directory, returns its standard output
+fn run_git(args: &[&str], db_config: &DbConfig) -> DocDbResult<String> {
+    let output = git_command(db_config).args(args).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(DocDbError::FileStorage {
+            message: format!("git {} failed: {}", args.join(" "), stderr),
+            source: stderr.into(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
@@ -0,0 +1,119 @@
+use std::{fmt, sync::Mutex};
+
+use serde_json::Value;
+use ulid::Ulid;
+
+use super::{
+    errors::DocDbError,
+    model::{ChangeOperation, DocDbChange},
+    transaction::Transaction,
+    DocDbResult,
+};
+
+/// Hook outcome, error is the reason of rejecting the write
+pub type HookResult = Result<(), String>;
+
+type PreWriteHook = Box<dyn Fn(&Transaction, &mut PreWrite) -> HookResult + Send + Sync>;
+type PostCommitHook = Box<dyn Fn(&DocDbChange) -> HookResult + Send + Sync>;
+
+/// Write about to be executed, passed to pre-write hooks
+pub struct PreWrite<'a> {
+    pub operation: ChangeOperation,
+    pub entity_id: Ulid,
+    /// Entity before the write, `None` for inserts
+    pub old_entity: Option<&'a Value>,
+    /// Entity to be stored, can be changed by hooks, `None` for deletes
+    pub new_entity: Option<&'a mut Value>,
+}
+
+/// Named callbacks run on every write
+///
+/// Pre-write hooks run inside the write transaction (and can read through it), they can change
+/// the stored entity or reject the write, which rolls back the whole transaction. Post-commit
+/// hooks run for every committed change, their errors are only logged, as the write is durable
+/// by then.
+/// Hooks run while the registry is locked, so they must not register other hooks.
+#[derive(Default)]
+pub struct WriteHooks {
+    pre_write: Mutex<Vec<(String, PreWriteHook)>>,
+    post_commit: Mutex<Vec<(String, PostCommitHook)>>,
+}
+
+impl WriteHooks {
+    pub fn before_write(
+        &self,
+        name: &str,
+        hook: impl Fn(&Transaction, &mut PreWrite) -> HookResult + Send + Sync + 'static,
+    ) {
+        self.pre_write
+            .lock()
+            .unwrap()
+            .push((name.to_string(), Box::new(hook)));
+    }
+
+    pub fn after_commit(
+        &self,
+        name: &str,
+        hook: impl Fn(&DocDbChange) -> HookResult + Send + Sync + 'static,
+    ) {
+        self.post_commit
+            .lock()
+            .unwrap()
+            .push((name.to_string(), Box::new(hook)));
+    }
+
+    pub fn has_pre_write_hooks(&self) -> bool {
+        !self.pre_write.lock().unwrap().is_empty()
+    }
+
+    pub fn has_post_commit_hooks(&self) -> bool {
+        !self.post_commit.lock().unwrap().is_empty()
+    }
+
+    pub(super) fn run_pre_write(
+        &self,
+        transaction: &Transaction,
+        pre_write: &mut PreWrite,
+    ) -> DocDbResult<()> {
+        for (name, hook) in self.pre_write.lock().unwrap().iter() {
+            hook(transaction, pre_write).map_err(|reason| DocDbError::WriteHook {
+                hook: name.clone(),
+                entity_id: pre_write.entity_id.to_string(),
+                reason,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Runs all hooks for all changes, logging their errors
+    pub(super) fn run_post_commit(&self, changes: &[DocDbChange]) {
+        for change in changes {
+            for (name, hook) in self.post_commit.lock().unwrap().iter() {
+                if let Err(reason) = hook(change) {
+                    log::error!(
+                        "Hook {} failed for entity {}: {}",
+                        name,
+                        change.entity_id,
+                        reason
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for WriteHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteHooks")
+            .field("pre_write", &hook_names(&self.pre_write.lock().unwrap()))
+            .field(
+                "post_commit",
+                &hook_names(&self.post_commit.lock().unwrap()),
+            )
+            .finish()
+    }
+}
+
+fn hook_names<T>(hooks: &[(String, T)]) -> Vec<&str> {
+    hooks.iter().map(|(name, _)| name.as_str()).collect()
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde_json::Value;
+use ulid::Ulid;
+
+use super::{
+    document::COLLECTION_FIELD_NAME,
+    expiry::format_rfc3339,
+    file_storage::{
+        list_entity_file_paths, load_entity_from_path, move_entity_file, paths_depend_on_content,
+    },
+    git, DbConfig, DocDbResult,
+};
+
+/// Directory layout of YAML files in `text_db_path`
+///
+/// Files are found by ULID in their names whatever directory they are in, so files stored with
+/// other layout are still read and synced, `relayout_files` moves them to their layout paths.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FileLayout {
+    /// `<ULID>.yaml`
+    #[default]
+    Flat,
+    /// `ab/cd/<ULID>.yaml`, by prefix of the ULID hash, spreading files evenly
+    HashPrefix,
+    /// `2026/10/<ULID>.yaml`, by year and month of the ULID timestamp
+    UlidMonth,
+    /// `<collection>/<ULID>.yaml`, by `_collection` field, documents without one stay in the root
+    Collection,
+}
+
+impl FileLayout {
+    /// Directory of entity file, relative to `text_db_path`
+    pub fn entity_dir(&self, entity_id: &Ulid, entity: &Value) -> PathBuf {
+        match self {
+            FileLayout::Flat => PathBuf::new(),
+            FileLayout::HashPrefix => {
+                let hash = format!("{:08x}", fnv1a_hash(entity_id.to_string().as_bytes()));
+                PathBuf::from(&hash[0..2]).join(&hash[2..4])
+            }
+            FileLayout::UlidMonth => {
+                let date = format_rfc3339(entity_id.datetime());
+                PathBuf::from(&date[0..4]).join(&date[5..7])
+            }
+            FileLayout::Collection => match entity[COLLECTION_FIELD_NAME].as_str() {
+                Some(collection) if is_directory_name(collection) => PathBuf::from(collection),
+                _ => PathBuf::new(),
+            },
+        }
+    }
+
+    /// Whether directory of entity file depends on the entity content, not only on its ULID
+    pub fn depends_on_content(&self) -> bool {
+        matches!(self, FileLayout::Collection)
+    }
+}
+
+/// Paths of entity files by ULID, used when paths depend on content (layout or file names) or
+/// files aren't at their layout paths
+///
+/// Built by scanning `text_db_path` on first lookup and kept up to date by writes through the
+/// config. Files renamed by other processes are picked up by the next scan, e.g. `sync_from_files`.
+#[derive(Debug, Default)]
+pub struct FileIndex {
+    paths: Mutex<Option<HashMap<Ulid, PathBuf>>>,
+}
+
+impl FileIndex {
+    pub(super) fn get(
+        &self,
+        entity_id: &Ulid,
+        scan: impl FnOnce() -> DocDbResult<HashMap<Ulid, PathBuf>>,
+    ) -> DocDbResult<Option<PathBuf>> {
+        let mut paths = self.paths.lock().unwrap();
+        if paths.is_none() {
+            *paths = Some(scan()?);
+        }
+        Ok(paths
+            .as_ref()
+            .and_then(|paths| paths.get(entity_id).cloned()))
+    }
+
+    /// Records path of the entity file, `None` when it was removed
+    pub(super) fn set(&self, entity_id: &Ulid, path: Option<PathBuf>) {
+        // index not built yet will find the file when scanning
+        if let Some(paths) = self.paths.lock().unwrap().as_mut() {
+            match path {
+                Some(path) => paths.insert(*entity_id, path),
+                None => paths.remove(entity_id),
+            };
+        }
+    }
+
+    /// Removes the entity file path, unless the entity was moved to other path since
+    pub(super) fn remove_path(&self, entity_id: &Ulid, path: &Path) {
+        if let Some(paths) = self.paths.lock().unwrap().as_mut() {
+            if paths
+                .get(entity_id)
+                .is_some_and(|indexed_path| indexed_path == path)
+            {
+                paths.remove(entity_id);
+            }
+        }
+    }
+
+    pub(super) fn replace(&self, paths: HashMap<Ulid, PathBuf>) {
+        *self.paths.lock().unwrap() = Some(paths);
+    }
+}
+
+/// Moves files stored with other layout (or named by other file name templates) to their
+/// `DbConfig.file_layout` paths, returns number of moved files
+///
+/// Directories left empty are removed. Moving keeps modification times, so moved files are not
+/// treated as changed by `sync_from_files`.
+pub fn relayout_files(db_config: &DbConfig) -> DocDbResult<usize> {
+    log::info!(
+        "Moving files in {} to {:?} layout",
+        db_config.text_db_path,
+        db_config.file_layout
+    );
+    let mut moved_count = 0;
+    for (entity_id, path) in list_entity_file_paths(db_config)? {
+        let entity = if paths_depend_on_content(db_config) {
+            load_entity_from_path(&path, db_config)?
+        } else {
+            Value::Null
+        };
+        if move_entity_file(&entity_id, &path, &entity, db_config)? {
+            moved_count += 1;
+        }
+    }
+    log::info!("Moved {} files", moved_count);
+    // files are moved, they are committed with the next commit when Git fails
+    if moved_count > 0 {
+        if let Err(err) = git::commit_files(&[], Some("relayout files"), db_config) {
+            log::error!("Unable to commit moved files to Git: {}", err);
+        }
+    }
+    Ok(moved_count)
+}
+
+/// Single path component which isn't hidden, so it can't point outside of `text_db_path`
+fn is_directory_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+/// 32-bit FNV-1a, stable across Rust versions unlike `DefaultHasher`
+fn fnv1a_hash(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
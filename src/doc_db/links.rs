@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+
+use serde_json::{Map, Value};
+use ulid::Ulid;
+
+use super::{
+    errors::DocDbError,
+    model::DocDbLink,
+    sql_storage::{get_links_from_sqlite, select_links_from_sqlite},
+    transaction::Transaction,
+    DbConfig, DocDbResult,
+};
+
+/// Links are stored in source entity as `{"_links": {"<link type>": ["<target ULID>", ...]}}`
+pub const LINKS_FIELD_NAME: &str = "_links";
+
+/// What happens to links (and their sources) when link target is deleted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkDeleteRule {
+    /// Target can't be deleted while it is linked
+    #[default]
+    Restrict,
+    /// Sources linking the target are deleted together with it
+    Cascade,
+    /// Links to the target are removed from sources
+    Nullify,
+}
+
+pub fn link_entities(
+    source_id: &Ulid,
+    link_type: &str,
+    target_id: &Ulid,
+    db_config: &DbConfig,
+) -> DocDbResult<()> {
+    db_config.transaction(|tx| tx.link(source_id, link_type, target_id))
+}
+
+pub fn unlink_entities(
+    source_id: &Ulid,
+    link_type: &str,
+    target_id: &Ulid,
+    db_config: &DbConfig,
+) -> DocDbResult<()> {
+    db_config.transaction(|tx| tx.unlink(source_id, link_type, target_id))
+}
+
+/// Links going out of the entity
+pub fn get_links_from(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<Vec<DocDbLink>> {
+    get_links_from_sqlite("source_id", entity_id, db_config)
+}
+
+/// Links pointing at the entity
+pub fn get_links_to(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<Vec<DocDbLink>> {
+    get_links_from_sqlite("target_id", entity_id, db_config)
+}
+
+impl Transaction<'_> {
+    pub fn link(&mut self, source_id: &Ulid, link_type: &str, target_id: &Ulid) -> DocDbResult<()> {
+        log::info!("Linking entity {} {} {}", source_id, link_type, target_id);
+        if link_type.is_empty() || link_type.contains(char::is_whitespace) {
+            return Err(DocDbError::validation(format!(
+                "Invalid link type \"{}\"",
+                link_type
+            )));
+        }
+        if self.get(target_id)?.is_none() {
+            return Err(DocDbError::not_found(target_id));
+        }
+        self.update_links_of_entity(source_id, |links| {
+            let targets = links
+                .entry(link_type.to_string())
+                .or_insert_with(|| Value::Array(Vec::new()));
+            let target = Value::String(target_id.to_string());
+            match targets.as_array_mut() {
+                Some(targets) if !targets.contains(&target) => {
+                    targets.push(target);
+                    true
+                }
+                _ => false,
+            }
+        })
+    }
+
+    pub fn unlink(
+        &mut self,
+        source_id: &Ulid,
+        link_type: &str,
+        target_id: &Ulid,
+    ) -> DocDbResult<()> {
+        log::info!("Unlinking entity {} {} {}", source_id, link_type, target_id);
+        self.update_links_of_entity(source_id, |links| {
+            let target = Value::String(target_id.to_string());
+            let changed = match links.get_mut(link_type).and_then(Value::as_array_mut) {
+                Some(targets) if targets.contains(&target) => {
+                    targets.retain(|existing| *existing != target);
+                    true
+                }
+                _ => false,
+            };
+            if links
+                .get(link_type)
+                .and_then(Value::as_array)
+                .is_some_and(|targets| targets.is_empty())
+            {
+                links.remove(link_type);
+            }
+            changed
+        })
+    }
+
+    pub fn get_links_from(&self, entity_id: &Ulid) -> DocDbResult<Vec<DocDbLink>> {
+        select_links_from_sqlite(self.connection(), "source_id", entity_id)
+    }
+
+    pub fn get_links_to(&self, entity_id: &Ulid) -> DocDbResult<Vec<DocDbLink>> {
+        select_links_from_sqlite(self.connection(), "target_id", entity_id)
+    }
+
+    fn update_links_of_entity(
+        &mut self,
+        entity_id: &Ulid,
+        change_links: impl FnOnce(&mut Map<String, Value>) -> bool,
+    ) -> DocDbResult<()> {
+        let mut db_entry = self
+            .get(entity_id)?
+            .ok_or_else(|| DocDbError::not_found(entity_id))?;
+        if !db_entry.entity[LINKS_FIELD_NAME].is_object() {
+            db_entry.set_field_value(LINKS_FIELD_NAME, Value::Object(Map::new()))?;
+        }
+        let links = db_entry.entity[LINKS_FIELD_NAME].as_object_mut().unwrap();
+        if change_links(links) {
+            // replaced, as merging with existing version would bring removed links back
+            self.replace(entity_id, &db_entry.entity)?;
+        }
+        Ok(())
+    }
+}
+
+/// Entities to delete (the entities and cascaded link sources) and links to remove from remaining
+/// entities
+pub(super) struct DeletionPlan {
+    pub entity_ids: Vec<Ulid>,
+    pub nullified_links: Vec<DocDbLink>,
+}
+
+pub(super) fn plan_entities_deletion(
+    deleted_ids: &[Ulid],
+    transaction: &Transaction,
+) -> DocDbResult<DeletionPlan> {
+    let db_config = transaction.db_config();
+    let mut entity_ids: Vec<Ulid> = Vec::new();
+    let mut planned_ids: HashSet<Ulid> = HashSet::new();
+    let mut incoming_links: Vec<DocDbLink> = Vec::new();
+    let mut pending_ids: Vec<Ulid> = deleted_ids.iter().rev().copied().collect();
+    while let Some(pending_id) = pending_ids.pop() {
+        if !planned_ids.insert(pending_id) {
+            continue;
+        }
+        entity_ids.push(pending_id);
+        for link in transaction.get_links_to(&pending_id)? {
+            if link_delete_rule(&link.link_type, db_config) == LinkDeleteRule::Cascade {
+                pending_ids.push(link.source_id);
+            }
+            incoming_links.push(link);
+        }
+    }
+
+    let mut nullified_links: Vec<DocDbLink> = Vec::new();
+    for link in incoming_links {
+        if planned_ids.contains(&link.source_id) {
+            continue;
+        }
+        if link_delete_rule(&link.link_type, db_config) == LinkDeleteRule::Restrict {
+            return Err(DocDbError::RestrictedDelete {
+                entity_id: link.target_id.to_string(),
+                link_type: link.link_type,
+                source_id: link.source_id.to_string(),
+            });
+        }
+        nullified_links.push(link);
+    }
+    Ok(DeletionPlan {
+        entity_ids,
+        nullified_links,
+    })
+}
+
+fn link_delete_rule(link_type: &str, db_config: &DbConfig) -> LinkDeleteRule {
+    db_config
+        .link_delete_rules
+        .get(link_type)
+        .copied()
+        .unwrap_or_default()
+}
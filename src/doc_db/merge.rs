@@ -0,0 +1,171 @@
+use serde_json::{Map, Value};
+
+use super::{
+    errors::DocDbError,
+    yaml_formatter::{format_yaml, KeyOrder},
+    DocDbResult,
+};
+
+/// Field changed differently in both merged versions
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldConflict {
+    /// Dot separated path of the field, e.g. `address.city`
+    pub field_path: String,
+    pub ours: Value,
+    pub theirs: Value,
+}
+
+/// Merged document, conflicting fields keep our value
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeResult {
+    pub merged: Value,
+    pub conflicts: Vec<FieldConflict>,
+}
+
+/// Three-way merge of document versions, field by field (nested objects are merged recursively,
+/// arrays and other values are compared as a whole)
+///
+/// Like `merge_entities`, a field missing (or `null`) in one version is taken from the other one
+/// when the other version changes it, as the version could have been written by a domain which
+/// doesn't know the field. A field removed in one version and left unchanged in the other one is
+/// removed. Conflict is reported when both versions change the field from its base value in
+/// different ways.
+pub fn merge_documents(base: &Value, ours: &Value, theirs: &Value) -> MergeResult {
+    let mut conflicts: Vec<FieldConflict> = Vec::new();
+    let merged = merge_values(Some(base), ours, theirs, "", &mut conflicts);
+    MergeResult { merged, conflicts }
+}
+
+fn merge_values(
+    base: Option<&Value>,
+    ours: &Value,
+    theirs: &Value,
+    field_path: &str,
+    conflicts: &mut Vec<FieldConflict>,
+) -> Value {
+    if ours == theirs || Some(theirs) == base {
+        return ours.clone();
+    }
+    // also removes field which their version removed and ours left unchanged
+    if Some(ours) == base || ours.is_null() {
+        return theirs.clone();
+    }
+    if theirs.is_null() {
+        return ours.clone();
+    }
+    if let (Some(ours), Some(theirs)) = (ours.as_object(), theirs.as_object()) {
+        let empty_base = Map::new();
+        let base = base.and_then(Value::as_object).unwrap_or(&empty_base);
+        let mut merged = Map::new();
+        for key in ours
+            .keys()
+            .chain(theirs.keys().filter(|key| !ours.contains_key(*key)))
+        {
+            let merged_value = merge_values(
+                base.get(key),
+                ours.get(key).unwrap_or(&Value::Null),
+                theirs.get(key).unwrap_or(&Value::Null),
+                &join_path(field_path, key),
+                conflicts,
+            );
+            if !merged_value.is_null() {
+                merged.insert(key.clone(), merged_value);
+            }
+        }
+        return Value::Object(merged);
+    }
+    conflicts.push(FieldConflict {
+        field_path: field_path.to_string(),
+        ours: ours.clone(),
+        theirs: theirs.clone(),
+    });
+    ours.clone()
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Merges YAML documents, conflicting fields are wrapped in Git conflict markers of given size
+///
+/// Returns merged YAML and whether it is free of conflicts. Empty base (file added in both
+/// versions) is treated as an empty document. Merged YAML is formatted like entity files, keeping
+/// our text (and comments) of fields.
+pub fn merge_yaml_documents(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    marker_size: usize,
+) -> DocDbResult<(String, bool)> {
+    let parse = |yaml: &str, version: &str| -> DocDbResult<Value> {
+        let value: Value = serde_yaml::from_str(yaml)
+            .map_err(|err| DocDbError::corrupted(format!("<{}>", version), err))?;
+        Ok(value)
+    };
+    let result = merge_documents(
+        &parse(base, "base")?,
+        &parse(ours, "ours")?,
+        &parse(theirs, "theirs")?,
+    );
+    if result.conflicts.is_empty() {
+        return Ok((
+            format_yaml(&result.merged, &KeyOrder::default(), Some(ours))?,
+            true,
+        ));
+    }
+
+    // conflicting fields are rendered as placeholders first, then replaced with both versions
+    let mut merged = result.merged;
+    for (index, conflict) in result.conflicts.iter().enumerate() {
+        if let Some(field) = field_mut(&mut merged, &conflict.field_path) {
+            *field = Value::String(conflict_placeholder(index));
+        }
+    }
+    let mut merged_yaml = format_yaml(&merged, &KeyOrder::default(), Some(ours))?;
+    for (index, conflict) in result.conflicts.iter().enumerate() {
+        let placeholder = conflict_placeholder(index);
+        let line = match merged_yaml
+            .lines()
+            .find(|line| line.ends_with(&placeholder))
+        {
+            Some(line) => line.to_string(),
+            None => continue,
+        };
+        let indentation = &line[..line.len() - line.trim_start().len()];
+        let key = conflict.field_path.rsplit('.').next().unwrap_or_default();
+        let side = |value: &Value| -> DocDbResult<String> {
+            let mut field = Map::new();
+            field.insert(key.to_string(), value.clone());
+            Ok(
+                format_yaml(&Value::Object(field), &KeyOrder::default(), None)?
+                    .lines()
+                    .map(|field_line| format!("{}{}\n", indentation, field_line))
+                    .collect(),
+            )
+        };
+        let conflict_block = format!(
+            "{} ours\n{}{}\n{}{} theirs",
+            "<".repeat(marker_size),
+            side(&conflict.ours)?,
+            "=".repeat(marker_size),
+            side(&conflict.theirs)?,
+            ">".repeat(marker_size),
+        );
+        merged_yaml = merged_yaml.replacen(&line, &conflict_block, 1);
+    }
+    Ok((merged_yaml, false))
+}
+
+fn conflict_placeholder(index: usize) -> String {
+    format!("__doc_db_merge_conflict_{}__", index)
+}
+
+fn field_mut<'a>(document: &'a mut Value, field_path: &str) -> Option<&'a mut Value> {
+    field_path
+        .split('.')
+        .try_fold(document, |value, key| value.get_mut(key))
+}
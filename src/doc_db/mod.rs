@@ -2,37 +2,109 @@
 
 use std::collections::HashMap;
 
-use self::{errors::DocDbError, file_storage::*, model::DocDbEntry, sql_storage::*};
+use self::{
+    changes::ChangeSubscribers,
+    constraints::UniqueConstraints,
+    derived::DerivedFields,
+    document::{DocDbDocument, DocQuery, COLLECTION_FIELD_NAME},
+    domain::DomainView,
+    file_storage::*,
+    formats::FileFormats,
+    git::GitSettings,
+    hooks::WriteHooks,
+    layout::{FileIndex, FileLayout},
+    links::LinkDeleteRule,
+    model::DocDbEntry,
+    slugs::FileSlugs,
+    sql_storage::*,
+    tags::{validate_tag, TagVocabulary},
+};
 use serde_json::Value;
 use ulid::Ulid;
 
+pub mod attachments;
+pub mod changes;
+pub mod constraints;
+pub mod derived;
+pub mod document;
+pub mod domain;
 mod errors;
+pub mod expiry;
 mod file_storage;
+pub mod file_watcher;
+pub mod formats;
+pub mod git;
+pub mod hooks;
+pub mod layout;
+pub mod links;
+pub mod merge;
 pub mod model;
+pub mod references;
+pub mod slugs;
 mod sql_storage;
+pub mod sync;
+pub mod tags;
+pub mod transaction;
+pub mod traversal;
+pub mod yaml_formatter;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct DbConfig {
     pub sqlite_db_full_filename: String,
     pub text_db_path: String,
+    /// Directories of YAML files, flat by default
+    pub file_layout: FileLayout,
+    /// Formats of entity files, YAML by default
+    pub file_formats: FileFormats,
+    /// Human-friendly names of YAML files per collection, ULIDs by default
+    pub file_slugs: FileSlugs,
+    /// Paths of YAML files by ULID, when they depend on content
+    pub file_index: FileIndex,
+    /// Commits YAML files to a Git repository in `text_db_path` when set
+    pub git: Option<GitSettings>,
+    /// Allowed `key:value` tags, when not set only tag syntax is validated
+    pub tag_vocabulary: Option<TagVocabulary>,
+    /// What happens on deleting linked entity, per link type (restrict by default)
+    pub link_delete_rules: HashMap<String, LinkDeleteRule>,
+    /// Callbacks notified about changes committed through this config
+    pub change_subscribers: ChangeSubscribers,
+    /// Callbacks run before writes and after their commit
+    pub write_hooks: WriteHooks,
+    /// Fields computed from other fields on every write, per collection
+    pub derived_fields: DerivedFields,
+    /// Combinations of field values no two documents can share
+    pub unique_constraints: UniqueConstraints,
 }
 
+pub use self::errors::DocDbError;
+
 pub type DocDbResult<T> = std::result::Result<T, DocDbError>;
 
 pub fn make_sure_db_exists(db_config: &DbConfig) -> DocDbResult<()> {
     log::info!("Checking if DB exists");
     create_sqlite_db_if_not_exists(db_config)?;
     create_text_db_if_not_exists(db_config)?;
+    remove_temp_files(db_config)?;
+    git::init_repository_if_not_exists(db_config)?;
     Ok(())
 }
 
 pub fn insert_entity_to_db(entity: &serde_json::Value, db_config: &DbConfig) -> DocDbResult<Ulid> {
     log::info!("Adding entity to DB");
-    let ulid = insert_entity_to_sqlite(entity, db_config)?;
-    store_entity_in_yaml_file(&ulid, entity, db_config)?;
-    Ok(ulid)
+    db_config.transaction(|tx| tx.insert(entity))
+}
+
+/// Inserts all entities in a single transaction, returns their ids in the same order
+pub fn insert_entities_to_db(
+    entities: &[serde_json::Value],
+    db_config: &DbConfig,
+) -> DocDbResult<Vec<Ulid>> {
+    log::info!("Adding {} entities to DB", entities.len());
+    db_config.transaction(|tx| tx.insert_many(entities))
 }
 
+/// Returns `None` when entity does not exist (or is expired), operations which need an existing
+/// entity (updates, deletes, tagging, linking) fail with `DocDbError::NotFound` instead
 pub fn get_entry_from_db(
     &entity_id: &Ulid,
     db_config: &DbConfig,
@@ -47,37 +119,37 @@ pub fn update_entity_in_db(
     db_config: &DbConfig,
 ) -> DocDbResult<()> {
     log::info!("Updating entity {} in DB", entity_id);
-    let merged_entity = try_merge_entity_with_existing_version(entity, entity_id, db_config)?;
-    update_entity_in_sqlite(entity_id, &merged_entity, db_config)?;
-    store_entity_in_yaml_file(entity_id, &merged_entity, db_config)?;
-    Ok(())
+    db_config.transaction(|tx| tx.update(entity_id, entity))
+}
+
+/// Updates all entries in a single transaction, fields missing in new versions are kept
+pub fn update_entities_in_db(entries: &[DocDbEntry], db_config: &DbConfig) -> DocDbResult<()> {
+    log::info!("Updating {} entities in DB", entries.len());
+    db_config.transaction(|tx| tx.update_many(entries))
 }
 
 pub fn delete_entity_from_db(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<()> {
     log::info!("Deleting entity {} from DB", entity_id);
-    delete_entity_from_sqlite(entity_id, db_config)?;
-    delete_yaml_file(entity_id, db_config)?;
-    Ok(())
+    db_config.transaction(|tx| tx.delete(entity_id))
+}
+
+/// Deletes all entities matching the query in a single transaction, returns number of deleted
+/// entities
+pub fn delete_entities_from_db(query: &DocQuery, db_config: &DbConfig) -> DocDbResult<usize> {
+    log::info!("Deleting entities matching query from DB");
+    db_config.transaction(|tx| tx.delete_where(query))
 }
 
 pub fn clear_db(db_config: &DbConfig) -> DocDbResult<()> {
     log::info!("Clearing DB");
-    remove_all_entity_yaml_files(db_config)?;
+    remove_all_entity_files(db_config)?;
+    attachments::remove_all_blobs(db_config)?;
     remove_all_entities_from_sqlite(db_config)?;
-    Ok(())
-}
-
-fn try_merge_entity_with_existing_version(
-    entity: &serde_json::Value,
-    entity_id: &Ulid,
-    db_config: &DbConfig,
-) -> DocDbResult<serde_json::Value> {
-    let mut merged_entity = entity.clone();
-    let db_entry_option = get_entry_from_sqlite(entity_id, db_config)?;
-    if let Some(db_entry) = db_entry_option {
-        merge_entities(&db_entry.entity, &mut merged_entity)?;
+    // DB is cleared, removed files are committed with the next commit when Git fails
+    if let Err(err) = git::commit_files(&[], Some("clear DB"), db_config) {
+        log::error!("Unable to commit cleared DB to Git: {}", err);
     }
-    Ok(merged_entity)
+    Ok(())
 }
 
 fn merge_entities(json_parent_entity: &Value, json_new_entity: &mut Value) -> DocDbResult<()> {
@@ -104,17 +176,8 @@ pub fn set_entity_field_value(
         entity_id
     );
 
-    let db_entry_option = get_entry_from_db(entity_id, db_config)?;
-    if db_entry_option.is_none() {
-        return Err(DocDbError::SqlStorage {
-            message: format!(
-                "Unable to set entity {} field {} to {}",
-                entity_id, field_name, field_value
-            ),
-            inner_type_name: "?".to_string(),
-        });
-    }
-    let mut db_entry = db_entry_option.unwrap();
+    let mut db_entry =
+        get_entry_from_db(entity_id, db_config)?.ok_or_else(|| DocDbError::not_found(entity_id))?;
     db_entry.set_field_value(field_name, Value::String(field_value.to_string()))?;
     update_entity_in_db(entity_id, &db_entry.entity, db_config)?;
     Ok(())
@@ -122,15 +185,10 @@ pub fn set_entity_field_value(
 
 pub fn tag_entity(entity_id: &Ulid, tag: &str, db_config: &DbConfig) -> DocDbResult<()> {
     log::info!("Adding \"{}\" tag for entity {}", tag, entity_id);
+    validate_tag(tag, db_config)?;
 
-    let db_entry_option = get_entry_from_db(entity_id, db_config)?;
-    if db_entry_option.is_none() {
-        return Err(DocDbError::SqlStorage {
-            message: format!("Unable to tag entity {}", entity_id),
-            inner_type_name: "?".to_string(),
-        });
-    }
-    let mut db_entry = db_entry_option.unwrap();
+    let mut db_entry =
+        get_entry_from_db(entity_id, db_config)?.ok_or_else(|| DocDbError::not_found(entity_id))?;
 
     if !db_entry.has_field("tags")? {
         log::info!("Creating tags field to store tags for entity {}", entity_id);
@@ -141,7 +199,6 @@ pub fn tag_entity(entity_id: &Ulid, tag: &str, db_config: &DbConfig) -> DocDbRes
     let tags_array_option = json_tags_option
         .ok_or(DocDbError::Internal {
             message: "Unable to extract tags array".to_string(),
-            inner_type_name: "?".to_string(),
         })?
         .as_array_mut();
 
@@ -160,14 +217,8 @@ pub fn tag_entity(entity_id: &Ulid, tag: &str, db_config: &DbConfig) -> DocDbRes
 pub fn untag_entity(entity_id: &Ulid, tag: &str, db_config: &DbConfig) -> DocDbResult<()> {
     log::info!("Removing \"{}\" tag for entity {}", tag, entity_id);
 
-    let db_entry_option = get_entry_from_db(entity_id, db_config)?;
-    if db_entry_option.is_none() {
-        return Err(DocDbError::SqlStorage {
-            message: format!("Unable to untag entity {}", entity_id),
-            inner_type_name: "?".to_string(),
-        });
-    }
-    let mut db_entry = db_entry_option.unwrap();
+    let mut db_entry =
+        get_entry_from_db(entity_id, db_config)?.ok_or_else(|| DocDbError::not_found(entity_id))?;
 
     if !db_entry.has_field("tags")? {
         return Ok(());
@@ -177,7 +228,6 @@ pub fn untag_entity(entity_id: &Ulid, tag: &str, db_config: &DbConfig) -> DocDbR
     let tags_array_option = json_tags_option
         .ok_or(DocDbError::Internal {
             message: "Unable to extract tags array".to_string(),
-            inner_type_name: "?".to_string(),
         })?
         .as_array_mut();
 
@@ -214,6 +264,117 @@ pub fn get_entries_from_db(
     get_entries_from_sqlite(where_clause, where_clause_params, db_config)
 }
 
+pub fn get_entries_by_query(
+    query: &DocQuery,
+    db_config: &DbConfig,
+) -> DocDbResult<Vec<DocDbEntry>> {
+    let (where_clause, params) = query.to_where_clause();
+    let where_clause_params: HashMap<&str, &str> = params
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    get_entries_from_db(&where_clause, where_clause_params, db_config)
+}
+
+pub fn insert_entity_in_domain(
+    domain: &DomainView,
+    entity: &serde_json::Value,
+    db_config: &DbConfig,
+) -> DocDbResult<Ulid> {
+    log::info!("Adding entity to DB in domain {}", domain.name);
+    db_config.derived_fields.check_not_owned_by(domain)?;
+    let mut domain_entity = domain.apply_update(&Value::Object(Default::default()), entity)?;
+    if let Some(collection) = &domain.collection {
+        domain_entity[COLLECTION_FIELD_NAME] = Value::String(collection.clone());
+    }
+    insert_entity_to_db(&domain_entity, db_config)
+}
+
+pub fn get_entry_in_domain(
+    domain: &DomainView,
+    entity_id: &Ulid,
+    db_config: &DbConfig,
+) -> DocDbResult<Option<DocDbEntry>> {
+    log::info!("Obtaining entity {} in domain {}", entity_id, domain.name);
+    let db_entry_option = get_entry_from_db(entity_id, db_config)?;
+    Ok(db_entry_option.map(|db_entry| DocDbEntry {
+        id: db_entry.id,
+        entity: domain.project(&db_entry.entity),
+    }))
+}
+
+pub fn get_entries_in_domain(
+    domain: &DomainView,
+    query: &DocQuery,
+    db_config: &DbConfig,
+) -> DocDbResult<Vec<DocDbEntry>> {
+    let db_entries = get_entries_by_query(query, db_config)?;
+    Ok(db_entries
+        .into_iter()
+        .map(|db_entry| DocDbEntry {
+            id: db_entry.id,
+            entity: domain.project(&db_entry.entity),
+        })
+        .collect())
+}
+
+/// Updates only fields owned by the domain, fails if any other field would change
+pub fn update_entity_in_domain(
+    domain: &DomainView,
+    entity_id: &Ulid,
+    entity: &serde_json::Value,
+    db_config: &DbConfig,
+) -> DocDbResult<()> {
+    log::info!("Updating entity {} in domain {}", entity_id, domain.name);
+    db_config.derived_fields.check_not_owned_by(domain)?;
+    // read in the transaction, so that concurrent changes of other fields aren't overwritten
+    db_config.transaction(|tx| {
+        let db_entry = tx
+            .get(entity_id)?
+            .ok_or_else(|| DocDbError::not_found(entity_id))?;
+        let merged_entity = domain.apply_update(&db_entry.entity, entity)?;
+        tx.replace(entity_id, &merged_entity)
+    })
+}
+
+pub fn create_document_indexes<T: DocDbDocument>(db_config: &DbConfig) -> DocDbResult<()> {
+    for index in T::index_definitions() {
+        log::info!("Creating index {} on {}", index.name, index.json_path);
+        create_index_in_sqlite(&index, db_config)?;
+    }
+    Ok(())
+}
+
+pub fn create_derived_field_indexes(db_config: &DbConfig) -> DocDbResult<()> {
+    for index in db_config.derived_fields.index_definitions() {
+        log::info!("Creating index {} on {}", index.name, index.json_path);
+        create_index_in_sqlite(&index, db_config)?;
+    }
+    Ok(())
+}
+
+/// Recomputes derived fields of all stored documents (e.g. after defining a new one), returns
+/// number of updated documents
+pub fn refresh_derived_fields(db_config: &DbConfig) -> DocDbResult<usize> {
+    log::info!("Refreshing derived fields in DB");
+    db_config.transaction(|tx| {
+        let mut refreshed_count = 0;
+        for collection in db_config.derived_fields.collections() {
+            let query = DocQuery::condition_with_values(
+                format!(
+                    "json_extract(content, '$.{}') = {{}}",
+                    COLLECTION_FIELD_NAME
+                ),
+                vec![collection],
+            );
+            let entries = tx.query(&query)?;
+            refreshed_count += entries.len();
+            tx.replace_many(entries)?;
+        }
+        Ok(refreshed_count)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
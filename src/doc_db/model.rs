@@ -1,3 +1,4 @@
+use serde::Serialize;
 use serde_json::Value;
 use ulid::Ulid;
 
@@ -9,14 +10,51 @@ pub struct DocDbEntry {
     pub entity: Value,
 }
 
+/// Typed link from one entity to another, e.g. person `works_at` company
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocDbLink {
+    pub source_id: Ulid,
+    pub link_type: String,
+    pub target_id: Ulid,
+}
+
+/// Binary file attached to an entity, content is stored once per SHA-256 `hash`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocDbAttachment {
+    pub entity_id: Ulid,
+    pub name: String,
+    /// Lowercase hex SHA-256 of the content
+    pub hash: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// Entry of the change feed, `seq` grows monotonically with every change
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocDbChange {
+    pub seq: u64,
+    pub operation: ChangeOperation,
+    pub entity_id: Ulid,
+    /// Entity before the change, `None` for inserts
+    pub old_entity: Option<Value>,
+    /// Entity after the change, `None` for deletes
+    pub new_entity: Option<Value>,
+    /// UTC timestamp in RFC 3339 format
+    pub changed_at: String,
+}
+
 impl DocDbEntry {
     pub fn set_field_value(&mut self, field_name: &str, field_value: Value) -> DocDbResult<()> {
         self.entity
             .as_object_mut()
-            .ok_or(DocDbError::Internal {
-                message: "Unable to extract tags array".to_string(),
-                inner_type_name: "?".to_string(),
-            })?
+            .ok_or_else(|| DocDbError::validation(format!("Entity {} is not an object", self.id)))?
             .insert(field_name.to_string(), field_value);
         Ok(())
     }
@@ -25,10 +63,7 @@ impl DocDbEntry {
         let result = self
             .entity
             .as_object()
-            .ok_or(DocDbError::Internal {
-                message: "Unable to extract tags array".to_string(),
-                inner_type_name: "?".to_string(),
-            })?
+            .ok_or_else(|| DocDbError::validation(format!("Entity {} is not an object", self.id)))?
             .contains_key(field_name);
         Ok(result)
     }
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use ulid::Ulid;
+
+use super::{
+    document::{DocDbDocument, DocQuery},
+    model::DocDbEntry,
+    sql_storage::{expired_condition, get_entries_with_content_from_sqlite},
+    DbConfig, DocDbResult,
+};
+
+/// Reference field resolved on read, referenced document(s) are inlined into the entity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedReference {
+    /// Top-level field holding ULID or array of ULIDs, e.g. `employer_id`
+    pub field_name: String,
+    /// Top-level field the document(s) are inlined as, e.g. `employer`
+    pub embedded_field_name: String,
+    /// Fields of referenced document(s) to inline, all when not set
+    pub projection: Option<Vec<String>>,
+}
+
+impl EmbeddedReference {
+    /// Referenced document(s) are inlined as the field name without `_id` / `_ids` suffix
+    /// (e.g. `employer_id` as `employer`, `friend_ids` as `friends`)
+    pub fn new(field_name: &str) -> Self {
+        let embedded_field_name = if let Some(name) = field_name.strip_suffix("_ids") {
+            format!("{}s", name)
+        } else if let Some(name) = field_name.strip_suffix("_id") {
+            name.to_string()
+        } else {
+            format!("{}_document", field_name)
+        };
+        EmbeddedReference {
+            field_name: field_name.to_string(),
+            embedded_field_name,
+            projection: None,
+        }
+    }
+
+    pub fn embedded_as(mut self, embedded_field_name: &str) -> Self {
+        self.embedded_field_name = embedded_field_name.to_string();
+        self
+    }
+
+    pub fn projection(mut self, field_names: &[&str]) -> Self {
+        self.projection = Some(field_names.iter().map(|name| name.to_string()).collect());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    pub embedded_references: Vec<EmbeddedReference>,
+}
+
+impl ReadOptions {
+    /// Embeds all reference fields declared by the document type
+    pub fn embedding_references_of<T: DocDbDocument>() -> Self {
+        ReadOptions {
+            embedded_references: T::REFERENCE_FIELDS
+                .iter()
+                .map(|field_name| EmbeddedReference::new(field_name))
+                .collect(),
+        }
+    }
+
+    pub fn embed(mut self, reference: EmbeddedReference) -> Self {
+        self.embedded_references.push(reference);
+        self
+    }
+
+    /// SQL expression computing entity content with referenced documents inlined, with its
+    /// named parameters
+    fn content_expression(&self) -> (String, Vec<(String, String)>) {
+        if self.embedded_references.is_empty() {
+            return ("content".to_string(), Vec::new());
+        }
+        let mut params: Vec<(String, String)> = Vec::new();
+        let mut json_object_args: Vec<String> = Vec::new();
+        for (index, reference) in self.embedded_references.iter().enumerate() {
+            let path_param = format!("ref{}_path", index);
+            let embedded_name_param = format!("ref{}_embedded_name", index);
+            params.push((path_param.clone(), format!("$.{}", reference.field_name)));
+            params.push((
+                embedded_name_param.clone(),
+                reference.embedded_field_name.clone(),
+            ));
+
+            let referenced_content = match &reference.projection {
+                None => "json(referenced.content)".to_string(),
+                Some(field_names) => {
+                    let mut projection_args: Vec<String> = Vec::new();
+                    for (field_index, field_name) in field_names.iter().enumerate() {
+                        let key_param = format!("ref{}_key{}", index, field_index);
+                        let key_path_param = format!("ref{}_key{}_path", index, field_index);
+                        projection_args.push(format!(
+                            ":{}, referenced.content -> :{}",
+                            key_param, key_path_param
+                        ));
+                        params.push((key_param, field_name.clone()));
+                        params.push((key_path_param, format!("$.{}", field_name)));
+                    }
+                    format!("json_object({})", projection_args.join(", "))
+                }
+            };
+            // single referenced document is inlined as object, array of them as array
+            json_object_args.push(format!(
+                ":{embedded}, json(CASE json_type(entities.content, :{path})
+                    WHEN 'array' THEN (SELECT json_group_array({content})
+                        FROM json_each(entities.content, :{path}) AS referenced_ids
+                        JOIN entities AS referenced ON referenced.id = referenced_ids.value
+                        WHERE NOT {expired})
+                    ELSE (SELECT {content} FROM entities AS referenced
+                        WHERE referenced.id = json_extract(entities.content, :{path})
+                        AND NOT {expired})
+                END)",
+                embedded = embedded_name_param,
+                path = path_param,
+                content = referenced_content,
+                expired = expired_condition("referenced")
+            ));
+        }
+        // merge patch skips nulls, so nothing is inlined for missing references
+        (
+            format!(
+                "json_patch(entities.content, json_object({}))",
+                json_object_args.join(", ")
+            ),
+            params,
+        )
+    }
+}
+
+/// Queries entities inlining referenced documents, in a single SQL query
+pub fn get_entries_with_references(
+    query: &DocQuery,
+    read_options: &ReadOptions,
+    db_config: &DbConfig,
+) -> DocDbResult<Vec<DocDbEntry>> {
+    let (where_clause, mut params) = query.to_where_clause();
+    log::info!("Querying DB with references: {}", where_clause);
+    let (content_expression, content_params) = read_options.content_expression();
+    params.extend(content_params);
+    let params: HashMap<&str, &str> = params
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    get_entries_with_content_from_sqlite(&content_expression, &where_clause, params, db_config)
+}
+
+pub fn get_entry_with_references(
+    entity_id: &Ulid,
+    read_options: &ReadOptions,
+    db_config: &DbConfig,
+) -> DocDbResult<Option<DocDbEntry>> {
+    log::info!("Obtaining entity {} with references from DB", entity_id);
+    Ok(get_entries_with_references(&DocQuery::with_id(entity_id), read_options, db_config)?.pop())
+}
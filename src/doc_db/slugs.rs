@@ -0,0 +1,121 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use serde_json::Value;
+use ulid::{Ulid, ULID_LEN};
+
+use super::{document::COLLECTION_FIELD_NAME, errors::DocDbError, DocDbResult};
+
+/// Placeholder of the ULID, every template has to contain it
+pub const ID_PLACEHOLDER: &str = "{id}";
+
+/// Longest slug of a single field, keeping file names well below file system limits
+const MAX_FIELD_SLUG_LENGTH: usize = 40;
+
+/// Templates of human-friendly file names per collection, e.g. `{lastname}-{firstname}-{id}`
+///
+/// Placeholders are dot separated field paths, their values are lowercased and non-alphanumeric
+/// characters replaced with `-`, e.g. `kowalski-jan-01H6....yaml`. The ULID keeps names unique and
+/// lets files be found by ULID whatever their name. Files are renamed when slug fields change.
+#[derive(Debug, Default)]
+pub struct FileSlugs {
+    templates: Mutex<HashMap<String, String>>,
+}
+
+impl FileSlugs {
+    pub fn declare(&self, collection: &str, template: &str) -> DocDbResult<()> {
+        if !template.contains(ID_PLACEHOLDER) {
+            return Err(DocDbError::validation(format!(
+                "File name template {} of collection {} has no {} placeholder",
+                template, collection, ID_PLACEHOLDER
+            )));
+        }
+        log::info!(
+            "Declaring file name template {} for collection {}",
+            template,
+            collection
+        );
+        self.templates
+            .lock()
+            .unwrap()
+            .insert(collection.to_string(), template.to_string());
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.lock().unwrap().is_empty()
+    }
+
+    /// File name of the entity without extension, the ULID for collections without template
+    pub fn file_stem(&self, entity_id: &Ulid, entity: &Value) -> String {
+        let templates = self.templates.lock().unwrap();
+        let template = match entity[COLLECTION_FIELD_NAME]
+            .as_str()
+            .and_then(|collection| templates.get(collection))
+        {
+            Some(template) => template,
+            None => return entity_id.to_string(),
+        };
+        let mut file_stem = String::new();
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find('{') {
+            file_stem.push_str(&rest[..start]);
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+            let placeholder = &rest[start..=end];
+            if placeholder == ID_PLACEHOLDER {
+                file_stem.push_str(&entity_id.to_string());
+            } else {
+                file_stem.push_str(&slugify(field_value(
+                    entity,
+                    &placeholder[1..placeholder.len() - 1],
+                )));
+            }
+            rest = &rest[end + 1..];
+        }
+        file_stem.push_str(rest);
+        collapse_separators(&file_stem)
+    }
+}
+
+/// ULID in a file name made by `FileSlugs::file_stem`
+pub fn entity_id_of_file_stem(file_stem: &str) -> Option<Ulid> {
+    file_stem
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| part.len() == ULID_LEN)
+        .find_map(|part| {
+            Ulid::from_string(part)
+                .ok()
+                .filter(|entity_id| entity_id.to_string() == part)
+        })
+}
+
+fn field_value(entity: &Value, field_path: &str) -> String {
+    let value = field_path
+        .split('.')
+        .try_fold(entity, |value, key| value.get(key));
+    match value {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Number(number)) => number.to_string(),
+        Some(Value::Bool(flag)) => flag.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn slugify(text: String) -> String {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .take(MAX_FIELD_SLUG_LENGTH)
+        .collect()
+}
+
+/// Single `-` between parts, none at the ends (e.g. when a slug field is missing)
+fn collapse_separators(file_stem: &str) -> String {
+    file_stem
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
@@ -6,56 +6,273 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use serde_json::Value;
 use sqlite::State;
 use ulid::Ulid;
 
-use super::{model::DocDbEntry, DbConfig, DocDbResult};
+use super::{
+    document::IndexDefinition,
+    file_storage::FileState,
+    model::{ChangeOperation, DocDbAttachment, DocDbChange, DocDbEntry, DocDbLink},
+    traversal::{Traversal, TraversalDirection, TraversalEdge, TraversalResult},
+    DbConfig, DocDbResult,
+};
 use crate::doc_db::errors::DocDbError;
 
+/// Condition matching entities whose `_expires_at` timestamp has passed (invalid ones never expire)
+const EXPIRED_CONDITION: &str =
+    "IFNULL(julianday(json_extract(entities.content, '$._expires_at')) <= julianday('now'), 0)";
+
+/// `EXPIRED_CONDITION` for entities table aliased in subqueries, e.g. as `referenced`
+pub(super) fn expired_condition(table_alias: &str) -> String {
+    EXPIRED_CONDITION.replace("entities.content", &format!("{}.content", table_alias))
+}
+
+/// Prepares SQL built from caller's input, so that its errors are reported with the query
+pub fn prepare_query(
+    connection: &sqlite::Connection,
+    query: String,
+) -> DocDbResult<sqlite::Statement<'_>> {
+    connection
+        .prepare(&query)
+        .map_err(|source| DocDbError::InvalidQuery { query, source })
+}
+
+/// Parses ULID read from the table, IDs are validated on writes so invalid one means corruption
+fn parse_stored_id(table: &str, raw_id: &str) -> DocDbResult<Ulid> {
+    Ulid::from_string(raw_id)
+        .map_err(|err| DocDbError::corrupted(format!("{}/{}", table, raw_id), err))
+}
+
+fn parse_stored_content(table: &str, key: &str, raw_content: &str) -> DocDbResult<Value> {
+    serde_json::from_str(raw_content)
+        .map_err(|err| DocDbError::corrupted(format!("{}/{}", table, key), err))
+}
+
+/// How long a connection waits for a lock held by another one (e.g. the file watcher) before
+/// failing with `SQLITE_BUSY`
+const BUSY_TIMEOUT_MS: usize = 5000;
+
 pub fn get_sqlite_connection(db_full_filename: &str) -> Result<sqlite::Connection, sqlite::Error> {
-    sqlite::open(db_full_filename)
+    let mut connection = sqlite::open(db_full_filename)?;
+    connection.set_busy_timeout(BUSY_TIMEOUT_MS)?;
+    Ok(connection)
 }
 
 pub fn create_sqlite_db_if_not_exists(db_config: &DbConfig) -> DocDbResult<bool> {
-    if Path::new(&db_config.sqlite_db_full_filename).exists() {
-        return Ok(true);
-    }
-
-    log::info!(
-        "Creating SQLite database in {}",
-        db_config.sqlite_db_full_filename
-    );
-    let mut sqlite_db_path = PathBuf::from(&db_config.sqlite_db_full_filename);
-    sqlite_db_path.pop();
-    fs::create_dir_all(sqlite_db_path.to_str().ok_or(DocDbError::Internal {
-        message: format!(
-            "Unable to process DB path {}",
+    if !Path::new(&db_config.sqlite_db_full_filename).exists() {
+        log::info!(
+            "Creating SQLite database in {}",
             db_config.sqlite_db_full_filename
-        ),
-        inner_type_name: "?".to_string(),
-    })?)?;
+        );
+        let mut sqlite_db_path = PathBuf::from(&db_config.sqlite_db_full_filename);
+        sqlite_db_path.pop();
+        fs::create_dir_all(sqlite_db_path.to_str().ok_or(DocDbError::Internal {
+            message: format!(
+                "Unable to process DB path {}",
+                db_config.sqlite_db_full_filename
+            ),
+        })?)?;
+    }
 
     let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
-    let mut statement = connection
-    .prepare("CREATE TABLE `entities` ( `id` TEXT NOT NULL UNIQUE, `content` TEXT NOT NULL, PRIMARY KEY(`id`) )")?;
-    statement.next()?;
+    connection.execute("CREATE TABLE IF NOT EXISTS `entities` ( `id` TEXT NOT NULL UNIQUE, `content` TEXT NOT NULL, PRIMARY KEY(`id`) )")?;
+    create_tags_table_if_not_exists(&connection)?;
+    create_links_table_if_not_exists(&connection)?;
+    create_changes_table_if_not_exists(&connection)?;
+    create_attachments_table_if_not_exists(&connection)?;
+    create_unique_values_table_if_not_exists(&connection)?;
+    connection.execute("CREATE TABLE IF NOT EXISTS `entity_files` ( `entity_id` TEXT NOT NULL, `modified_ns` INTEGER NOT NULL, `size` INTEGER NOT NULL, PRIMARY KEY(`entity_id`) )")?;
     Ok(true)
 }
 
+fn table_exists(connection: &sqlite::Connection, table_name: &str) -> DocDbResult<bool> {
+    let mut statement =
+        connection.prepare("SELECT 1 FROM sqlite_master WHERE type='table' AND name=:name")?;
+    statement.bind((":name", table_name))?;
+    Ok(matches!(statement.next()?, State::Row))
+}
+
+/// Tags of entities are mirrored in `entity_tags` table, so they can be looked up without
+/// scanning documents
+fn create_tags_table_if_not_exists(connection: &sqlite::Connection) -> DocDbResult<()> {
+    if table_exists(connection, "entity_tags")? {
+        return Ok(());
+    }
+    log::info!("Creating tags table in SQLite");
+    connection.execute(
+        "CREATE TABLE `entity_tags` ( `entity_id` TEXT NOT NULL, `tag` TEXT NOT NULL, PRIMARY KEY(`entity_id`, `tag`) );
+        CREATE INDEX `idx_entity_tags_tag` ON entity_tags (tag);
+        INSERT OR IGNORE INTO entity_tags (entity_id, tag)
+            SELECT entities.id, json_each.value FROM entities, json_each(entities.content, '$.tags')
+            WHERE json_each.type = 'text';",
+    )?;
+    Ok(())
+}
+
+/// Links between entities are mirrored in `entity_links` table, so they can be looked up in both
+/// directions
+fn create_links_table_if_not_exists(connection: &sqlite::Connection) -> DocDbResult<()> {
+    if table_exists(connection, "entity_links")? {
+        return Ok(());
+    }
+    log::info!("Creating links table in SQLite");
+    connection.execute(
+        "CREATE TABLE `entity_links` ( `source_id` TEXT NOT NULL, `link_type` TEXT NOT NULL, `target_id` TEXT NOT NULL, PRIMARY KEY(`source_id`, `link_type`, `target_id`) );
+        CREATE INDEX `idx_entity_links_target_id` ON entity_links (target_id);
+        INSERT OR IGNORE INTO entity_links (source_id, link_type, target_id)
+            SELECT entities.id, links.key, targets.value
+            FROM entities, json_each(entities.content, '$._links') AS links, json_each(links.value) AS targets
+            WHERE targets.type = 'text';",
+    )?;
+    Ok(())
+}
+
+/// Attachments of entities, `hash` names the blob holding their content (shared by attachments
+/// with the same content)
+fn create_attachments_table_if_not_exists(connection: &sqlite::Connection) -> DocDbResult<()> {
+    if table_exists(connection, "entity_attachments")? {
+        return Ok(());
+    }
+    log::info!("Creating attachments table in SQLite");
+    connection.execute(
+        "CREATE TABLE `entity_attachments` ( `entity_id` TEXT NOT NULL, `name` TEXT NOT NULL, `hash` TEXT NOT NULL, `size` INTEGER NOT NULL, PRIMARY KEY(`entity_id`, `name`) );
+        CREATE INDEX `idx_entity_attachments_hash` ON entity_attachments (hash);",
+    )?;
+    Ok(())
+}
+
+/// Values of unique constraints are kept in `unique_values` table, whose key makes conflicts
+/// lookups instead of scans; `unique_constraints` holds definitions the values were built for
+fn create_unique_values_table_if_not_exists(connection: &sqlite::Connection) -> DocDbResult<()> {
+    if table_exists(connection, "unique_values")? {
+        return Ok(());
+    }
+    log::info!("Creating unique values table in SQLite");
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS `unique_constraints` ( `name` TEXT NOT NULL, `definition` TEXT NOT NULL, PRIMARY KEY(`name`) );
+        CREATE TABLE `unique_values` ( `constraint_name` TEXT NOT NULL, `value_key` TEXT NOT NULL, `entity_id` TEXT NOT NULL, PRIMARY KEY(`constraint_name`, `value_key`) );
+        CREATE INDEX `idx_unique_values_entity_id` ON unique_values (entity_id);
+        DELETE FROM unique_constraints;",
+    )?;
+    Ok(())
+}
+
+/// Every change of entities is recorded (by triggers) in `entity_changes` table, AUTOINCREMENT
+/// makes sure sequence numbers are never reused
+fn create_changes_table_if_not_exists(connection: &sqlite::Connection) -> DocDbResult<()> {
+    if table_exists(connection, "entity_changes")? {
+        return Ok(());
+    }
+    log::info!("Creating changes table in SQLite");
+    connection.execute(
+        "CREATE TABLE `entity_changes` ( `seq` INTEGER PRIMARY KEY AUTOINCREMENT, `operation` TEXT NOT NULL, `entity_id` TEXT NOT NULL, `old_content` TEXT, `new_content` TEXT, `changed_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')) );
+        CREATE TRIGGER `entities_insert_change` AFTER INSERT ON entities BEGIN
+            INSERT INTO entity_changes (operation, entity_id, new_content) VALUES ('insert', NEW.id, NEW.content);
+        END;
+        CREATE TRIGGER `entities_update_change` AFTER UPDATE ON entities BEGIN
+            INSERT INTO entity_changes (operation, entity_id, old_content, new_content) VALUES ('update', NEW.id, OLD.content, NEW.content);
+        END;
+        CREATE TRIGGER `entities_delete_change` AFTER DELETE ON entities BEGIN
+            INSERT INTO entity_changes (operation, entity_id, old_content) VALUES ('delete', OLD.id, OLD.content);
+        END;",
+    )?;
+    Ok(())
+}
+
+/// Refreshes side tables (tags, links) of the entity from its content
+fn sync_entity_side_tables_in_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+    entity: &serde_json::Value,
+) -> DocDbResult<()> {
+    sync_entity_tags_in_sqlite(connection, entity_id, entity)?;
+    sync_entity_links_in_sqlite(connection, entity_id, entity)
+}
+
+fn sync_entity_links_in_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+    entity: &serde_json::Value,
+) -> DocDbResult<()> {
+    let mut statement = connection.prepare("DELETE FROM entity_links WHERE source_id=:id")?;
+    statement.bind((":id", entity_id.to_string().as_str()))?;
+    statement.next()?;
+
+    let mut statement = connection.prepare(
+        "INSERT OR IGNORE INTO entity_links (source_id, link_type, target_id)
+        SELECT :id, links.key, targets.value
+        FROM json_each(:content, '$._links') AS links, json_each(links.value) AS targets
+        WHERE targets.type = 'text'",
+    )?;
+    statement.bind((":id", entity_id.to_string().as_str()))?;
+    statement.bind((":content", entity.to_string().as_str()))?;
+    statement.next()?;
+    Ok(())
+}
+
+fn sync_entity_tags_in_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+    entity: &serde_json::Value,
+) -> DocDbResult<()> {
+    let mut statement = connection.prepare("DELETE FROM entity_tags WHERE entity_id=:id")?;
+    statement.bind((":id", entity_id.to_string().as_str()))?;
+    statement.next()?;
+
+    let mut statement = connection.prepare(
+        "INSERT OR IGNORE INTO entity_tags (entity_id, tag)
+        SELECT :id, value FROM json_each(:content, '$.tags') WHERE type = 'text'",
+    )?;
+    statement.bind((":id", entity_id.to_string().as_str()))?;
+    statement.bind((":content", entity.to_string().as_str()))?;
+    statement.next()?;
+    Ok(())
+}
+
+/// Runs given operations atomically, rolls back on error
+///
+/// Savepoint is used instead of BEGIN, so that it can be nested in an explicit transaction.
+fn in_sqlite_transaction<T>(
+    connection: &sqlite::Connection,
+    operations: impl FnOnce(&sqlite::Connection) -> DocDbResult<T>,
+) -> DocDbResult<T> {
+    connection.execute("SAVEPOINT doc_db_write")?;
+    match operations(connection) {
+        Ok(result) => {
+            connection.execute("RELEASE doc_db_write")?;
+            Ok(result)
+        }
+        Err(err) => {
+            connection.execute("ROLLBACK TO doc_db_write; RELEASE doc_db_write")?;
+            Err(err)
+        }
+    }
+}
+
 pub fn get_entry_from_sqlite(
     entity_id: &Ulid,
     db_config: &DbConfig,
 ) -> DocDbResult<Option<DocDbEntry>> {
-    log::info!("Obtaining entity {} from SQLite", entity_id);
     let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    select_entry_from_sqlite(&connection, entity_id)
+}
 
-    let mut statement = connection.prepare("SELECT content FROM entities WHERE id=:id")?;
+pub fn select_entry_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+) -> DocDbResult<Option<DocDbEntry>> {
+    log::info!("Obtaining entity {} from SQLite", entity_id);
+    let mut statement = connection.prepare(format!(
+        "SELECT content FROM entities WHERE id=:id AND NOT {}",
+        EXPIRED_CONDITION
+    ))?;
     statement.bind((1, entity_id.to_string().as_str()))?;
     if let Ok(State::Row) = statement.next() {
         if let Ok(raw_entity) = statement.read::<String, _>(0) {
             let entry = DocDbEntry {
                 id: *entity_id,
-                entity: serde_json::from_str(raw_entity.as_str())?,
+                entity: parse_stored_content("entities", &entity_id.to_string(), &raw_entity)?,
             };
             return Ok(Some(entry));
         }
@@ -63,50 +280,331 @@ pub fn get_entry_from_sqlite(
     Ok(None)
 }
 
+/// Stored content of the entity, including expired one
+pub fn select_stored_entity_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+) -> DocDbResult<Option<Value>> {
+    let mut statement = connection.prepare("SELECT content FROM entities WHERE id=:id")?;
+    statement.bind((":id", entity_id.to_string().as_str()))?;
+    if let State::Row = statement.next()? {
+        let raw_entity = statement.read::<String, _>("content")?;
+        return Ok(Some(parse_stored_content(
+            "entities",
+            &entity_id.to_string(),
+            &raw_entity,
+        )?));
+    }
+    Ok(None)
+}
+
 pub fn insert_entity_to_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
     entity: &serde_json::Value,
-    db_config: &DbConfig,
-) -> DocDbResult<Ulid> {
-    let entity_id = Ulid::new();
+) -> DocDbResult<()> {
     log::info!("Inserting entity {} to SQLite", entity_id);
-    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
-
-    let mut statement =
-        connection.prepare("INSERT INTO entities (id, content) VALUES (:id, :content)")?;
-    statement.bind((":id", entity_id.to_string().as_str()))?;
-    statement.bind((":content", entity.to_string().as_str()))?;
-    statement.next()?;
-    Ok(entity_id)
+    in_sqlite_transaction(connection, |connection| {
+        let mut statement =
+            connection.prepare("INSERT INTO entities (id, content) VALUES (:id, :content)")?;
+        statement.bind((":id", entity_id.to_string().as_str()))?;
+        statement.bind((":content", entity.to_string().as_str()))?;
+        statement.next()?;
+        sync_entity_side_tables_in_sqlite(connection, entity_id, entity)
+    })
 }
 
 pub fn update_entity_in_sqlite(
+    connection: &sqlite::Connection,
     entity_id: &Ulid,
     entity: &serde_json::Value,
-    db_config: &DbConfig,
 ) -> DocDbResult<()> {
     log::info!("Updating entity {} in SQLite", entity_id);
-    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
-    let mut statement = connection.prepare("UPDATE entities SET content=:content WHERE id=:id")?;
+    in_sqlite_transaction(connection, |connection| {
+        let mut statement =
+            connection.prepare("UPDATE entities SET content=:content WHERE id=:id")?;
+        statement.bind((":id", entity_id.to_string().as_str()))?;
+        statement.bind((":content", entity.to_string().as_str()))?;
+        statement.next()?;
+        if connection.change_count() == 0 {
+            return Err(DocDbError::not_found(entity_id));
+        }
+        sync_entity_side_tables_in_sqlite(connection, entity_id, entity)
+    })
+}
+
+pub fn delete_entity_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+) -> DocDbResult<()> {
+    log::info!("Removing entity {} from SQLite", entity_id);
+    in_sqlite_transaction(connection, |connection| {
+        let mut statement = connection.prepare("DELETE FROM entities WHERE id=:id")?;
+        statement.bind((":id", entity_id.to_string().as_str()))?;
+        statement.next()?;
+        let mut statement = connection.prepare("DELETE FROM entity_tags WHERE entity_id=:id")?;
+        statement.bind((":id", entity_id.to_string().as_str()))?;
+        statement.next()?;
+        let mut statement = connection.prepare("DELETE FROM entity_links WHERE source_id=:id")?;
+        statement.bind((":id", entity_id.to_string().as_str()))?;
+        statement.next()?;
+        let mut statement =
+            connection.prepare("DELETE FROM entity_attachments WHERE entity_id=:id")?;
+        statement.bind((":id", entity_id.to_string().as_str()))?;
+        statement.next()?;
+        let mut statement = connection.prepare("DELETE FROM unique_values WHERE entity_id=:id")?;
+        statement.bind((":id", entity_id.to_string().as_str()))?;
+        statement.next()?;
+        Ok(())
+    })
+}
+
+/// Inserts all given entries reusing a single prepared statement
+pub fn insert_entities_to_sqlite(
+    connection: &sqlite::Connection,
+    entries: &[DocDbEntry],
+) -> DocDbResult<()> {
+    log::info!("Inserting {} entities to SQLite", entries.len());
+    in_sqlite_transaction(connection, |connection| {
+        let mut statement =
+            connection.prepare("INSERT INTO entities (id, content) VALUES (:id, :content)")?;
+        for entry in entries {
+            statement.reset()?;
+            statement.bind((":id", entry.id.to_string().as_str()))?;
+            statement.bind((":content", entry.entity.to_string().as_str()))?;
+            statement.next()?;
+        }
+        sync_side_tables_of_entities_in_sqlite(connection, entries)
+    })
+}
+
+/// Updates content of all given entries reusing a single prepared statement
+pub fn update_entities_in_sqlite(
+    connection: &sqlite::Connection,
+    entries: &[DocDbEntry],
+) -> DocDbResult<()> {
+    log::info!("Updating {} entities in SQLite", entries.len());
+    in_sqlite_transaction(connection, |connection| {
+        let mut statement =
+            connection.prepare("UPDATE entities SET content=:content WHERE id=:id")?;
+        for entry in entries {
+            statement.reset()?;
+            statement.bind((":id", entry.id.to_string().as_str()))?;
+            statement.bind((":content", entry.entity.to_string().as_str()))?;
+            statement.next()?;
+            if connection.change_count() == 0 {
+                return Err(DocDbError::not_found(&entry.id));
+            }
+        }
+        sync_side_tables_of_entities_in_sqlite(connection, entries)
+    })
+}
+
+pub fn delete_entities_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_ids: &[Ulid],
+) -> DocDbResult<usize> {
+    log::info!("Removing {} entities from SQLite", entity_ids.len());
+    let ids = entity_ids_as_json_array(entity_ids);
+    in_sqlite_transaction(connection, |connection| {
+        let mut statement = connection
+            .prepare("DELETE FROM entities WHERE id IN (SELECT value FROM json_each(:ids))")?;
+        statement.bind((":ids", ids.as_str()))?;
+        statement.next()?;
+        let deleted_count = connection.change_count();
+        for sql in [
+            "DELETE FROM entity_tags WHERE entity_id IN (SELECT value FROM json_each(:ids))",
+            "DELETE FROM entity_links WHERE source_id IN (SELECT value FROM json_each(:ids))",
+            "DELETE FROM entity_attachments WHERE entity_id IN (SELECT value FROM json_each(:ids))",
+            "DELETE FROM unique_values WHERE entity_id IN (SELECT value FROM json_each(:ids))",
+        ] {
+            let mut statement = connection.prepare(sql)?;
+            statement.bind((":ids", ids.as_str()))?;
+            statement.next()?;
+        }
+        Ok(deleted_count)
+    })
+}
+
+/// Records state of files written by the DB, `None` for removed ones
+pub fn record_file_states_in_sqlite(
+    connection: &sqlite::Connection,
+    file_states: &[(Ulid, Option<FileState>)],
+) -> DocDbResult<()> {
+    let mut replace_statement = connection.prepare(
+        "INSERT OR REPLACE INTO entity_files (entity_id, modified_ns, size) VALUES (:id, :modified_ns, :size)",
+    )?;
+    let mut delete_statement =
+        connection.prepare("DELETE FROM entity_files WHERE entity_id=:id")?;
+    for (entity_id, file_state) in file_states {
+        match file_state {
+            Some(file_state) => {
+                replace_statement.reset()?;
+                replace_statement.bind((":id", entity_id.to_string().as_str()))?;
+                replace_statement.bind((":modified_ns", file_state.modified_ns))?;
+                replace_statement.bind((":size", file_state.size))?;
+                replace_statement.next()?;
+            }
+            None => {
+                delete_statement.reset()?;
+                delete_statement.bind((":id", entity_id.to_string().as_str()))?;
+                delete_statement.next()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn select_file_state_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+) -> DocDbResult<Option<FileState>> {
+    let mut statement =
+        connection.prepare("SELECT modified_ns, size FROM entity_files WHERE entity_id=:id")?;
     statement.bind((":id", entity_id.to_string().as_str()))?;
-    statement.bind((":content", entity.to_string().as_str()))?;
+    if let State::Row = statement.next()? {
+        return Ok(Some(FileState {
+            modified_ns: statement.read::<i64, _>("modified_ns")?,
+            size: statement.read::<i64, _>("size")?,
+        }));
+    }
+    Ok(None)
+}
+
+pub fn select_unique_constraint_definition_from_sqlite(
+    connection: &sqlite::Connection,
+    constraint_name: &str,
+) -> DocDbResult<Option<String>> {
+    let mut statement =
+        connection.prepare("SELECT definition FROM unique_constraints WHERE name=:name")?;
+    statement.bind((":name", constraint_name))?;
+    if let State::Row = statement.next()? {
+        return Ok(Some(statement.read::<String, _>("definition")?));
+    }
+    Ok(None)
+}
+
+/// Records definition of the constraint, removing values built for its previous definition
+pub fn record_unique_constraint_in_sqlite(
+    connection: &sqlite::Connection,
+    constraint_name: &str,
+    definition: &str,
+) -> DocDbResult<()> {
+    let mut statement = connection.prepare(
+        "INSERT OR REPLACE INTO unique_constraints (name, definition) VALUES (:name, :definition)",
+    )?;
+    statement.bind((":name", constraint_name))?;
+    statement.bind((":definition", definition))?;
+    statement.next()?;
+    let mut statement =
+        connection.prepare("DELETE FROM unique_values WHERE constraint_name=:name")?;
+    statement.bind((":name", constraint_name))?;
     statement.next()?;
     Ok(())
 }
 
-pub fn delete_entity_from_sqlite(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<()> {
-    log::info!("Removing entity {} from SQLite", entity_id);
-    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
-    let mut statement = connection.prepare("DELETE FROM entities WHERE id=:id")?;
+/// Returns ID of the entity holding the value of the constraint, if any
+pub fn select_unique_value_owner_from_sqlite(
+    connection: &sqlite::Connection,
+    constraint_name: &str,
+    value_key: &str,
+) -> DocDbResult<Option<Ulid>> {
+    let mut statement = connection.prepare(
+        "SELECT entity_id FROM unique_values WHERE constraint_name=:name AND value_key=:key",
+    )?;
+    statement.bind((":name", constraint_name))?;
+    statement.bind((":key", value_key))?;
+    if let State::Row = statement.next()? {
+        let raw_id = statement.read::<String, _>("entity_id")?;
+        return Ok(Some(parse_stored_id("unique_values", &raw_id)?));
+    }
+    Ok(None)
+}
+
+/// Makes the entity hold given values of the constraint (taking them over from other entities)
+/// instead of its previous ones
+pub fn replace_unique_values_in_sqlite(
+    connection: &sqlite::Connection,
+    constraint_name: &str,
+    entity_id: &Ulid,
+    value_keys: &[String],
+) -> DocDbResult<()> {
+    let mut statement = connection
+        .prepare("DELETE FROM unique_values WHERE constraint_name=:name AND entity_id=:id")?;
+    statement.bind((":name", constraint_name))?;
     statement.bind((":id", entity_id.to_string().as_str()))?;
     statement.next()?;
+    let mut statement = connection.prepare(
+        "INSERT OR REPLACE INTO unique_values (constraint_name, value_key, entity_id) VALUES (:name, :key, :id)",
+    )?;
+    for value_key in value_keys {
+        statement.reset()?;
+        statement.bind((":name", constraint_name))?;
+        statement.bind((":key", value_key.as_str()))?;
+        statement.bind((":id", entity_id.to_string().as_str()))?;
+        statement.next()?;
+    }
     Ok(())
 }
 
+pub fn select_file_states_from_sqlite(
+    connection: &sqlite::Connection,
+) -> DocDbResult<HashMap<Ulid, FileState>> {
+    let mut statement =
+        connection.prepare("SELECT entity_id, modified_ns, size FROM entity_files")?;
+    let mut file_states: HashMap<Ulid, FileState> = HashMap::new();
+    while let State::Row = statement.next()? {
+        file_states.insert(
+            parse_stored_id("entity_files", &statement.read::<String, _>("entity_id")?)?,
+            FileState {
+                modified_ns: statement.read::<i64, _>("modified_ns")?,
+                size: statement.read::<i64, _>("size")?,
+            },
+        );
+    }
+    Ok(file_states)
+}
+
+/// Refreshes side tables (tags, links) of many entities at once, from their stored content
+fn sync_side_tables_of_entities_in_sqlite(
+    connection: &sqlite::Connection,
+    entries: &[DocDbEntry],
+) -> DocDbResult<()> {
+    let entity_ids: Vec<Ulid> = entries.iter().map(|entry| entry.id).collect();
+    let ids = entity_ids_as_json_array(&entity_ids);
+    for sql in [
+        "DELETE FROM entity_tags WHERE entity_id IN (SELECT value FROM json_each(:ids))",
+        "INSERT OR IGNORE INTO entity_tags (entity_id, tag)
+            SELECT entities.id, tags.value FROM entities, json_each(entities.content, '$.tags') AS tags
+            WHERE entities.id IN (SELECT value FROM json_each(:ids)) AND tags.type = 'text'",
+        "DELETE FROM entity_links WHERE source_id IN (SELECT value FROM json_each(:ids))",
+        "INSERT OR IGNORE INTO entity_links (source_id, link_type, target_id)
+            SELECT entities.id, links.key, targets.value
+            FROM entities, json_each(entities.content, '$._links') AS links, json_each(links.value) AS targets
+            WHERE entities.id IN (SELECT value FROM json_each(:ids)) AND targets.type = 'text'",
+    ] {
+        let mut statement = connection.prepare(sql)?;
+        statement.bind((":ids", ids.as_str()))?;
+        statement.next()?;
+    }
+    Ok(())
+}
+
+fn entity_ids_as_json_array(entity_ids: &[Ulid]) -> String {
+    serde_json::Value::Array(
+        entity_ids
+            .iter()
+            .map(|entity_id| serde_json::Value::String(entity_id.to_string()))
+            .collect(),
+    )
+    .to_string()
+}
+
 pub fn remove_all_entities_from_sqlite(db_config: &DbConfig) -> DocDbResult<()> {
     log::info!("Removing all entities from SQLite");
     let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
-    let query = "DELETE FROM entities".to_string();
-    connection.execute(query)?;
+    connection
+        .execute("DELETE FROM entities; DELETE FROM entity_tags; DELETE FROM entity_links; DELETE FROM entity_files; DELETE FROM entity_attachments; DELETE FROM unique_values;")?;
     Ok(())
 }
 
@@ -114,25 +612,375 @@ pub fn get_entries_from_sqlite(
     where_clause: &str,
     where_clause_params: HashMap<&str, &str>,
     db_config: &DbConfig,
+) -> DocDbResult<Vec<DocDbEntry>> {
+    get_entries_with_content_from_sqlite("content", where_clause, where_clause_params, db_config)
+}
+
+/// Like `get_entries_from_sqlite`, but entity content is computed with given SQL expression
+/// (which can refer to the entity as `entities`)
+pub fn get_entries_with_content_from_sqlite(
+    content_expression: &str,
+    where_clause: &str,
+    params: HashMap<&str, &str>,
+    db_config: &DbConfig,
 ) -> DocDbResult<Vec<DocDbEntry>> {
     let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
-    let mut statement = connection.prepare(format!(
-        "SELECT id, content FROM entities WHERE {}",
-        where_clause
-    ))?;
-    for (key, value) in where_clause_params {
+    select_entries_with_content_from_sqlite(&connection, content_expression, where_clause, params)
+}
+
+pub fn select_entries_with_content_from_sqlite(
+    connection: &sqlite::Connection,
+    content_expression: &str,
+    where_clause: &str,
+    params: HashMap<&str, &str>,
+) -> DocDbResult<Vec<DocDbEntry>> {
+    let mut statement = prepare_query(
+        connection,
+        format!(
+            "SELECT id, {} AS selected_content FROM entities WHERE ({}) AND NOT {}",
+            content_expression, where_clause, EXPIRED_CONDITION
+        ),
+    )?;
+    for (key, value) in params {
         statement.bind((format!(":{}", key).as_str(), value))?;
     }
     let mut entities: Vec<DocDbEntry> = Vec::new();
     while let Ok(State::Row) = statement.next() {
         let entity_id = statement.read::<String, _>("id")?;
-        let entity = statement.read::<String, _>("content")?;
-        let json_entity = serde_json::from_str(&entity)?;
+        let entity = statement.read::<String, _>("selected_content")?;
         let entry = DocDbEntry {
-            id: Ulid::from_string(entity_id.as_str())?,
-            entity: json_entity,
+            id: parse_stored_id("entities", &entity_id)?,
+            entity: parse_stored_content("entities", &entity_id, &entity)?,
         };
         entities.push(entry);
     }
     Ok(entities)
 }
+
+pub fn select_expired_entity_ids_from_sqlite(
+    connection: &sqlite::Connection,
+) -> DocDbResult<Vec<Ulid>> {
+    let mut statement = connection.prepare(format!(
+        "SELECT id FROM entities WHERE {} ORDER BY id",
+        EXPIRED_CONDITION
+    ))?;
+    let mut entity_ids: Vec<Ulid> = Vec::new();
+    while let Ok(State::Row) = statement.next() {
+        entity_ids.push(parse_stored_id(
+            "entities",
+            &statement.read::<String, _>("id")?,
+        )?);
+    }
+    Ok(entity_ids)
+}
+
+pub fn select_changes_since_from_sqlite(
+    connection: &sqlite::Connection,
+    seq: u64,
+) -> DocDbResult<Vec<DocDbChange>> {
+    let mut statement = connection.prepare(
+        "SELECT seq, operation, entity_id, old_content, new_content, changed_at
+        FROM entity_changes WHERE seq > :seq ORDER BY seq",
+    )?;
+    statement.bind((":seq", seq as i64))?;
+    let mut changes: Vec<DocDbChange> = Vec::new();
+    while let Ok(State::Row) = statement.next() {
+        let seq = statement.read::<i64, _>("seq")?;
+        let row_key = seq.to_string();
+        let operation = match statement.read::<String, _>("operation")?.as_str() {
+            "insert" => ChangeOperation::Insert,
+            "update" => ChangeOperation::Update,
+            "delete" => ChangeOperation::Delete,
+            operation => {
+                return Err(DocDbError::corrupted(
+                    format!("entity_changes/{}", seq),
+                    format!("Unknown change operation {}", operation),
+                ))
+            }
+        };
+        let old_content = statement.read::<Option<String>, _>("old_content")?;
+        let new_content = statement.read::<Option<String>, _>("new_content")?;
+        changes.push(DocDbChange {
+            seq: seq as u64,
+            operation,
+            entity_id: parse_stored_id(
+                "entity_changes",
+                &statement.read::<String, _>("entity_id")?,
+            )?,
+            old_entity: old_content
+                .map(|content| parse_stored_content("entity_changes", &row_key, &content))
+                .transpose()?,
+            new_entity: new_content
+                .map(|content| parse_stored_content("entity_changes", &row_key, &content))
+                .transpose()?,
+            changed_at: statement.read::<String, _>("changed_at")?,
+        });
+    }
+    Ok(changes)
+}
+
+/// Sequence number of the latest change, 0 when nothing changed yet
+pub fn select_last_change_seq_from_sqlite(connection: &sqlite::Connection) -> DocDbResult<u64> {
+    let mut statement = connection.prepare("SELECT IFNULL(MAX(seq), 0) FROM entity_changes")?;
+    statement.next()?;
+    Ok(statement.read::<i64, _>(0)? as u64)
+}
+
+pub fn create_index_in_sqlite(index: &IndexDefinition, db_config: &DbConfig) -> DocDbResult<()> {
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    connection.execute(index.to_sql())?;
+    Ok(())
+}
+
+pub fn get_tag_counts_from_sqlite(db_config: &DbConfig) -> DocDbResult<Vec<(String, usize)>> {
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    let mut statement = connection
+        .prepare("SELECT tag, COUNT(*) AS count FROM entity_tags GROUP BY tag ORDER BY tag")?;
+    let mut tag_counts: Vec<(String, usize)> = Vec::new();
+    while let Ok(State::Row) = statement.next() {
+        let tag = statement.read::<String, _>("tag")?;
+        let count = statement.read::<i64, _>("count")?;
+        tag_counts.push((tag, count as usize));
+    }
+    Ok(tag_counts)
+}
+
+/// Returns links where the entity is a source (`source_id`) or a target (`target_id`)
+pub fn get_links_from_sqlite(
+    entity_id_column: &str,
+    entity_id: &Ulid,
+    db_config: &DbConfig,
+) -> DocDbResult<Vec<DocDbLink>> {
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    select_links_from_sqlite(&connection, entity_id_column, entity_id)
+}
+
+pub fn select_links_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_id_column: &str,
+    entity_id: &Ulid,
+) -> DocDbResult<Vec<DocDbLink>> {
+    let mut statement = connection.prepare(format!(
+        "SELECT source_id, link_type, target_id FROM entity_links WHERE {}=:id ORDER BY link_type, source_id, target_id",
+        entity_id_column
+    ))?;
+    statement.bind((":id", entity_id.to_string().as_str()))?;
+    let mut links: Vec<DocDbLink> = Vec::new();
+    while let Ok(State::Row) = statement.next() {
+        links.push(DocDbLink {
+            source_id: parse_stored_id("entity_links", &statement.read::<String, _>("source_id")?)?,
+            link_type: statement.read::<String, _>("link_type")?,
+            target_id: parse_stored_id("entity_links", &statement.read::<String, _>("target_id")?)?,
+        });
+    }
+    Ok(links)
+}
+
+pub fn traverse_graph_in_sqlite(
+    start_id: &Ulid,
+    traversal: &Traversal,
+    db_config: &DbConfig,
+) -> DocDbResult<Vec<TraversalResult>> {
+    let mut params: Vec<(String, String)> = vec![("start_id".to_string(), start_id.to_string())];
+    let mut edge_selects: Vec<String> = Vec::new();
+    for (index, edge) in traversal.edges.iter().enumerate() {
+        let param_name = format!("edge{}", index);
+        match edge {
+            TraversalEdge::Link(link_type) => {
+                edge_selects.push(format!(
+                    "SELECT source_id, target_id FROM entity_links WHERE link_type = :{}",
+                    param_name
+                ));
+                params.push((param_name, link_type.clone()));
+            }
+            TraversalEdge::Field(field_name) => {
+                edge_selects.push(format!(
+                    "SELECT entities.id, json_each.value FROM entities, json_each(entities.content, :{}) WHERE json_each.type = 'text'",
+                    param_name
+                ));
+                params.push((param_name, format!("$.{}", field_name)));
+            }
+        }
+    }
+    if edge_selects.is_empty() {
+        edge_selects.push("SELECT NULL, NULL WHERE 0".to_string());
+    }
+    let directed_edges_select = match traversal.direction {
+        TraversalDirection::Outgoing => "SELECT source_id, target_id FROM edges",
+        TraversalDirection::Incoming => "SELECT target_id, source_id FROM edges",
+        TraversalDirection::Both => {
+            "SELECT source_id, target_id FROM edges UNION SELECT target_id, source_id FROM edges"
+        }
+    };
+    let filter_clause = match &traversal.filter {
+        Some(filter) => {
+            let (where_clause, filter_params) = filter.to_where_clause();
+            params.extend(filter_params);
+            where_clause
+        }
+        None => "1".to_string(),
+    };
+
+    // paths are comma separated ULIDs, nodes already on the path are not visited again;
+    // bare `path` column comes from the row with minimal depth (SQLite specific)
+    let query = format!(
+        "WITH RECURSIVE
+        edges(source_id, target_id) AS ({}),
+        directed_edges(from_id, to_id) AS ({}),
+        traversal(node_id, depth, path) AS (
+            SELECT :start_id, 0, :start_id
+            UNION ALL
+            SELECT directed_edges.to_id, traversal.depth + 1, traversal.path || ',' || directed_edges.to_id
+            FROM traversal JOIN directed_edges ON directed_edges.from_id = traversal.node_id
+            WHERE traversal.depth < {} AND instr(traversal.path, directed_edges.to_id) = 0
+        ),
+        shortest(node_id, depth, path) AS (
+            SELECT node_id, MIN(depth), path FROM traversal GROUP BY node_id
+        )
+        SELECT shortest.path AS path, entities.id AS id, entities.content AS content
+        FROM shortest JOIN entities ON entities.id = shortest.node_id
+        WHERE shortest.depth >= {} AND ({}) AND NOT {}
+        ORDER BY shortest.depth, entities.id",
+        edge_selects.join(" UNION ALL "),
+        directed_edges_select,
+        traversal.max_depth,
+        traversal.min_depth,
+        filter_clause,
+        EXPIRED_CONDITION
+    );
+
+    let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+    let mut statement = prepare_query(&connection, query)?;
+    for (key, value) in &params {
+        statement.bind((format!(":{}", key).as_str(), value.as_str()))?;
+    }
+    let mut results: Vec<TraversalResult> = Vec::new();
+    while let Ok(State::Row) = statement.next() {
+        let path = statement
+            .read::<String, _>("path")?
+            .split(',')
+            .map(|raw_id| parse_stored_id("entity_links", raw_id))
+            .collect::<DocDbResult<Vec<Ulid>>>()?;
+        let entity_id = statement.read::<String, _>("id")?;
+        let entity = statement.read::<String, _>("content")?;
+        results.push(TraversalResult {
+            entry: DocDbEntry {
+                id: parse_stored_id("entities", &entity_id)?,
+                entity: parse_stored_content("entities", &entity_id, &entity)?,
+            },
+            path,
+        });
+    }
+    Ok(results)
+}
+
+/// Inserts or replaces attachment, returns hash of the replaced one
+pub fn upsert_attachment_in_sqlite(
+    connection: &sqlite::Connection,
+    attachment: &DocDbAttachment,
+) -> DocDbResult<Option<String>> {
+    in_sqlite_transaction(connection, |connection| {
+        let previous_hash =
+            select_attachment_from_sqlite(connection, &attachment.entity_id, &attachment.name)?
+                .map(|previous| previous.hash);
+        let mut statement = connection.prepare(
+            "INSERT OR REPLACE INTO entity_attachments (entity_id, name, hash, size)
+            VALUES (:entity_id, :name, :hash, :size)",
+        )?;
+        statement.bind((":entity_id", attachment.entity_id.to_string().as_str()))?;
+        statement.bind((":name", attachment.name.as_str()))?;
+        statement.bind((":hash", attachment.hash.as_str()))?;
+        statement.bind((":size", attachment.size as i64))?;
+        statement.next()?;
+        Ok(previous_hash)
+    })
+}
+
+pub fn select_attachment_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+    name: &str,
+) -> DocDbResult<Option<DocDbAttachment>> {
+    let mut statement = connection.prepare(
+        "SELECT entity_id, name, hash, size FROM entity_attachments WHERE entity_id=:entity_id AND name=:name",
+    )?;
+    statement.bind((":entity_id", entity_id.to_string().as_str()))?;
+    statement.bind((":name", name))?;
+    if let State::Row = statement.next()? {
+        return Ok(Some(read_attachment(&statement)?));
+    }
+    Ok(None)
+}
+
+/// Attachments of the entity ordered by name
+pub fn select_attachments_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+) -> DocDbResult<Vec<DocDbAttachment>> {
+    let mut statement = connection.prepare(
+        "SELECT entity_id, name, hash, size FROM entity_attachments WHERE entity_id=:entity_id ORDER BY name",
+    )?;
+    statement.bind((":entity_id", entity_id.to_string().as_str()))?;
+    let mut attachments: Vec<DocDbAttachment> = Vec::new();
+    while let State::Row = statement.next()? {
+        attachments.push(read_attachment(&statement)?);
+    }
+    Ok(attachments)
+}
+
+fn read_attachment(statement: &sqlite::Statement) -> DocDbResult<DocDbAttachment> {
+    Ok(DocDbAttachment {
+        entity_id: parse_stored_id(
+            "entity_attachments",
+            &statement.read::<String, _>("entity_id")?,
+        )?,
+        name: statement.read::<String, _>("name")?,
+        hash: statement.read::<String, _>("hash")?,
+        size: statement.read::<i64, _>("size")? as u64,
+    })
+}
+
+/// Removes attachment, returns its hash, `None` when there was no such attachment
+pub fn delete_attachment_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_id: &Ulid,
+    name: &str,
+) -> DocDbResult<Option<String>> {
+    in_sqlite_transaction(connection, |connection| {
+        let hash = select_attachment_from_sqlite(connection, entity_id, name)?
+            .map(|attachment| attachment.hash);
+        let mut statement = connection
+            .prepare("DELETE FROM entity_attachments WHERE entity_id=:entity_id AND name=:name")?;
+        statement.bind((":entity_id", entity_id.to_string().as_str()))?;
+        statement.bind((":name", name))?;
+        statement.next()?;
+        Ok(hash)
+    })
+}
+
+/// Hashes of attachments of the entities
+pub fn select_attachment_hashes_from_sqlite(
+    connection: &sqlite::Connection,
+    entity_ids: &[Ulid],
+) -> DocDbResult<Vec<String>> {
+    let mut statement = connection.prepare(
+        "SELECT DISTINCT hash FROM entity_attachments WHERE entity_id IN (SELECT value FROM json_each(:ids))",
+    )?;
+    statement.bind((":ids", entity_ids_as_json_array(entity_ids).as_str()))?;
+    let mut hashes: Vec<String> = Vec::new();
+    while let State::Row = statement.next()? {
+        hashes.push(statement.read::<String, _>("hash")?);
+    }
+    Ok(hashes)
+}
+
+/// Whether any attachment still has content with the hash
+pub fn is_attachment_hash_used_in_sqlite(
+    connection: &sqlite::Connection,
+    hash: &str,
+) -> DocDbResult<bool> {
+    let mut statement =
+        connection.prepare("SELECT 1 FROM entity_attachments WHERE hash=:hash LIMIT 1")?;
+    statement.bind((":hash", hash))?;
+    Ok(matches!(statement.next()?, State::Row))
+}
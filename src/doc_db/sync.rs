@@ -0,0 +1,138 @@
+use serde::Serialize;
+use ulid::Ulid;
+
+use super::{
+    errors::DocDbError,
+    file_storage::{get_entity_file_state, list_entity_files, load_entity_from_file, FileState},
+    sql_storage::{
+        record_file_states_in_sqlite, select_file_state_from_sqlite,
+        select_file_states_from_sqlite, select_stored_entity_from_sqlite,
+    },
+    transaction::Transaction,
+    DbConfig, DocDbResult,
+};
+
+/// Numbers of entities changed in SQLite by `sync_from_files`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SyncReport {
+    pub inserted: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+/// Imports YAML files changed outside of the DB (e.g. by `git pull`) into SQLite
+///
+/// Modification time and size of every file written by the DB are recorded, so only files which
+/// differ from the recorded state are read. Added and changed files are stored (when content
+/// differs from SQLite) and entities of removed files are deleted, all in a single transaction
+/// running the same hooks and checks as other writes. Files never written nor synced before are
+/// treated as added, so the first sync reads all of them.
+pub fn sync_from_files(db_config: &DbConfig) -> DocDbResult<SyncReport> {
+    log::info!("Syncing entities from files in {}", db_config.text_db_path);
+    let files = list_entity_files(db_config)?;
+    db_config.transaction(|tx| {
+        let mut recorded_states = select_file_states_from_sqlite(tx.connection())?;
+        let mut report = SyncReport::default();
+        let mut touched_files: Vec<(Ulid, Option<FileState>)> = Vec::new();
+        for (entity_id, file_state) in files {
+            if recorded_states.remove(&entity_id) == Some(file_state) {
+                continue;
+            }
+            sync_entity_file(
+                tx,
+                &entity_id,
+                Some(file_state),
+                &mut report,
+                &mut touched_files,
+            )?;
+        }
+        // deleted after other changes, as changed files could have removed links to them
+        let mut removed_ids: Vec<Ulid> = recorded_states.into_keys().collect();
+        removed_ids.sort();
+        for entity_id in removed_ids {
+            sync_entity_file(tx, &entity_id, None, &mut report, &mut touched_files)?;
+        }
+        record_file_states_in_sqlite(tx.connection(), &touched_files)?;
+        log::info!("Synced entities from files: {:?}", report);
+        Ok(report)
+    })
+}
+
+/// Imports given entity files (e.g. reported changed by a file watcher), each in its own transaction
+///
+/// Files whose state matches the recorded one are skipped, so files written by the DB itself are
+/// not imported back. A file which can't be imported (e.g. doesn't parse) doesn't stop the others,
+/// its error is returned next to the report.
+pub fn sync_entity_files(
+    entity_ids: &[Ulid],
+    db_config: &DbConfig,
+) -> (SyncReport, Vec<(Ulid, DocDbError)>) {
+    let mut report = SyncReport::default();
+    let mut failures: Vec<(Ulid, DocDbError)> = Vec::new();
+    for entity_id in entity_ids {
+        let result = db_config.transaction(|tx| {
+            let file_state = get_entity_file_state(entity_id, db_config)?;
+            if select_file_state_from_sqlite(tx.connection(), entity_id)? == file_state {
+                return Ok(SyncReport::default());
+            }
+            let mut file_report = SyncReport::default();
+            let mut touched_files: Vec<(Ulid, Option<FileState>)> = Vec::new();
+            sync_entity_file(
+                tx,
+                entity_id,
+                file_state,
+                &mut file_report,
+                &mut touched_files,
+            )?;
+            record_file_states_in_sqlite(tx.connection(), &touched_files)?;
+            Ok(file_report)
+        });
+        match result {
+            Ok(file_report) => {
+                report.inserted += file_report.inserted;
+                report.updated += file_report.updated;
+                report.deleted += file_report.deleted;
+            }
+            Err(err) => failures.push((*entity_id, err)),
+        }
+    }
+    (report, failures)
+}
+
+/// Stores entity read from its file (`None` state when the file was removed) if it differs from
+/// SQLite, files left unchanged are added to `touched_files` as their state isn't recorded on write
+fn sync_entity_file(
+    tx: &mut Transaction,
+    entity_id: &Ulid,
+    file_state: Option<FileState>,
+    report: &mut SyncReport,
+    touched_files: &mut Vec<(Ulid, Option<FileState>)>,
+) -> DocDbResult<()> {
+    let stored_entity = select_stored_entity_from_sqlite(tx.connection(), entity_id)?;
+    if file_state.is_none() {
+        if stored_entity.is_some() {
+            log::info!("Deleting entity {} as its file was removed", entity_id);
+            tx.delete(entity_id)?;
+            report.deleted += 1;
+        }
+        touched_files.push((*entity_id, None));
+        return Ok(());
+    }
+    let file_entity = load_entity_from_file(entity_id, tx.db_config())?;
+    match stored_entity {
+        Some(stored_entity) if stored_entity == file_entity => {
+            touched_files.push((*entity_id, file_state));
+        }
+        Some(_) => {
+            log::info!("Updating entity {} from its file", entity_id);
+            tx.put(entity_id, &file_entity)?;
+            report.updated += 1;
+        }
+        None => {
+            log::info!("Inserting entity {} from its file", entity_id);
+            tx.put(entity_id, &file_entity)?;
+            report.inserted += 1;
+        }
+    }
+    Ok(())
+}
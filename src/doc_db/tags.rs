@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::{
+    document::DocQuery, errors::DocDbError, get_entries_by_query, model::DocDbEntry,
+    sql_storage::*, DbConfig, DocDbResult,
+};
+
+pub const TAGS_FIELD_NAME: &str = "tags";
+/// Separates levels of hierarchical tags, e.g. `project/alpha/urgent`
+pub const TAG_HIERARCHY_SEPARATOR: char = '/';
+/// Separates key and value of namespaced tags, e.g. `status:done`
+pub const TAG_VALUE_SEPARATOR: char = ':';
+
+/// Tags allowed in DB
+#[derive(Debug, Clone)]
+pub struct TagVocabulary {
+    /// Allowed values per key of `key:value` tags, empty list allows any value
+    pub keys: HashMap<String, Vec<String>>,
+    /// Whether tags without key (e.g. `project/alpha`) are allowed
+    pub allow_plain_tags: bool,
+}
+
+impl Default for TagVocabulary {
+    fn default() -> Self {
+        TagVocabulary {
+            keys: HashMap::new(),
+            allow_plain_tags: true,
+        }
+    }
+}
+
+/// Tag split into optional key and hierarchical path, e.g. `status:done` or `project/alpha`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedTag<'a> {
+    pub key: Option<&'a str>,
+    pub value: &'a str,
+}
+
+impl<'a> ParsedTag<'a> {
+    pub fn parse(tag: &'a str) -> DocDbResult<Self> {
+        let invalid_tag = |reason: &str| DocDbError::InvalidTag {
+            tag: tag.to_string(),
+            reason: reason.to_string(),
+        };
+        if tag.trim() != tag || tag.is_empty() {
+            return Err(invalid_tag("has to be non empty and trimmed"));
+        }
+        let (key, value) = match tag.split_once(TAG_VALUE_SEPARATOR) {
+            Some((key, value)) => (Some(key), value),
+            None => (None, tag),
+        };
+        if key == Some("") || value.is_empty() || value.contains(TAG_VALUE_SEPARATOR) {
+            return Err(invalid_tag("has to have a single non empty key and value"));
+        }
+        if tag
+            .split([TAG_HIERARCHY_SEPARATOR, TAG_VALUE_SEPARATOR])
+            .any(|segment| segment.is_empty())
+        {
+            return Err(invalid_tag("has empty hierarchy level"));
+        }
+        Ok(ParsedTag { key, value })
+    }
+}
+
+/// Checks tag syntax and, if configured, whether it belongs to tag vocabulary
+pub fn validate_tag(tag: &str, db_config: &DbConfig) -> DocDbResult<()> {
+    let parsed_tag = ParsedTag::parse(tag)?;
+    let vocabulary = match &db_config.tag_vocabulary {
+        Some(vocabulary) => vocabulary,
+        None => return Ok(()),
+    };
+    let invalid_tag = |reason: &str| DocDbError::InvalidTag {
+        tag: tag.to_string(),
+        reason: reason.to_string(),
+    };
+    match parsed_tag.key {
+        None if !vocabulary.allow_plain_tags => Err(invalid_tag("has no key")),
+        None => Ok(()),
+        Some(key) => match vocabulary.keys.get(key) {
+            None => Err(invalid_tag("has unknown key")),
+            Some(values) if !values.is_empty() && !values.iter().any(|v| v == parsed_tag.value) => {
+                Err(invalid_tag("has value not allowed for its key"))
+            }
+            Some(_) => Ok(()),
+        },
+    }
+}
+
+/// Checks every tag in tags field of the entity (which has to be an array of strings, when set)
+pub fn validate_tags(entity: &Value, db_config: &DbConfig) -> DocDbResult<()> {
+    let tags = match &entity[TAGS_FIELD_NAME] {
+        Value::Null => return Ok(()),
+        Value::Array(tags) => tags,
+        _ => {
+            return Err(DocDbError::validation(format!(
+                "Field {} is not an array",
+                TAGS_FIELD_NAME
+            )))
+        }
+    };
+    for tag in tags {
+        let tag = tag
+            .as_str()
+            .ok_or_else(|| DocDbError::validation(format!("Tag {} is not a string", tag)))?;
+        validate_tag(tag, db_config)?;
+    }
+    Ok(())
+}
+
+/// Adds tag to tags field of the entity (created when missing), e.g. in pre-write hooks, returns
+/// whether the entity didn't have it yet
+pub fn add_tag(entity: &mut Value, tag: &str, db_config: &DbConfig) -> DocDbResult<bool> {
+    validate_tag(tag, db_config)?;
+    if entity[TAGS_FIELD_NAME].is_null() {
+        entity[TAGS_FIELD_NAME] = Value::Array(Vec::new());
+    }
+    let tags = entity[TAGS_FIELD_NAME].as_array_mut().ok_or_else(|| {
+        DocDbError::validation(format!("Field {} is not an array", TAGS_FIELD_NAME))
+    })?;
+    let tag_as_value = Value::String(tag.to_string());
+    if tags.contains(&tag_as_value) {
+        return Ok(false);
+    }
+    tags.push(tag_as_value);
+    Ok(true)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagMatch {
+    /// Entity has at least one of the tags
+    Any,
+    /// Entity has every of the tags
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// Query matching entities by tags, backed by `entity_tags` table
+///
+/// Tag matches itself and its descendants, e.g. `project` matches `project/alpha` and
+/// `project:alpha`.
+pub fn tagged_with(tags: &[&str], tag_match: TagMatch) -> DocQuery {
+    // ranges select tags starting with `<tag>/` or `<tag>:`, as '0' and ';' directly follow
+    // '/' and ':' in ASCII, so that index on tag can be used
+    const TAG_CONDITION: &str = "id IN (SELECT entity_id FROM entity_tags WHERE tag = {} \
+        OR (tag > {} || '/' AND tag < {} || '0') OR (tag > {} || ':' AND tag < {} || ';'))";
+    let operator = match tag_match {
+        TagMatch::Any => " OR ",
+        TagMatch::All => " AND ",
+    };
+    let sql_template = format!("({})", vec![TAG_CONDITION; tags.len()].join(operator));
+    let values = tags
+        .iter()
+        .flat_map(|tag| std::iter::repeat_n(tag.to_string(), 5))
+        .collect();
+    DocQuery::condition_with_values(sql_template, values)
+}
+
+/// Query matching entities by tag, without its descendants
+pub fn tagged_exactly_with(tag: &str) -> DocQuery {
+    DocQuery::condition_with_values(
+        "id IN (SELECT entity_id FROM entity_tags WHERE tag = {})".to_string(),
+        vec![tag.to_string()],
+    )
+}
+
+pub fn get_all_tags(db_config: &DbConfig) -> DocDbResult<Vec<TagCount>> {
+    log::info!("Obtaining all tags from DB");
+    Ok(get_tag_counts_from_sqlite(db_config)?
+        .into_iter()
+        .map(|(tag, count)| TagCount { tag, count })
+        .collect())
+}
+
+pub fn get_entries_by_tags(
+    tags: &[&str],
+    tag_match: TagMatch,
+    db_config: &DbConfig,
+) -> DocDbResult<Vec<DocDbEntry>> {
+    if tags.is_empty() {
+        return Ok(Vec::new());
+    }
+    get_entries_by_query(&tagged_with(tags, tag_match), db_config)
+}
+
+/// Renames tag in all entities, merges it with the new tag if an entity already has both
+pub fn rename_tag(old_tag: &str, new_tag: &str, db_config: &DbConfig) -> DocDbResult<usize> {
+    log::info!("Renaming tag \"{}\" to \"{}\"", old_tag, new_tag);
+    validate_tag(new_tag, db_config)?;
+    let query = tagged_exactly_with(old_tag);
+    update_tags_of_entries(&query, db_config, |tags| {
+        let new_tag_value = Value::String(new_tag.to_string());
+        let had_new_tag = tags.contains(&new_tag_value);
+        let mut renamed = false;
+        tags.retain_mut(|tag| {
+            if tag.as_str() != Some(old_tag) {
+                return true;
+            }
+            renamed = true;
+            *tag = new_tag_value.clone();
+            !had_new_tag
+        });
+        renamed
+    })
+}
+
+/// Adds tag to all entities matching the query
+pub fn tag_entries(query: &DocQuery, tag: &str, db_config: &DbConfig) -> DocDbResult<usize> {
+    log::info!("Adding \"{}\" tag to entities matching query", tag);
+    validate_tag(tag, db_config)?;
+    update_tags_of_entries(query, db_config, |tags| {
+        let tag_as_value = Value::String(tag.to_string());
+        if tags.contains(&tag_as_value) {
+            return false;
+        }
+        tags.push(tag_as_value);
+        true
+    })
+}
+
+/// Removes tag from all entities matching the query
+pub fn untag_entries(query: &DocQuery, tag: &str, db_config: &DbConfig) -> DocDbResult<usize> {
+    log::info!("Removing \"{}\" tag from entities matching query", tag);
+    let query = query.clone().and(tagged_exactly_with(tag));
+    update_tags_of_entries(&query, db_config, |tags| {
+        let tags_count = tags.len();
+        tags.retain(|existing_tag| existing_tag.as_str() != Some(tag));
+        tags.len() != tags_count
+    })
+}
+
+/// Applies change to tags of every entry matching the query, returns number of changed entries
+///
+/// Entries are read and stored in a single transaction, so that concurrent changes of other fields
+/// aren't overwritten.
+fn update_tags_of_entries(
+    query: &DocQuery,
+    db_config: &DbConfig,
+    change_tags: impl Fn(&mut Vec<Value>) -> bool,
+) -> DocDbResult<usize> {
+    db_config.transaction(|tx| {
+        let mut changed_entries: Vec<DocDbEntry> = Vec::new();
+        for mut entry in tx.query(query)? {
+            if !entry.has_field(TAGS_FIELD_NAME)? {
+                entry.set_field_value(TAGS_FIELD_NAME, Value::Array(Vec::new()))?;
+            }
+            if let Some(tags) = entry.entity[TAGS_FIELD_NAME].as_array_mut() {
+                if change_tags(tags) {
+                    changed_entries.push(entry);
+                }
+            }
+        }
+        let changed_count = changed_entries.len();
+        tx.replace_many(changed_entries)?;
+        Ok(changed_count)
+    })
+}
@@ -0,0 +1,431 @@
+use std::collections::{HashMap, HashSet};
+
+use rayon::prelude::*;
+use serde_json::Value;
+use ulid::Ulid;
+
+use super::{
+    attachments::remove_unused_blobs,
+    document::DocQuery,
+    errors::DocDbError,
+    file_storage::{
+        delete_entity_file, get_entity_file_state, read_entity_file, restore_entity_file,
+        store_entity_in_file, FileState, RawEntityFile,
+    },
+    git,
+    hooks::PreWrite,
+    links::plan_entities_deletion,
+    merge_entities,
+    model::{ChangeOperation, DocDbChange, DocDbEntry},
+    sql_storage::*,
+    tags::validate_tags,
+    DbConfig, DocDbResult,
+};
+
+/// Writes of several entities which succeed or fail together
+///
+/// All operations run on a single SQLite connection in one transaction, YAML files are written
+/// only on commit. Functions taking `DbConfig` open their own connection, so they must not be
+/// used for writes inside a transaction (the DB is locked until it ends).
+pub struct Transaction<'a> {
+    db_config: &'a DbConfig,
+    connection: sqlite::Connection,
+    /// Content of entities changed in transaction, `None` for deleted ones
+    pending_files: HashMap<Ulid, Option<Value>>,
+    /// Hashes of attachments of deleted entities, their blobs are removed on commit unless used
+    /// by other attachments
+    released_attachment_hashes: HashSet<String>,
+    /// Last change before transaction, tracked only when there are change subscribers
+    start_change_seq: Option<u64>,
+}
+
+impl DbConfig {
+    /// Runs operations in a transaction, commits when they succeed and rolls back otherwise
+    ///
+    /// ```ignore
+    /// db_config.transaction(|tx| {
+    ///     let id = tx.insert(&person)?;
+    ///     tx.delete(&duplicate_id)
+    /// })?;
+    /// ```
+    pub fn transaction<T>(
+        &self,
+        operations: impl FnOnce(&mut Transaction) -> DocDbResult<T>,
+    ) -> DocDbResult<T> {
+        let mut transaction = Transaction::begin(self)?;
+        match operations(&mut transaction) {
+            Ok(result) => {
+                transaction.commit()?;
+                Ok(result)
+            }
+            Err(err) => {
+                transaction.rollback()?;
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<'a> Transaction<'a> {
+    fn begin(db_config: &'a DbConfig) -> DocDbResult<Self> {
+        log::info!("Beginning transaction");
+        let connection = get_sqlite_connection(&db_config.sqlite_db_full_filename)?;
+        // write lock is taken right away, so that transaction can't fail on upgrading it later
+        connection.execute("BEGIN IMMEDIATE")?;
+        let start_change_seq = if db_config.change_subscribers.is_empty()
+            && !db_config.write_hooks.has_post_commit_hooks()
+        {
+            None
+        } else {
+            Some(select_last_change_seq_from_sqlite(&connection)?)
+        };
+        let transaction = Transaction {
+            db_config,
+            connection,
+            pending_files: HashMap::new(),
+            released_attachment_hashes: HashSet::new(),
+            start_change_seq,
+        };
+        if !db_config.unique_constraints.is_empty() {
+            db_config.unique_constraints.build_values(&transaction)?;
+        }
+        Ok(transaction)
+    }
+
+    pub fn db_config(&self) -> &DbConfig {
+        self.db_config
+    }
+
+    pub(super) fn connection(&self) -> &sqlite::Connection {
+        &self.connection
+    }
+
+    /// Returns entity as seen in the transaction, including its uncommitted changes
+    pub fn get(&self, entity_id: &Ulid) -> DocDbResult<Option<DocDbEntry>> {
+        select_entry_from_sqlite(&self.connection, entity_id)
+    }
+
+    pub fn query(&self, query: &DocQuery) -> DocDbResult<Vec<DocDbEntry>> {
+        let (where_clause, params) = query.to_where_clause();
+        let params: HashMap<&str, &str> = params
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        select_entries_with_content_from_sqlite(&self.connection, "content", &where_clause, params)
+    }
+
+    pub fn insert(&mut self, entity: &Value) -> DocDbResult<Ulid> {
+        let entity_id = Ulid::new();
+        self.insert_with_id(&entity_id, entity)?;
+        Ok(entity_id)
+    }
+
+    /// Stores entity under given ID, replacing existing one (even expired) or inserting it
+    pub(super) fn put(&mut self, entity_id: &Ulid, entity: &Value) -> DocDbResult<()> {
+        match select_stored_entity_from_sqlite(&self.connection, entity_id)? {
+            Some(_) => self.replace(entity_id, entity),
+            None => self.insert_with_id(entity_id, entity),
+        }
+    }
+
+    fn insert_with_id(&mut self, entity_id: &Ulid, entity: &Value) -> DocDbResult<()> {
+        let mut entity = entity.clone();
+        self.prepare_entity(ChangeOperation::Insert, entity_id, &mut entity)?;
+        insert_entity_to_sqlite(&self.connection, entity_id, &entity)?;
+        self.check_unique_constraints(entity_id, &entity)?;
+        self.pending_files.insert(*entity_id, Some(entity));
+        Ok(())
+    }
+
+    /// Inserts all entities with a single prepared statement
+    pub fn insert_many(&mut self, entities: &[Value]) -> DocDbResult<Vec<Ulid>> {
+        let mut entries: Vec<DocDbEntry> = entities
+            .iter()
+            .map(|entity| DocDbEntry {
+                id: Ulid::new(),
+                entity: entity.clone(),
+            })
+            .collect();
+        for entry in &mut entries {
+            self.prepare_entity(ChangeOperation::Insert, &entry.id, &mut entry.entity)?;
+        }
+        insert_entities_to_sqlite(&self.connection, &entries)?;
+        for entry in &entries {
+            self.check_unique_constraints(&entry.id, &entry.entity)?;
+        }
+        Ok(entries
+            .into_iter()
+            .map(|entry| {
+                self.pending_files.insert(entry.id, Some(entry.entity));
+                entry.id
+            })
+            .collect())
+    }
+
+    /// Updates entity, fields missing in the new version are kept from the existing one
+    ///
+    /// Expired entity is merged too, so it stays expired unless `_expires_at` is updated.
+    pub fn update(&mut self, entity_id: &Ulid, entity: &Value) -> DocDbResult<()> {
+        let mut merged_entity = entity.clone();
+        if let Some(stored_entity) = select_stored_entity_from_sqlite(&self.connection, entity_id)?
+        {
+            merge_entities(&stored_entity, &mut merged_entity)?;
+        }
+        self.replace(entity_id, &merged_entity)
+    }
+
+    /// Updates all entries like `update`, with a single prepared statement
+    pub fn update_many(&mut self, entries: &[DocDbEntry]) -> DocDbResult<()> {
+        let mut merged_entries: Vec<DocDbEntry> = Vec::new();
+        for entry in entries {
+            let mut merged_entity = entry.entity.clone();
+            if let Some(stored_entity) =
+                select_stored_entity_from_sqlite(&self.connection, &entry.id)?
+            {
+                merge_entities(&stored_entity, &mut merged_entity)?;
+            }
+            merged_entries.push(DocDbEntry {
+                id: entry.id,
+                entity: merged_entity,
+            });
+        }
+        self.replace_many(merged_entries)
+    }
+
+    /// Stores entity as it is, fields missing in the new version are removed
+    pub fn replace(&mut self, entity_id: &Ulid, entity: &Value) -> DocDbResult<()> {
+        let mut entity = entity.clone();
+        self.prepare_entity(ChangeOperation::Update, entity_id, &mut entity)?;
+        update_entity_in_sqlite(&self.connection, entity_id, &entity)?;
+        self.check_unique_constraints(entity_id, &entity)?;
+        self.pending_files.insert(*entity_id, Some(entity));
+        Ok(())
+    }
+
+    pub(super) fn replace_many(&mut self, mut entries: Vec<DocDbEntry>) -> DocDbResult<()> {
+        for entry in &mut entries {
+            self.prepare_entity(ChangeOperation::Update, &entry.id, &mut entry.entity)?;
+        }
+        update_entities_in_sqlite(&self.connection, &entries)?;
+        for entry in &entries {
+            self.check_unique_constraints(&entry.id, &entry.entity)?;
+        }
+        for entry in entries {
+            self.pending_files.insert(entry.id, Some(entry.entity));
+        }
+        Ok(())
+    }
+
+    /// Deletes entity, applying delete rules of links pointing at it
+    pub fn delete(&mut self, entity_id: &Ulid) -> DocDbResult<()> {
+        match self.delete_entities(&[*entity_id])? {
+            0 => Err(DocDbError::not_found(entity_id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes all entities matching the query, applying delete rules of links pointing at them,
+    /// returns number of deleted entities (including cascaded ones)
+    pub fn delete_where(&mut self, query: &DocQuery) -> DocDbResult<usize> {
+        let entity_ids: Vec<Ulid> = self.query(query)?.iter().map(|entry| entry.id).collect();
+        self.delete_entities(&entity_ids)
+    }
+
+    fn delete_entities(&mut self, entity_ids: &[Ulid]) -> DocDbResult<usize> {
+        let deletion_plan = plan_entities_deletion(entity_ids, self)?;
+        for link in &deletion_plan.nullified_links {
+            self.unlink(&link.source_id, &link.link_type, &link.target_id)?;
+        }
+        for deleted_id in deletion_plan
+            .entity_ids
+            .iter()
+            .filter(|deleted_id| !entity_ids.contains(deleted_id))
+        {
+            log::info!("Deleting linked entity {} from DB", deleted_id);
+        }
+        for deleted_id in &deletion_plan.entity_ids {
+            self.run_pre_write_hooks(ChangeOperation::Delete, deleted_id, None)?;
+        }
+        self.released_attachment_hashes
+            .extend(select_attachment_hashes_from_sqlite(
+                &self.connection,
+                &deletion_plan.entity_ids,
+            )?);
+        let deleted_count =
+            delete_entities_from_sqlite(&self.connection, &deletion_plan.entity_ids)?;
+        for deleted_id in &deletion_plan.entity_ids {
+            self.pending_files.insert(*deleted_id, None);
+        }
+        Ok(deleted_count)
+    }
+
+    /// Runs pre-write hooks and then recomputes derived fields, so that hooks can't change them
+    ///
+    /// Tags (including ones added by hooks) are validated for every write, whatever function adds
+    /// them.
+    fn prepare_entity(
+        &self,
+        operation: ChangeOperation,
+        entity_id: &Ulid,
+        entity: &mut Value,
+    ) -> DocDbResult<()> {
+        self.run_pre_write_hooks(operation, entity_id, Some(entity))?;
+        validate_tags(entity, self.db_config)?;
+        self.db_config
+            .derived_fields
+            .apply(entity, &self.connection)
+    }
+
+    /// Checked after the entity is written to SQLite, so that entities written earlier in the
+    /// same batch are taken into account
+    fn check_unique_constraints(&self, entity_id: &Ulid, entity: &Value) -> DocDbResult<()> {
+        let unique_constraints = &self.db_config.unique_constraints;
+        if unique_constraints.is_empty() {
+            return Ok(());
+        }
+        unique_constraints.check(entity_id, entity, self)
+    }
+
+    fn run_pre_write_hooks(
+        &self,
+        operation: ChangeOperation,
+        entity_id: &Ulid,
+        new_entity: Option<&mut Value>,
+    ) -> DocDbResult<()> {
+        let write_hooks = &self.db_config.write_hooks;
+        if !write_hooks.has_pre_write_hooks() {
+            return Ok(());
+        }
+        let old_entry = match operation {
+            ChangeOperation::Insert => None,
+            _ => self.get(entity_id)?,
+        };
+        let mut pre_write = PreWrite {
+            operation,
+            entity_id: *entity_id,
+            old_entity: old_entry.as_ref().map(|entry| &entry.entity),
+            new_entity,
+        };
+        write_hooks.run_pre_write(self, &mut pre_write)
+    }
+
+    /// Writes YAML files (in parallel) and commits SQLite, files are restored if either fails
+    fn commit(self) -> DocDbResult<()> {
+        log::info!(
+            "Committing transaction changing {} entities",
+            self.pending_files.len()
+        );
+        let original_files = match self.read_original_files() {
+            Ok(original_files) => original_files,
+            Err(err) => {
+                self.rollback()?;
+                return Err(err);
+            }
+        };
+        let mut result: DocDbResult<()> =
+            self.pending_files
+                .par_iter()
+                .try_for_each(|(entity_id, entity)| match entity {
+                    Some(entity) => store_entity_in_file(entity_id, entity, self.db_config),
+                    None if original_files[entity_id].is_some() => {
+                        delete_entity_file(entity_id, self.db_config)
+                    }
+                    None => Ok(()),
+                });
+        if result.is_ok() {
+            result = self.record_file_states();
+        }
+        // changes are read before commit, so that only those of this transaction are included
+        let mut committed_changes: Vec<DocDbChange> = Vec::new();
+        if let (Ok(()), Some(start_change_seq)) = (&result, self.start_change_seq) {
+            result = select_changes_since_from_sqlite(&self.connection, start_change_seq)
+                .map(|changes| committed_changes = changes);
+        }
+        if result.is_ok() {
+            result = self.connection.execute("COMMIT").map_err(DocDbError::from);
+        }
+        if let Err(err) = result {
+            original_files
+                .par_iter()
+                .for_each(|(entity_id, original_file)| {
+                    if let Err(restore_err) =
+                        restore_entity_file(entity_id, original_file.as_ref(), self.db_config)
+                    {
+                        log::error!("Unable to restore entity {}: {}", entity_id, restore_err);
+                    }
+                });
+            if let Err(rollback_err) = self.rollback() {
+                log::error!("Unable to roll back transaction: {}", rollback_err);
+            }
+            return Err(err);
+        }
+        // blobs left behind are only taking space, so committed data isn't reported as failed
+        if let Err(err) = remove_unused_blobs(&self.released_attachment_hashes, self.db_config) {
+            log::error!("Unable to remove attachments of deleted entities: {}", err);
+        }
+        self.commit_files_to_git(&original_files);
+        if committed_changes.is_empty() {
+            return Ok(());
+        }
+        self.db_config.change_subscribers.notify(&committed_changes);
+        self.db_config
+            .write_hooks
+            .run_post_commit(&committed_changes);
+        Ok(())
+    }
+
+    /// Data stays committed when Git fails (the error is only logged), files left uncommitted are
+    /// added by the next commit
+    fn commit_files_to_git(&self, original_files: &HashMap<Ulid, Option<RawEntityFile>>) {
+        if self.db_config.git.is_none() {
+            return;
+        }
+        let mut file_changes: Vec<(ChangeOperation, Ulid)> = self
+            .pending_files
+            .iter()
+            .filter_map(|(entity_id, entity)| {
+                let existed = original_files[entity_id].is_some();
+                match (existed, entity) {
+                    (false, Some(_)) => Some((ChangeOperation::Insert, *entity_id)),
+                    (true, Some(_)) => Some((ChangeOperation::Update, *entity_id)),
+                    (true, None) => Some((ChangeOperation::Delete, *entity_id)),
+                    (false, None) => None,
+                }
+            })
+            .collect();
+        file_changes.sort_by_key(|(_, entity_id)| *entity_id);
+        if let Err(err) = git::commit_files(&file_changes, None, self.db_config) {
+            log::error!("Unable to commit files to Git: {}", err);
+        }
+    }
+
+    /// Stores states of written files, so that later changes made outside of the DB are detected
+    fn record_file_states(&self) -> DocDbResult<()> {
+        let file_states: Vec<(Ulid, Option<FileState>)> = self
+            .pending_files
+            .par_iter()
+            .map(|(entity_id, entity)| match entity {
+                Some(_) => Ok((
+                    *entity_id,
+                    get_entity_file_state(entity_id, self.db_config)?,
+                )),
+                None => Ok((*entity_id, None)),
+            })
+            .collect::<DocDbResult<_>>()?;
+        record_file_states_in_sqlite(&self.connection, &file_states)
+    }
+
+    /// Current content of files of changed entities
+    fn read_original_files(&self) -> DocDbResult<HashMap<Ulid, Option<RawEntityFile>>> {
+        self.pending_files
+            .par_iter()
+            .map(|(entity_id, _)| Ok((*entity_id, read_entity_file(entity_id, self.db_config)?)))
+            .collect()
+    }
+
+    fn rollback(self) -> DocDbResult<()> {
+        log::info!("Rolling back transaction");
+        self.connection.execute("ROLLBACK")?;
+        Ok(())
+    }
+}
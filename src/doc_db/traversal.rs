@@ -0,0 +1,110 @@
+use ulid::Ulid;
+
+use super::{
+    document::DocQuery, model::DocDbEntry, sql_storage::traverse_graph_in_sqlite, DbConfig,
+    DocDbResult,
+};
+
+/// Kind of connection between documents followed by traversal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraversalEdge {
+    /// Typed link (see `links`), e.g. `works_at`
+    Link(String),
+    /// Top-level field holding ULID or array of ULIDs, e.g. `employer_id`
+    Field(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TraversalDirection {
+    /// From link source / field owner to referenced document
+    #[default]
+    Outgoing,
+    /// From referenced document to link source / field owner
+    Incoming,
+    Both,
+}
+
+/// Graph traversal over document links, executed as recursive CTE in SQLite
+#[derive(Debug, Clone)]
+pub struct Traversal {
+    pub edges: Vec<TraversalEdge>,
+    pub direction: TraversalDirection,
+    pub min_depth: u32,
+    pub max_depth: u32,
+    /// Condition documents have to match to be returned (they are traversed anyway)
+    pub filter: Option<DocQuery>,
+}
+
+impl Traversal {
+    pub fn new(edges: Vec<TraversalEdge>, max_depth: u32) -> Self {
+        Traversal {
+            edges,
+            direction: TraversalDirection::default(),
+            min_depth: 1,
+            max_depth,
+            filter: None,
+        }
+    }
+
+    pub fn direction(mut self, direction: TraversalDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn min_depth(mut self, min_depth: u32) -> Self {
+        self.min_depth = min_depth;
+        self
+    }
+
+    pub fn filter(mut self, filter: DocQuery) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+}
+
+/// Document reached by traversal with the shortest path leading to it (starting document first)
+#[derive(Debug)]
+pub struct TraversalResult {
+    pub entry: DocDbEntry,
+    pub path: Vec<Ulid>,
+}
+
+impl TraversalResult {
+    pub fn depth(&self) -> usize {
+        self.path.len() - 1
+    }
+}
+
+/// Returns documents reachable from the start document, ordered by distance
+pub fn traverse(
+    start_id: &Ulid,
+    traversal: &Traversal,
+    db_config: &DbConfig,
+) -> DocDbResult<Vec<TraversalResult>> {
+    log::info!(
+        "Traversing DB from {} up to depth {}",
+        start_id,
+        traversal.max_depth
+    );
+    traverse_graph_in_sqlite(start_id, traversal, db_config)
+}
+
+/// Returns the shortest path between documents (both included), if any within traversal depth
+pub fn shortest_path(
+    from_id: &Ulid,
+    to_id: &Ulid,
+    traversal: &Traversal,
+    db_config: &DbConfig,
+) -> DocDbResult<Option<Vec<Ulid>>> {
+    let target_condition = DocQuery::with_id(to_id);
+    let path_traversal = Traversal {
+        min_depth: 0,
+        filter: Some(match &traversal.filter {
+            Some(filter) => filter.clone().and(target_condition),
+            None => target_condition,
+        }),
+        ..traversal.clone()
+    };
+    let mut results = traverse(from_id, &path_traversal, db_config)?;
+    Ok(results.pop().map(|result| result.path))
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use super::DocDbResult;
+
+/// Order of top-level fields in YAML files, nested objects are always sorted alphabetically
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum KeyOrder {
+    #[default]
+    Alphabetical,
+    /// Listed fields first in the listed order (e.g. as declared by the domain type), then the
+    /// rest alphabetically
+    Schema(Vec<String>),
+}
+
+impl KeyOrder {
+    pub fn schema(field_names: &[&str]) -> Self {
+        KeyOrder::Schema(field_names.iter().map(|name| name.to_string()).collect())
+    }
+
+    fn sort(&self, fields: &Map<String, Value>) -> Vec<String> {
+        let mut keys: Vec<String> = fields.keys().cloned().collect();
+        keys.sort();
+        if let KeyOrder::Schema(field_names) = self {
+            keys.sort_by_key(|key| {
+                field_names
+                    .iter()
+                    .position(|name| name == key)
+                    .unwrap_or(field_names.len())
+            });
+        }
+        keys
+    }
+}
+
+/// Writes entity as YAML with stable output for clean diffs
+///
+/// Top-level fields are written in `key_order`, multi-line strings as block scalars (unless they
+/// contain characters YAML can't keep in them, e.g. trailing spaces). When the file is rewritten,
+/// `previous_text` fields with unchanged values keep their text, and comments above fields are
+/// kept with them. Comments of removed fields are dropped.
+pub fn format_yaml(
+    entity: &Value,
+    key_order: &KeyOrder,
+    previous_text: Option<&str>,
+) -> DocDbResult<String> {
+    let fields = match entity {
+        Value::Object(fields) if !fields.is_empty() => fields,
+        other => return Ok(serde_yaml::to_string(other)?),
+    };
+    let previous = previous_text.and_then(split_fields);
+    let mut text = String::new();
+    if let Some(previous) = &previous {
+        text.push_str(&previous.header);
+    }
+    for key in key_order.sort(fields) {
+        let value = &fields[&key];
+        match previous
+            .as_ref()
+            .and_then(|previous| previous.fields.get(&key))
+        {
+            Some(field) if &field.value == value => {
+                text.push_str(&field.comments);
+                text.push_str(&field.text);
+            }
+            Some(field) => {
+                text.push_str(&field.comments);
+                text.push_str(&format_field(&key, value)?);
+            }
+            None => text.push_str(&format_field(&key, value)?),
+        }
+    }
+    if let Some(previous) = &previous {
+        text.push_str(&previous.footer);
+    }
+    Ok(text)
+}
+
+fn format_field(key: &str, value: &Value) -> DocDbResult<String> {
+    let mut field = Map::new();
+    field.insert(key.to_string(), value.clone());
+    Ok(serde_yaml::to_string(&field)?)
+}
+
+/// Text of a top-level field with comment (and blank) lines above it
+struct FieldText {
+    comments: String,
+    text: String,
+    value: Value,
+}
+
+struct DocumentText {
+    /// Lines up to the `---` document start
+    header: String,
+    fields: HashMap<String, FieldText>,
+    /// Comments after the last field
+    footer: String,
+}
+
+/// Splits YAML mapping into top-level fields, `None` when the text isn't split cleanly
+/// (e.g. flow mappings or fields of a block scalar ending with blank lines)
+fn split_fields(text: &str) -> Option<DocumentText> {
+    let mut header = String::new();
+    let mut blocks: Vec<(String, String)> = Vec::new();
+    let mut pending = String::new();
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        let is_sequence_item = trimmed == "-" || trimmed.starts_with("- ");
+        let is_continuation = line.starts_with([' ', '\t']) || is_sequence_item;
+        if blocks.is_empty() && trimmed == "---" {
+            header.push_str(&pending);
+            header.push_str(line);
+            pending.clear();
+        } else if trimmed.is_empty() || (trimmed.starts_with('#') && !is_continuation) {
+            pending.push_str(line);
+        } else if is_continuation {
+            let (_, field_text) = blocks.last_mut()?;
+            field_text.push_str(&pending);
+            field_text.push_str(line);
+            pending.clear();
+        } else {
+            blocks.push((std::mem::take(&mut pending), line.to_string()));
+        }
+    }
+
+    let mut fields = HashMap::new();
+    for (comments, mut field_text) in blocks {
+        if !field_text.ends_with('\n') {
+            field_text.push('\n');
+        }
+        let mut parsed: Map<String, Value> = serde_yaml::from_str(&field_text).ok()?;
+        let key = parsed.keys().next()?.clone();
+        let value = parsed.remove(&key)?;
+        if !parsed.is_empty() {
+            return None;
+        }
+        let field = FieldText {
+            comments,
+            text: field_text,
+            value,
+        };
+        if fields.insert(key, field).is_some() {
+            return None;
+        }
+    }
+
+    // e.g. blank lines kept by `|+` block scalar are read as separators of fields
+    let document: Map<String, Value> = serde_yaml::from_str(text).ok()?;
+    let matches_document = document.len() == fields.len()
+        && document
+            .iter()
+            .all(|(key, value)| fields.get(key).is_some_and(|field| &field.value == value));
+    if !matches_document {
+        return None;
+    }
+    Some(DocumentText {
+        header,
+        fields,
+        footer: pending,
+    })
+}
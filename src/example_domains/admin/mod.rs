@@ -2,16 +2,95 @@
 
 pub mod model;
 
+use serde_json::{json, Value};
 use ulid::Ulid;
 
-use crate::doc_db::{tag_entity, untag_entity, DbConfig, DocDbResult};
+use crate::doc_db::{
+    document::COLLECTION_FIELD_NAME,
+    domain::DomainView,
+    get_entry_in_domain,
+    hooks::WriteHooks,
+    model::ChangeOperation,
+    tags::{add_tag, TagVocabulary, TAGS_FIELD_NAME, TAG_VALUE_SEPARATOR},
+    update_entity_in_domain, DbConfig, DocDbResult,
+};
+
+use self::model::EntityMeta;
+
+/// Key of `key:value` tags set by admin domain
+pub const TAG_KEY: &str = "admin";
+pub const IMPORTANT_TAG: &str = "admin:important";
+/// Stamped on new entities of administered collections, until an admin reviews them
+pub const UNREVIEWED_TAG: &str = "admin:unreviewed";
+
+/// Allows tags set by admin domain in the tag vocabulary
+pub fn allow_tags(tag_vocabulary: &mut TagVocabulary) {
+    let values = [IMPORTANT_TAG, UNREVIEWED_TAG]
+        .iter()
+        .filter_map(|tag| tag.strip_prefix(TAG_KEY)?.strip_prefix(TAG_VALUE_SEPARATOR))
+        .map(|value| value.to_string())
+        .collect();
+    tag_vocabulary.keys.insert(TAG_KEY.to_string(), values);
+}
+
+pub fn domain_view() -> DomainView {
+    DomainView::new("admin").owning_document::<EntityMeta>()
+}
+
+/// Stamps new entities of the collections as unreviewed and protects their important entities
+/// from deletion
+pub fn register_write_hooks(write_hooks: &WriteHooks, collections: &[&str]) {
+    let collections: Vec<String> = collections.iter().map(|name| name.to_string()).collect();
+    let stamped_collections = collections.clone();
+    write_hooks.before_write("admin/stamp-unreviewed", move |tx, pre_write| {
+        if pre_write.operation != ChangeOperation::Insert {
+            return Ok(());
+        }
+        let entity = match pre_write.new_entity.as_deref_mut() {
+            Some(entity) if is_in_collections(entity, &stamped_collections) => entity,
+            _ => return Ok(()),
+        };
+        add_tag(entity, UNREVIEWED_TAG, tx.db_config()).map_err(|err| err.to_string())?;
+        Ok(())
+    });
+    write_hooks.before_write("admin/protect-important", move |_, pre_write| {
+        let is_important = pre_write
+            .old_entity
+            .filter(|entity| is_in_collections(entity, &collections))
+            .and_then(|entity| entity[TAGS_FIELD_NAME].as_array())
+            .is_some_and(|tags| tags.contains(&json!(IMPORTANT_TAG)));
+        if pre_write.operation == ChangeOperation::Delete && is_important {
+            return Err("important entities can't be deleted".to_string());
+        }
+        Ok(())
+    });
+}
+
+fn is_in_collections(entity: &Value, collections: &[String]) -> bool {
+    entity[COLLECTION_FIELD_NAME]
+        .as_str()
+        .is_some_and(|collection| collections.iter().any(|name| name == collection))
+}
+
+pub fn get_entity_meta(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<Option<EntityMeta>> {
+    match get_entry_in_domain(&domain_view(), entity_id, db_config)? {
+        Some(db_entry) => Ok(Some(serde_json::from_value(db_entry.entity)?)),
+        None => Ok(None),
+    }
+}
 
 pub fn mark_entity_as_important(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<()> {
-    tag_entity(entity_id, "important", db_config)?;
+    let mut entity_meta = get_entity_meta(entity_id, db_config)?.unwrap_or_default();
+    if !entity_meta.tags.contains(&IMPORTANT_TAG.to_string()) {
+        entity_meta.tags.push(IMPORTANT_TAG.to_string());
+    }
+    update_entity_in_domain(&domain_view(), entity_id, &json!(entity_meta), db_config)?;
     Ok(())
 }
 
 pub fn unmark_entity_as_important(entity_id: &Ulid, db_config: &DbConfig) -> DocDbResult<()> {
-    untag_entity(entity_id, "important", db_config)?;
+    let mut entity_meta = get_entity_meta(entity_id, db_config)?.unwrap_or_default();
+    entity_meta.tags.retain(|tag| tag != IMPORTANT_TAG);
+    update_entity_in_domain(&domain_view(), entity_id, &json!(entity_meta), db_config)?;
     Ok(())
 }
@@ -1,6 +1,10 @@
+use rust_doc_db::doc_db::document::DocDbDocument;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, DocDbDocument)]
+#[doc_db(schema_version = 1)]
 pub struct EntityMeta {
+    #[serde(default)]
+    #[doc_db(owned)]
     pub tags: Vec<String>,
 }
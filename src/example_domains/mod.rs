@@ -1,2 +1,33 @@
+use crate::doc_db::{
+    constraints::UniqueConstraints, derived::DerivedFields, document::DocDbDocument,
+    domain::DomainRegistry, hooks::WriteHooks, slugs::FileSlugs, DocDbResult,
+};
+
+use self::pim::model::Person;
+
 pub mod admin;
 pub mod pim;
+
+pub fn domain_registry() -> DocDbResult<DomainRegistry> {
+    let mut registry = DomainRegistry::new();
+    registry.register(admin::domain_view())?;
+    registry.register(pim::domain_view())?;
+    Ok(registry)
+}
+
+pub fn register_write_hooks(write_hooks: &WriteHooks) {
+    admin::register_write_hooks(write_hooks, &[Person::COLLECTION.unwrap()]);
+    pim::register_write_hooks(write_hooks);
+}
+
+pub fn register_derived_fields(derived_fields: &DerivedFields) {
+    pim::register_derived_fields(derived_fields);
+}
+
+pub fn register_unique_constraints(unique_constraints: &UniqueConstraints) {
+    pim::register_unique_constraints(unique_constraints);
+}
+
+pub fn register_file_slugs(file_slugs: &FileSlugs) -> DocDbResult<()> {
+    pim::register_file_slugs(file_slugs)
+}
@@ -1,25 +1,109 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use serde_json::json;
+use ulid::Ulid;
 
-use crate::doc_db::{get_entries_from_db, DbConfig, DocDbResult};
+use crate::doc_db::{
+    constraints::{UniqueConstraint, UniqueConstraints},
+    derived::{DerivedField, DerivedFields},
+    document::{DocDbDocument, DocField, COLLECTION_FIELD_NAME},
+    domain::DomainView,
+    get_entries_in_domain,
+    hooks::WriteHooks,
+    insert_entity_in_domain,
+    slugs::FileSlugs,
+    update_entity_in_domain, DbConfig, DocDbResult,
+};
 
 use self::model::Person;
 
 pub mod fake_data_generator;
 pub mod model;
 
+pub fn domain_view() -> DomainView {
+    DomainView::new("pim")
+        .owning_document::<Person>()
+        .reading(&["tags"])
+}
+
+/// Derived from names of a person, e.g. `Piotr Nowak`
+pub const FULLNAME: DocField = DocField::new("fullname");
+
+pub fn register_derived_fields(derived_fields: &DerivedFields) {
+    let collection = Person::COLLECTION.unwrap();
+    derived_fields.define(
+        collection,
+        DerivedField::sql(
+            "fullname",
+            "json_extract(content, '$.firstname') || ' ' || json_extract(content, '$.lastname')",
+        )
+        .indexed(),
+    );
+    derived_fields.define(
+        collection,
+        DerivedField::computed("phone_count", |person| {
+            json!(person["phones"].as_array().map_or(0, Vec::len))
+        }),
+    );
+}
+
+/// No two people can share a phone number (compared after normalization)
+pub fn register_unique_constraints(unique_constraints: &UniqueConstraints) {
+    unique_constraints.declare(
+        UniqueConstraint::new("pim/unique-phones", &["phones[*]"])
+            .in_collection(Person::COLLECTION.unwrap()),
+    );
+}
+
+/// Names files of people like `kowalski-jan-01H6....yaml`
+pub fn register_file_slugs(file_slugs: &FileSlugs) -> DocDbResult<()> {
+    file_slugs.declare(Person::COLLECTION.unwrap(), "{lastname}-{firstname}-{id}")
+}
+
+/// Stores phone numbers of people without separators, e.g. `+48 123-456-789` as `+48123456789`
+pub fn register_write_hooks(write_hooks: &WriteHooks) {
+    write_hooks.before_write("pim/normalize-phones", |_, pre_write| {
+        let phones = pre_write
+            .new_entity
+            .as_deref_mut()
+            .filter(|entity| entity[COLLECTION_FIELD_NAME] == json!(Person::COLLECTION))
+            .and_then(|entity| entity.get_mut("phones"))
+            .and_then(|phones| phones.as_array_mut());
+        for phone in phones.into_iter().flatten() {
+            if let Some(number) = phone.as_str() {
+                *phone = json!(normalize_phone_number(number)?);
+            }
+        }
+        Ok(())
+    });
+}
+
+fn normalize_phone_number(number: &str) -> Result<String, String> {
+    let normalized: String = number
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '(' | ')'))
+        .collect();
+    let digits = normalized.strip_prefix('+').unwrap_or(&normalized);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("invalid phone number {:?}", number));
+    }
+    Ok(normalized)
+}
+
+pub fn add_person(person: &Person, db_config: &DbConfig) -> DocDbResult<Ulid> {
+    insert_entity_in_domain(&domain_view(), &json!(person), db_config)
+}
+
+pub fn update_person(entity_id: &Ulid, person: &Person, db_config: &DbConfig) -> DocDbResult<()> {
+    update_entity_in_domain(&domain_view(), entity_id, &json!(person), db_config)
+}
+
 pub fn get_phones_of_people_by_firstname(
     firstname: &str,
     db_config: &DbConfig,
 ) -> DocDbResult<Vec<String>> {
-    let mut where_clause_params: HashMap<&str, &str> = HashMap::new();
-    where_clause_params.insert("firstname", firstname);
-    let entities = get_entries_from_db(
-        "json_extract(content, '$.firstname') = :firstname",
-        where_clause_params,
-        db_config,
-    )?;
+    let entities =
+        get_entries_in_domain(&domain_view(), &Person::FIRSTNAME.eq(firstname), db_config)?;
 
     let mut phones: Vec<String> = Vec::new();
     for entry in entities {
@@ -1,14 +1,20 @@
+use rust_doc_db::doc_db::document::{DocDbDocument, DocDbSchema};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, DocDbDocument)]
+#[doc_db(collection = "people", schema_version = 1)]
 pub struct Person {
+    #[doc_db(indexed, owned)]
     pub firstname: String,
+    #[doc_db(indexed, owned)]
     pub lastname: String,
+    #[doc_db(owned)]
     pub addresses: Vec<Address>,
+    #[doc_db(owned)]
     pub phones: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, DocDbSchema)]
 pub struct Address {
     pub street: String,
     pub home_number: i32,
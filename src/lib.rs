@@ -1,3 +1,6 @@
+// allows derived code to refer to this crate as `::rust_doc_db` also from within the crate
+extern crate self as rust_doc_db;
+
 pub mod config;
 pub mod doc_db;
 pub mod example_domains;
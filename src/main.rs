@@ -1,20 +1,54 @@
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, TagsCommands};
 use color_eyre::eyre::Result;
-use doc_db::{clear_db, insert_entity_to_db, make_sure_db_exists, DbConfig};
-use example_domains::pim::fake_data_generator::generate_people;
-use serde_json::json;
+use rust_doc_db::config;
+use rust_doc_db::doc_db::attachments::{get_attachment, list_attachments, put_attachment};
+use rust_doc_db::doc_db::changes::{changes_since, last_change_seq};
+use rust_doc_db::doc_db::document::DocQuery;
+use rust_doc_db::doc_db::expiry::sweep_expired;
+use rust_doc_db::doc_db::file_watcher::watch_files;
+use rust_doc_db::doc_db::git::{get_entity_history, GitSettings};
+use rust_doc_db::doc_db::layout::relayout_files;
+use rust_doc_db::doc_db::links::{get_links_from, get_links_to};
+use rust_doc_db::doc_db::sync::sync_from_files;
+use rust_doc_db::doc_db::tags::{
+    get_all_tags, get_entries_by_tags, rename_tag, tag_entries, untag_entries, TagMatch,
+};
+use rust_doc_db::doc_db::{
+    clear_db, insert_entities_to_db, make_sure_db_exists, tag_entity, untag_entity, DbConfig,
+};
+use rust_doc_db::example_domains::pim::fake_data_generator::generate_people;
+use rust_doc_db::example_domains::{
+    register_derived_fields, register_file_slugs, register_unique_constraints, register_write_hooks,
+};
+use serde_json::{json, Value};
+use std::{fs, path::PathBuf, thread, time::Duration};
+use ulid::Ulid;
 
 mod cli;
-mod config;
-mod doc_db;
-mod example_domains;
 
 fn get_prod_db_config() -> DbConfig {
-    DbConfig {
+    let db_config = DbConfig {
         sqlite_db_full_filename: config::SQLITE_DB_FULL_FILENAME.to_string(),
         text_db_path: config::YAML_FILES_ROOT_PATH.to_string(),
-    }
+        file_layout: config::FILE_LAYOUT,
+        git: config::GIT_VERSIONING_ENABLED.then(GitSettings::default),
+        ..Default::default()
+    };
+    register_write_hooks(&db_config.write_hooks);
+    register_derived_fields(&db_config.derived_fields);
+    register_unique_constraints(&db_config.unique_constraints);
+    register_file_slugs(&db_config.file_slugs).expect("file name templates are valid");
+    db_config
+}
+
+/// Query matching all `<FIELD>=<VALUE>` filters, clap ensures there is at least one
+fn filters_query(filters: &[(String, Value)]) -> DocQuery {
+    filters
+        .iter()
+        .map(|(field_name, value)| DocQuery::field_eq(field_name, value.clone()))
+        .reduce(DocQuery::and)
+        .expect("at least one filter")
 }
 
 fn main() -> Result<()> {
@@ -53,12 +87,226 @@ fn main() -> Result<()> {
         Some(Commands::GenerateData {}) => {
             let db_config = get_prod_db_config();
             const PEOPLE_COUNT: u32 = 100;
-            let people = generate_people(PEOPLE_COUNT);
-            for person in people {
-                let json_person = json!(person);
-                match insert_entity_to_db(&json_person, &db_config) {
-                    Ok(_) => {}
-                    Err(e) => log::error!("Unable to save person: {}", e),
+            let people: Vec<Value> = generate_people(PEOPLE_COUNT)
+                .iter()
+                .map(|person| json!(person))
+                .collect();
+            if let Err(e) = insert_entities_to_db(&people, &db_config) {
+                log::error!("Unable to save people: {}", e);
+            }
+        }
+        Some(Commands::Tag {
+            entity_id,
+            tag,
+            filters,
+        }) => {
+            let db_config = get_prod_db_config();
+            match entity_id {
+                Some(entity_id) => {
+                    let entity_id = Ulid::from_string(entity_id)?;
+                    match tag_entity(&entity_id, tag, &db_config) {
+                        Ok(_) => log::info!("Entity {} tagged with \"{}\"", entity_id, tag),
+                        Err(e) => log::error!("Unable to tag entity: {}", e),
+                    }
+                }
+                None => match tag_entries(&filters_query(filters), tag, &db_config) {
+                    Ok(count) => log::info!("{} entities tagged with \"{}\"", count, tag),
+                    Err(e) => log::error!("Unable to tag entities: {}", e),
+                },
+            }
+        }
+        Some(Commands::Untag {
+            entity_id,
+            tag,
+            filters,
+        }) => {
+            let db_config = get_prod_db_config();
+            match entity_id {
+                Some(entity_id) => {
+                    let entity_id = Ulid::from_string(entity_id)?;
+                    match untag_entity(&entity_id, tag, &db_config) {
+                        Ok(_) => log::info!("Tag \"{}\" removed from entity {}", tag, entity_id),
+                        Err(e) => log::error!("Unable to untag entity: {}", e),
+                    }
+                }
+                None => match untag_entries(&filters_query(filters), tag, &db_config) {
+                    Ok(count) => log::info!("Tag \"{}\" removed from {} entities", tag, count),
+                    Err(e) => log::error!("Unable to untag entities: {}", e),
+                },
+            }
+        }
+        Some(Commands::Attach {
+            entity_id,
+            file,
+            name,
+        }) => {
+            let db_config = get_prod_db_config();
+            let entity_id = Ulid::from_string(entity_id)?;
+            let name = match name {
+                Some(name) => name.clone(),
+                None => file
+                    .file_name()
+                    .map(|file_name| file_name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            };
+            let content = fs::read(file)?;
+            match put_attachment(&entity_id, &name, &content, &db_config) {
+                Ok(attachment) => log::info!(
+                    "Attached {} ({} bytes) to entity {}",
+                    attachment.name,
+                    attachment.size,
+                    entity_id
+                ),
+                Err(e) => log::error!("Unable to attach file: {}", e),
+            }
+        }
+        Some(Commands::Extract {
+            entity_id,
+            name,
+            output,
+        }) => {
+            let db_config = get_prod_db_config();
+            let entity_id = Ulid::from_string(entity_id)?;
+            match get_attachment(&entity_id, name, &db_config) {
+                Ok(Some(content)) => {
+                    let output = output.clone().unwrap_or_else(|| PathBuf::from(name));
+                    fs::write(&output, content)?;
+                    log::info!("Attachment {} written to {}", name, output.display());
+                }
+                Ok(None) => log::error!("Entity {} has no attachment {}", entity_id, name),
+                Err(e) => log::error!("Unable to extract attachment: {}", e),
+            }
+        }
+        Some(Commands::Attachments { entity_id }) => {
+            let db_config = get_prod_db_config();
+            let entity_id = Ulid::from_string(entity_id)?;
+            match list_attachments(&entity_id, &db_config) {
+                Ok(attachments) => {
+                    for attachment in attachments {
+                        println!(
+                            "{}\t{}\t{}",
+                            attachment.name, attachment.size, attachment.hash
+                        );
+                    }
+                }
+                Err(e) => log::error!("Unable to list attachments: {}", e),
+            }
+        }
+        Some(Commands::Links { entity_id }) => {
+            let db_config = get_prod_db_config();
+            let entity_id = Ulid::from_string(entity_id)?;
+            match get_links_from(&entity_id, &db_config) {
+                Ok(links) => {
+                    for link in links {
+                        println!("-> {}\t{}", link.link_type, link.target_id);
+                    }
+                }
+                Err(e) => log::error!("Unable to get links from entity: {}", e),
+            }
+            match get_links_to(&entity_id, &db_config) {
+                Ok(links) => {
+                    for link in links {
+                        println!("<- {}\t{}", link.link_type, link.source_id);
+                    }
+                }
+                Err(e) => log::error!("Unable to get links to entity: {}", e),
+            }
+        }
+        Some(Commands::SyncFromFiles {}) => {
+            let db_config = get_prod_db_config();
+            match sync_from_files(&db_config) {
+                Ok(report) => log::info!(
+                    "Synced from files: {} inserted, {} updated, {} deleted",
+                    report.inserted,
+                    report.updated,
+                    report.deleted
+                ),
+                Err(e) => log::error!("Unable to sync from files: {}", e),
+            }
+        }
+        Some(Commands::WatchFiles { debounce_ms }) => {
+            let db_config = get_prod_db_config();
+            make_sure_db_exists(&db_config)?;
+            watch_files(&db_config, Duration::from_millis(*debounce_ms))?;
+        }
+        Some(Commands::Relayout {}) => {
+            let db_config = get_prod_db_config();
+            match relayout_files(&db_config) {
+                Ok(count) => log::info!(
+                    "Moved {} files to {:?} layout",
+                    count,
+                    db_config.file_layout
+                ),
+                Err(e) => log::error!("Unable to move files: {}", e),
+            }
+        }
+        Some(Commands::GitLog { entity_id }) => {
+            let db_config = get_prod_db_config();
+            let entity_id = Ulid::from_string(entity_id)?;
+            match get_entity_history(&entity_id, &db_config) {
+                Ok(revisions) => {
+                    for revision in revisions {
+                        println!("{}\t{}\t{}", revision.hash, revision.date, revision.subject);
+                    }
+                }
+                Err(e) => log::error!("Unable to get history of entity: {}", e),
+            }
+        }
+        Some(Commands::Watch { since, interval_ms }) => {
+            let db_config = get_prod_db_config();
+            let mut last_seq = match since {
+                Some(seq) => *seq,
+                None => last_change_seq(&db_config)?,
+            };
+            loop {
+                for change in changes_since(last_seq, &db_config)? {
+                    println!("{}", serde_json::to_string(&change)?);
+                    last_seq = change.seq;
+                }
+                thread::sleep(Duration::from_millis(*interval_ms));
+            }
+        }
+        Some(Commands::SweepExpired { interval_secs }) => {
+            let db_config = get_prod_db_config();
+            loop {
+                match sweep_expired(&db_config) {
+                    Ok(count) => log::info!("Deleted {} expired entities", count),
+                    Err(e) => log::error!("Unable to sweep expired entities: {}", e),
+                }
+                match interval_secs {
+                    Some(interval_secs) => thread::sleep(Duration::from_secs(*interval_secs)),
+                    None => break,
+                }
+            }
+        }
+        Some(Commands::Tags { command }) => {
+            let db_config = get_prod_db_config();
+            match command {
+                None | Some(TagsCommands::List {}) => match get_all_tags(&db_config) {
+                    Ok(tag_counts) => {
+                        for tag_count in tag_counts {
+                            println!("{}\t{}", tag_count.tag, tag_count.count);
+                        }
+                    }
+                    Err(e) => log::error!("Unable to list tags: {}", e),
+                },
+                Some(TagsCommands::Find { tags, all }) => {
+                    let tags: Vec<&str> = tags.iter().map(|tag| tag.as_str()).collect();
+                    let tag_match = if *all { TagMatch::All } else { TagMatch::Any };
+                    match get_entries_by_tags(&tags, tag_match, &db_config) {
+                        Ok(entries) => {
+                            for entry in entries {
+                                println!("{}\t{}", entry.id, entry.entity);
+                            }
+                        }
+                        Err(e) => log::error!("Unable to find tagged entities: {}", e),
+                    }
+                }
+                Some(TagsCommands::Rename { old_tag, new_tag }) => {
+                    match rename_tag(old_tag, new_tag, &db_config) {
+                        Ok(count) => log::info!("Tag renamed in {} entities", count),
+                        Err(e) => log::error!("Unable to rename tag: {}", e),
+                    }
                 }
             }
         }
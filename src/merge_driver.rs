@@ -0,0 +1,53 @@
+use clap::Parser;
+use color_eyre::eyre::Result;
+use rust_doc_db::doc_db::merge::merge_yaml_documents;
+use std::{fs, process::Command};
+
+/// Git merge driver for entity YAML files, merging documents field by field
+///
+/// Registered with `git config merge.rust-doc-db.driver "rust_doc_db_merge %O %A %B %L"`,
+/// `.gitattributes` in the YAML files directory assigns it to `*.yaml` files.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct MergeDriverCli {
+    /// Common ancestor version (`%O`)
+    base: String,
+    /// Current version (`%A`), replaced with the merge result
+    ours: String,
+    /// Other branch version (`%B`)
+    theirs: String,
+    /// Conflict marker size (`%L`)
+    #[arg(default_value_t = 7)]
+    marker_size: usize,
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    simple_logger::SimpleLogger::new().env().init()?;
+
+    let cli = MergeDriverCli::parse();
+    let merged = merge_yaml_documents(
+        &fs::read_to_string(&cli.base)?,
+        &fs::read_to_string(&cli.ours)?,
+        &fs::read_to_string(&cli.theirs)?,
+        cli.marker_size,
+    );
+    let clean = match merged {
+        Ok((merged_yaml, clean)) => {
+            fs::write(&cli.ours, merged_yaml)?;
+            clean
+        }
+        Err(e) => {
+            // not a document (e.g. file with conflict markers), falling back to line-based merge
+            log::warn!("Unable to merge {} as documents: {}", cli.ours, e);
+            Command::new("git")
+                .args(["merge-file", "--marker-size"])
+                .arg(cli.marker_size.to_string())
+                .args(["-L", "ours", "-L", "base", "-L", "theirs"])
+                .args([&cli.ours, &cli.base, &cli.theirs])
+                .status()?
+                .success()
+        }
+    };
+    std::process::exit(if clean { 0 } else { 1 });
+}
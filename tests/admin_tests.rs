@@ -1,7 +1,9 @@
 use rust_doc_db::{
     doc_db::{get_entry_from_db, insert_entity_to_db},
     example_domains::{
-        admin::{mark_entity_as_important, model::EntityMeta, unmark_entity_as_important},
+        admin::{
+            mark_entity_as_important, model::EntityMeta, unmark_entity_as_important, IMPORTANT_TAG,
+        },
         pim::fake_data_generator,
     },
 };
@@ -27,11 +29,11 @@ fn admin_can_tag_untag_entities() {
 
     let db_entry_1 = get_entry_from_db(&entity_id, &db_config).unwrap();
     let entity_as_meta_1: EntityMeta = serde_json::from_value(db_entry_1.unwrap().entity).unwrap();
-    assert!(entity_as_meta_1.tags.contains(&"important".to_string()));
+    assert!(entity_as_meta_1.tags.contains(&IMPORTANT_TAG.to_string()));
 
     unmark_entity_as_important(&entity_id, &db_config).unwrap();
 
     let db_entry_2 = get_entry_from_db(&entity_id, &db_config).unwrap();
     let entity_as_meta_2: EntityMeta = serde_json::from_value(db_entry_2.unwrap().entity).unwrap();
-    assert!(!entity_as_meta_2.tags.contains(&"important".to_string()));
+    assert!(!entity_as_meta_2.tags.contains(&IMPORTANT_TAG.to_string()));
 }
@@ -0,0 +1,60 @@
+use std::{fs, path::Path};
+
+use rust_doc_db::doc_db::{
+    attachments::{put_attachment, ATTACHMENTS_DIR_NAME},
+    get_entry_from_db, insert_entity_to_db, make_sure_db_exists,
+    sync::{sync_from_files, SyncReport},
+    update_entity_in_db,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn writes_leave_no_temp_files() {
+    setup_test();
+    let db_config = get_test_config();
+    let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+    update_entity_in_db(&entity_id, &json!({ "title": "My week" }), &db_config).unwrap();
+    let file_names: Vec<String> = fs::read_dir(&db_config.text_db_path)
+        .unwrap()
+        .map(|dir_entry| dir_entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|file_name| !file_name.starts_with(".git"))
+        .collect();
+    assert_eq!(file_names, vec![format!("{}.yaml", entity_id)]);
+}
+
+#[serial]
+#[test]
+fn temp_files_of_interrupted_writes_are_removed() {
+    setup_test();
+    let db_config = get_test_config();
+    let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+    let temp_file_path = format!("{}.{}.yaml.tmp", db_config.text_db_path, entity_id);
+    let other_file_path = format!("{}.notes.tmp", db_config.text_db_path);
+    fs::write(&temp_file_path, "title: My we").unwrap();
+    fs::write(&other_file_path, "notes").unwrap();
+    let blob = put_attachment(&entity_id, "notes.txt", b"notes", &db_config).unwrap();
+    let blob_dir = format!(
+        "{}{}/{}",
+        db_config.text_db_path,
+        ATTACHMENTS_DIR_NAME,
+        &blob.hash[0..2]
+    );
+    let temp_blob_path = format!("{}/.{}.tmp", blob_dir, blob.hash);
+    fs::write(&temp_blob_path, "no").unwrap();
+
+    make_sure_db_exists(&db_config).unwrap();
+    assert!(!Path::new(&temp_file_path).exists());
+    assert!(!Path::new(&temp_blob_path).exists());
+    assert!(Path::new(&format!("{}/{}", blob_dir, blob.hash)).exists());
+    assert!(Path::new(&other_file_path).exists());
+    fs::remove_file(&other_file_path).unwrap();
+    assert_eq!(sync_from_files(&db_config).unwrap(), SyncReport::default());
+    let entry = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
+    assert_eq!(entry.entity, json!({ "title": "My day" }));
+}
@@ -0,0 +1,94 @@
+use std::{fs, path::Path};
+
+use rust_doc_db::doc_db::{
+    attachments::{
+        delete_attachment, get_attachment, list_attachments, put_attachment, ATTACHMENTS_DIR_NAME,
+    },
+    clear_db, delete_entity_from_db, insert_entity_to_db, DbConfig, DocDbError,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+const PHOTO: &[u8] = &[0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10];
+const SCAN: &[u8] = b"%PDF-1.7";
+
+fn blob_count(db_config: &DbConfig) -> usize {
+    let attachments_dir = Path::new(&db_config.text_db_path).join(ATTACHMENTS_DIR_NAME);
+    if !attachments_dir.exists() {
+        return 0;
+    }
+    fs::read_dir(attachments_dir)
+        .unwrap()
+        .map(|dir| fs::read_dir(dir.unwrap().path()).unwrap().count())
+        .sum()
+}
+
+#[serial]
+#[test]
+fn attachments_are_stored_by_content_hash() {
+    setup_test();
+    let db_config = get_test_config();
+    let person_id = insert_entity_to_db(&json!({ "name": "Jan" }), &db_config).unwrap();
+    let other_id = insert_entity_to_db(&json!({ "name": "Anna" }), &db_config).unwrap();
+
+    let photo = put_attachment(&person_id, "photo.jpg", PHOTO, &db_config).unwrap();
+    assert_eq!(photo.size, 6);
+    assert_eq!(photo.hash.len(), 64);
+    put_attachment(&person_id, "id-card.pdf", SCAN, &db_config).unwrap();
+    put_attachment(&other_id, "same-photo.jpg", PHOTO, &db_config).unwrap();
+    assert_eq!(blob_count(&db_config), 2);
+    assert_eq!(
+        get_attachment(&person_id, "photo.jpg", &db_config).unwrap(),
+        Some(PHOTO.to_vec())
+    );
+    assert_eq!(
+        get_attachment(&person_id, "missing.jpg", &db_config).unwrap(),
+        None
+    );
+    let names: Vec<String> = list_attachments(&person_id, &db_config)
+        .unwrap()
+        .into_iter()
+        .map(|attachment| attachment.name)
+        .collect();
+    assert_eq!(names, vec!["id-card.pdf", "photo.jpg"]);
+
+    // replaced content is removed once nothing uses it
+    put_attachment(&person_id, "id-card.pdf", b"%PDF-2.0", &db_config).unwrap();
+    assert_eq!(blob_count(&db_config), 2);
+    assert!(delete_attachment(&person_id, "photo.jpg", &db_config).unwrap());
+    assert!(!delete_attachment(&person_id, "photo.jpg", &db_config).unwrap());
+    assert_eq!(blob_count(&db_config), 2);
+    assert_eq!(
+        get_attachment(&other_id, "same-photo.jpg", &db_config).unwrap(),
+        Some(PHOTO.to_vec())
+    );
+
+    let result = put_attachment(&person_id, "../photo.jpg", PHOTO, &db_config);
+    assert!(matches!(result, Err(DocDbError::Validation { .. })));
+    let result = put_attachment(&ulid::Ulid::new(), "photo.jpg", PHOTO, &db_config);
+    assert!(matches!(result, Err(DocDbError::NotFound { .. })));
+}
+
+#[serial]
+#[test]
+fn attachments_are_removed_with_their_entities() {
+    setup_test();
+    let db_config = get_test_config();
+    let person_id = insert_entity_to_db(&json!({ "name": "Jan" }), &db_config).unwrap();
+    let other_id = insert_entity_to_db(&json!({ "name": "Anna" }), &db_config).unwrap();
+    put_attachment(&person_id, "photo.jpg", PHOTO, &db_config).unwrap();
+    put_attachment(&person_id, "id-card.pdf", SCAN, &db_config).unwrap();
+    put_attachment(&other_id, "photo.jpg", PHOTO, &db_config).unwrap();
+
+    delete_entity_from_db(&person_id, &db_config).unwrap();
+    assert!(list_attachments(&person_id, &db_config).unwrap().is_empty());
+    assert_eq!(blob_count(&db_config), 1);
+
+    clear_db(&db_config).unwrap();
+    assert!(list_attachments(&other_id, &db_config).unwrap().is_empty());
+    assert_eq!(blob_count(&db_config), 0);
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use rust_doc_db::doc_db::{
+    delete_entities_from_db,
+    document::DocField,
+    get_entries_by_query, get_entry_from_db, insert_entities_to_db,
+    links::{get_links_from, link_entities, LinkDeleteRule},
+    model::DocDbEntry,
+    tags::{get_entries_by_tags, TagMatch},
+    update_entities_in_db, DbConfig,
+};
+use serde_json::json;
+use serial_test::serial;
+use std::{fs, path::Path};
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn can_insert_and_update_many_entities() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let ids = insert_entities_to_db(
+        &[
+            json!({ "firstname": "Jan", "tags": ["friend"] }),
+            json!({ "firstname": "Eva" }),
+        ],
+        &db_config,
+    )
+    .unwrap();
+    assert_eq!(ids.len(), 2);
+    let eva = get_entry_from_db(&ids[1], &db_config).unwrap().unwrap();
+    assert_eq!(eva.entity["firstname"], "Eva");
+    assert!(Path::new(&format!("{}{}.yaml", db_config.text_db_path, ids[1])).exists());
+
+    update_entities_in_db(
+        &[
+            DocDbEntry {
+                id: ids[0],
+                entity: json!({ "tags": ["colleague"] }),
+            },
+            DocDbEntry {
+                id: ids[1],
+                entity: json!({ "tags": ["colleague"] }),
+            },
+        ],
+        &db_config,
+    )
+    .unwrap();
+    let jan = get_entry_from_db(&ids[0], &db_config).unwrap().unwrap();
+    assert_eq!(jan.entity["firstname"], "Jan");
+    assert_eq!(
+        get_entries_by_tags(&["colleague"], TagMatch::Any, &db_config)
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(get_entries_by_tags(&["friend"], TagMatch::Any, &db_config)
+        .unwrap()
+        .is_empty());
+    let yaml_entity =
+        fs::read_to_string(format!("{}{}.yaml", db_config.text_db_path, ids[1])).unwrap();
+    assert!(yaml_entity.contains("colleague"));
+}
+
+#[serial]
+#[test]
+fn can_delete_entities_matching_query() {
+    setup_test();
+    let db_config = DbConfig {
+        link_delete_rules: HashMap::from([("works_at".to_string(), LinkDeleteRule::Nullify)]),
+        ..get_test_config()
+    };
+
+    let ids = insert_entities_to_db(
+        &[
+            json!({ "kind": "company", "name": "ACME" }),
+            json!({ "kind": "company", "name": "Initech" }),
+            json!({ "kind": "person", "firstname": "Jan" }),
+        ],
+        &db_config,
+    )
+    .unwrap();
+    link_entities(&ids[2], "works_at", &ids[0], &db_config).unwrap();
+
+    let deleted_count =
+        delete_entities_from_db(&DocField::new("kind").eq("company"), &db_config).unwrap();
+
+    assert_eq!(deleted_count, 2);
+    let remaining = get_entries_by_query(&DocField::new("kind").ne("nothing"), &db_config).unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, ids[2]);
+    assert!(get_links_from(&ids[2], &db_config).unwrap().is_empty());
+    assert!(!Path::new(&format!("{}{}.yaml", db_config.text_db_path, ids[0])).exists());
+}
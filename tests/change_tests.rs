@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+use rust_doc_db::doc_db::{
+    changes::{changes_since, last_change_seq},
+    delete_entity_from_db, insert_entity_to_db,
+    model::{ChangeOperation, DocDbChange},
+    update_entity_in_db, DocDbResult,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn can_read_changes_since_sequence_number() {
+    setup_test();
+    let db_config = get_test_config();
+    let start_seq = last_change_seq(&db_config).unwrap();
+
+    let person_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    update_entity_in_db(&person_id, &json!({ "lastname": "Novak" }), &db_config).unwrap();
+    delete_entity_from_db(&person_id, &db_config).unwrap();
+
+    let changes = changes_since(start_seq, &db_config).unwrap();
+    let operations: Vec<ChangeOperation> = changes.iter().map(|change| change.operation).collect();
+    assert_eq!(
+        operations,
+        vec![
+            ChangeOperation::Insert,
+            ChangeOperation::Update,
+            ChangeOperation::Delete
+        ]
+    );
+    assert!(changes.iter().all(|change| change.entity_id == person_id));
+    assert!(changes.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+    assert_eq!(changes[0].old_entity, None);
+    assert_eq!(
+        changes[1].new_entity,
+        Some(json!({ "firstname": "Jan", "lastname": "Novak" }))
+    );
+    assert_eq!(changes[2].new_entity, None);
+
+    let later_changes = changes_since(changes[1].seq, &db_config).unwrap();
+    assert_eq!(later_changes, vec![changes[2].clone()]);
+}
+
+#[serial]
+#[test]
+fn subscribers_are_notified_about_committed_changes_only() {
+    setup_test();
+    let db_config = get_test_config();
+    let notified_changes: Arc<Mutex<Vec<DocDbChange>>> = Arc::new(Mutex::new(Vec::new()));
+    let subscriber_changes = notified_changes.clone();
+    let subscription_id = db_config.change_subscribers.subscribe(move |change| {
+        subscriber_changes.lock().unwrap().push(change.clone());
+    });
+
+    let person_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    let result: DocDbResult<()> = db_config.transaction(|tx| {
+        tx.update(&person_id, &json!({ "lastname": "Novak" }))?;
+        tx.delete(&ulid::Ulid::new())?;
+        tx.link(&person_id, "knows", &ulid::Ulid::new())
+    });
+    assert!(result.is_err());
+
+    {
+        let notified_changes = notified_changes.lock().unwrap();
+        assert_eq!(notified_changes.len(), 1);
+        assert_eq!(notified_changes[0].operation, ChangeOperation::Insert);
+        assert_eq!(notified_changes[0].entity_id, person_id);
+    }
+
+    db_config.change_subscribers.unsubscribe(subscription_id);
+    delete_entity_from_db(&person_id, &db_config).unwrap();
+    assert_eq!(notified_changes.lock().unwrap().len(), 1);
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use rust_doc_db::{
+    doc_db::{
+        constraints::UniqueConstraint, delete_entity_from_db, get_entries_from_db,
+        get_entry_from_db, insert_entities_to_db, insert_entity_to_db, update_entity_in_db,
+        DocDbError,
+    },
+    example_domains::{pim::add_person, register_unique_constraints, register_write_hooks},
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, person, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn unique_constraint_rejects_conflicting_writes() {
+    setup_test();
+    let db_config = get_test_config();
+    db_config
+        .unique_constraints
+        .declare(UniqueConstraint::new("unique-email", &["email"]).in_collection("users"));
+
+    let jan_id = insert_entity_to_db(
+        &json!({ "_collection": "users", "email": "jan@example.com" }),
+        &db_config,
+    )
+    .unwrap();
+    let eva_id = insert_entity_to_db(
+        &json!({ "_collection": "users", "email": "eva@example.com" }),
+        &db_config,
+    )
+    .unwrap();
+    // documents outside of the collection and without the field are not constrained
+    insert_entity_to_db(&json!({ "email": "jan@example.com" }), &db_config).unwrap();
+    insert_entity_to_db(&json!({ "_collection": "users" }), &db_config).unwrap();
+    insert_entity_to_db(&json!({ "_collection": "users" }), &db_config).unwrap();
+
+    let result = insert_entity_to_db(
+        &json!({ "_collection": "users", "email": "jan@example.com" }),
+        &db_config,
+    );
+    assert!(matches!(
+        result,
+        Err(DocDbError::UniqueConstraint { constraint, conflicting_id, .. })
+            if constraint == "unique-email" && conflicting_id == jan_id.to_string()
+    ));
+
+    let result = update_entity_in_db(&eva_id, &json!({ "email": "jan@example.com" }), &db_config);
+    assert!(matches!(
+        result,
+        Err(DocDbError::UniqueConstraint { entity_id, conflicting_id, .. })
+            if entity_id == eva_id.to_string() && conflicting_id == jan_id.to_string()
+    ));
+    let eva = get_entry_from_db(&eva_id, &db_config).unwrap().unwrap();
+    assert_eq!(eva.entity["email"], "eva@example.com");
+    update_entity_in_db(&jan_id, &json!({ "name": "Jan" }), &db_config).unwrap();
+}
+
+#[serial]
+#[test]
+fn array_elements_are_unique() {
+    setup_test();
+    let db_config = get_test_config();
+    register_write_hooks(&db_config.write_hooks);
+    register_unique_constraints(&db_config.unique_constraints);
+
+    let jan_id = add_person(&person("Jan", "Nowak", &["+48 123", "+48 456"]), &db_config).unwrap();
+    add_person(&person("Eva", "Nowak", &["+48 789"]), &db_config).unwrap();
+
+    // phones are compared after normalization by domain hook
+    let result = add_person(
+        &person("Piotr", "Nowak", &["+48 000", "+48-456"]),
+        &db_config,
+    );
+    assert!(matches!(
+        result,
+        Err(DocDbError::UniqueConstraint { conflicting_id, .. })
+            if conflicting_id == jan_id.to_string()
+    ));
+
+    // conflicts within a batch are detected too and nothing is stored
+    let people = vec![
+        json!({ "_collection": "people", "phones": ["+48111"] }),
+        json!({ "_collection": "people", "phones": ["+48222", "+48111"] }),
+    ];
+    assert!(matches!(
+        insert_entities_to_db(&people, &db_config),
+        Err(DocDbError::UniqueConstraint { .. })
+    ));
+    assert_eq!(
+        get_entries_from_db("1", HashMap::new(), &db_config)
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[serial]
+#[test]
+fn values_are_released_and_built_for_existing_documents() {
+    setup_test();
+    let db_config = get_test_config();
+    let jan_id = insert_entity_to_db(&json!({ "login": "jan" }), &db_config).unwrap();
+
+    // constraint declared later covers documents stored before
+    db_config
+        .unique_constraints
+        .declare(UniqueConstraint::new("unique-login", &["login"]));
+    let result = insert_entity_to_db(&json!({ "login": "jan" }), &db_config);
+    assert!(matches!(
+        result,
+        Err(DocDbError::UniqueConstraint { conflicting_id, .. })
+            if conflicting_id == jan_id.to_string()
+    ));
+
+    // changed and deleted documents release their values
+    update_entity_in_db(&jan_id, &json!({ "login": "jan.nowak" }), &db_config).unwrap();
+    let eva_id = insert_entity_to_db(&json!({ "login": "jan" }), &db_config).unwrap();
+    delete_entity_from_db(&eva_id, &db_config).unwrap();
+    insert_entity_to_db(&json!({ "login": "jan" }), &db_config).unwrap();
+    assert!(insert_entity_to_db(&json!({ "login": "jan.nowak" }), &db_config).is_err());
+}
@@ -1,8 +1,11 @@
 use rust_doc_db::{
-    doc_db::{get_entry_from_db, insert_entity_to_db},
+    doc_db::{
+        get_entry_from_db, get_entry_in_domain, insert_entity_to_db, update_entity_in_domain,
+    },
     example_domains::{
-        admin::{mark_entity_as_important, model::EntityMeta},
-        pim::fake_data_generator,
+        admin::{self, mark_entity_as_important, model::EntityMeta, IMPORTANT_TAG},
+        domain_registry,
+        pim::{self, fake_data_generator},
     },
 };
 use serde_json::{json, Value};
@@ -26,14 +29,44 @@ fn top_level_fields_are_preserved_across_domains() {
     mark_entity_as_important(&entity_id, &db_config).unwrap();
 
     let db_entry = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
-    let tags_from_db = db_entry
+    let tags_from_db: Vec<&Value> = db_entry
         .entity
         .get("tags")
         .and_then(|v| v.as_array())
         .map(|arr| arr.iter().collect())
-        .unwrap_or_else(Vec::new);
-    assert!(tags_from_db.contains(&&Value::String("important".to_string())));
+        .unwrap_or_default();
+    assert!(tags_from_db.contains(&&Value::String(IMPORTANT_TAG.to_string())));
 
     let entity_as_meta: EntityMeta = serde_json::from_value(db_entry.entity).unwrap();
-    assert!(entity_as_meta.tags.contains(&"important".to_string()));
+    assert!(entity_as_meta.tags.contains(&IMPORTANT_TAG.to_string()));
+}
+
+#[serial]
+#[test]
+fn domains_update_and_read_only_their_fields() {
+    setup_test();
+    let db_config = get_test_config();
+    domain_registry().unwrap();
+
+    let mut person = fake_data_generator::generate_people(1).pop().unwrap();
+    let entity_id = pim::add_person(&person, &db_config).unwrap();
+    mark_entity_as_important(&entity_id, &db_config).unwrap();
+
+    person.firstname = "Mark".to_string();
+    pim::update_person(&entity_id, &person, &db_config).unwrap();
+
+    let admin_entry = get_entry_in_domain(&admin::domain_view(), &entity_id, &db_config)
+        .unwrap()
+        .unwrap();
+    assert_eq!(admin_entry.entity, json!({ "tags": [IMPORTANT_TAG] }));
+
+    let mut json_person = json!(person);
+    json_person["tags"] = json!([]);
+    let forbidden_update =
+        update_entity_in_domain(&pim::domain_view(), &entity_id, &json_person, &db_config);
+    assert!(forbidden_update.is_err());
+
+    let db_entry = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
+    assert_eq!(db_entry.entity["firstname"], "Mark");
+    assert_eq!(db_entry.entity["tags"], json!([IMPORTANT_TAG]));
 }
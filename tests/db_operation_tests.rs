@@ -1,10 +1,10 @@
 use rust_doc_db::doc_db::{
-    delete_entity_from_db, get_entry_from_db, insert_entity_to_db, set_entity_field_value,
-    tag_entity,
+    delete_entity_from_db, get_entries_from_db, get_entry_from_db, insert_entity_to_db,
+    set_entity_field_value, tag_entity, update_entity_in_db, DocDbError,
 };
 use serde_json::{json, Value};
 use serial_test::serial;
-use std::fs;
+use std::{collections::HashMap, fs};
 
 use crate::test_helpers::{get_test_config, setup_test};
 
@@ -76,12 +76,12 @@ fn can_apply_tags_to_existing_entities() {
     tag_entity(&entity_id, "known", &db_config).unwrap();
 
     let entry_from_db = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
-    let tags_from_db = entry_from_db
+    let tags_from_db: Vec<&Value> = entry_from_db
         .entity
         .get("tags")
         .and_then(|v| v.as_array())
         .map(|arr| arr.iter().collect())
-        .unwrap_or_else(Vec::new);
+        .unwrap_or_default();
     assert!(tags_from_db.contains(&&Value::String("known".to_string())));
 }
 
@@ -104,15 +104,35 @@ fn can_delete_entity() {
     );
 
     delete_entity_from_db(&entity_id, &db_config).unwrap();
-    let entry_from_db_after_deletion_result = get_entry_from_db(&entity_id, &db_config);
-    match entry_from_db_after_deletion_result {
-        Err(err) => {
-            assert!(err.to_string().contains("Entity") && err.to_string().contains("not found"))
-        }
-        Ok(entry_option) => {
-            if entry_option.is_some() {
-                panic!("Should not be able to get deleted entity")
-            }
-        }
-    }
+    assert!(get_entry_from_db(&entity_id, &db_config).unwrap().is_none());
+    assert!(matches!(
+        delete_entity_from_db(&entity_id, &db_config),
+        Err(DocDbError::NotFound { id }) if id == entity_id.to_string()
+    ));
+}
+
+#[serial]
+#[test]
+fn errors_tell_what_went_wrong() {
+    setup_test();
+    let db_config = get_test_config();
+    let missing_id = ulid::Ulid::new();
+
+    assert!(matches!(
+        update_entity_in_db(&missing_id, &json!({ "title": "My day" }), &db_config),
+        Err(DocDbError::NotFound { .. })
+    ));
+    assert!(matches!(
+        tag_entity(&missing_id, "known", &db_config),
+        Err(DocDbError::NotFound { .. })
+    ));
+    assert!(matches!(
+        insert_entity_to_db(&json!(["not", "an", "object"]), &db_config)
+            .and_then(|entity_id| tag_entity(&entity_id, "known", &db_config)),
+        Err(DocDbError::Validation { .. })
+    ));
+    assert!(matches!(
+        get_entries_from_db("title = = 'My day'", HashMap::new(), &db_config),
+        Err(DocDbError::InvalidQuery { .. })
+    ));
 }
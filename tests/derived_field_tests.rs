@@ -0,0 +1,90 @@
+use rust_doc_db::{
+    doc_db::{
+        create_derived_field_indexes, derived::DerivedField, document::COLLECTION_FIELD_NAME,
+        domain::DomainView, get_entries_by_query, get_entry_from_db, insert_entity_in_domain,
+        insert_entity_to_db, refresh_derived_fields, DocDbError,
+    },
+    example_domains::{
+        pim::{add_person, update_person, FULLNAME},
+        register_derived_fields,
+    },
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, person, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn derived_fields_are_recomputed_on_write() {
+    setup_test();
+    let db_config = get_test_config();
+    register_derived_fields(&db_config.derived_fields);
+    create_derived_field_indexes(&db_config).unwrap();
+
+    let person_id = add_person(&person("Piotr", "Nowak", &["+48 123"]), &db_config).unwrap();
+    let stored_person = get_entry_from_db(&person_id, &db_config).unwrap().unwrap();
+    assert_eq!(stored_person.entity[COLLECTION_FIELD_NAME], "people");
+    assert_eq!(stored_person.entity["fullname"], "Piotr Nowak");
+    assert_eq!(stored_person.entity["phone_count"], 1);
+
+    update_person(
+        &person_id,
+        &person("Piotr", "Kowalski", &["+48 123", "+48 456"]),
+        &db_config,
+    )
+    .unwrap();
+    let people = get_entries_by_query(&FULLNAME.eq("Piotr Kowalski"), &db_config).unwrap();
+    assert_eq!(people.len(), 1);
+    assert_eq!(people[0].id, person_id);
+    assert_eq!(people[0].entity["phone_count"], 2);
+    assert!(
+        get_entries_by_query(&FULLNAME.eq("Piotr Nowak"), &db_config)
+            .unwrap()
+            .is_empty()
+    );
+
+    // documents outside of the collection are left untouched
+    let note_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    let note = get_entry_from_db(&note_id, &db_config).unwrap().unwrap();
+    assert_eq!(note.entity, json!({ "firstname": "Jan" }));
+}
+
+#[serial]
+#[test]
+fn derived_fields_can_be_refreshed_for_existing_documents() {
+    setup_test();
+    let db_config = get_test_config();
+    let person_id = add_person(&person("Eva", "Nowak", &[]), &db_config).unwrap();
+
+    register_derived_fields(&db_config.derived_fields);
+    assert_eq!(refresh_derived_fields(&db_config).unwrap(), 1);
+
+    let stored_person = get_entry_from_db(&person_id, &db_config).unwrap().unwrap();
+    assert_eq!(stored_person.entity["fullname"], "Eva Nowak");
+    assert_eq!(stored_person.entity["phone_count"], 0);
+}
+
+#[serial]
+#[test]
+fn domains_cannot_own_derived_fields() {
+    setup_test();
+    let db_config = get_test_config();
+    db_config.derived_fields.define(
+        "notes",
+        DerivedField::computed("length", |note| {
+            json!(note["text"].as_str().map_or(0, str::len))
+        }),
+    );
+
+    let domain = DomainView::new("notes")
+        .in_collection("notes")
+        .owning(&["text", "length"]);
+    let result = insert_entity_in_domain(&domain, &json!({ "text": "hello" }), &db_config);
+    assert!(matches!(
+        result,
+        Err(DocDbError::DomainViolation { field_path, .. }) if field_path == "length"
+    ));
+}
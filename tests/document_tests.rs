@@ -0,0 +1,70 @@
+use rust_doc_db::doc_db::{
+    document::{DocDbDocument, DocDbSchema},
+    get_entries_by_query, insert_entity_to_db,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[derive(Debug, Serialize, Deserialize, DocDbDocument)]
+#[doc_db(collection = "devices")]
+struct Device {
+    #[doc_db(indexed)]
+    r#type: String,
+    #[serde(rename = "serialNumber", default)]
+    #[doc_db(indexed)]
+    serial_number: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port_count: Option<u32>,
+    online: bool,
+}
+
+#[test]
+fn fields_are_named_as_serialized() {
+    assert_eq!(Device::TYPE.name(), "type");
+    assert_eq!(Device::SERIAL_NUMBER.name(), "serialNumber");
+    assert_eq!(Device::INDEXED_FIELDS, &["type", "serialNumber"]);
+
+    let schema = Device::json_schema();
+    assert_eq!(schema["properties"]["serialNumber"]["type"], "string");
+    assert!(schema["properties"].get("serial_number").is_none());
+}
+
+#[serial]
+#[test]
+fn fields_are_queried_by_typed_values() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let device = Device {
+        r#type: "switch".to_string(),
+        serial_number: "5".to_string(),
+        port_count: Some(5),
+        online: true,
+    };
+    let device_id = insert_entity_to_db(&json!(device), &db_config).unwrap();
+    insert_entity_to_db(
+        &json!({ "type": "router", "serialNumber": "7", "online": false }),
+        &db_config,
+    )
+    .unwrap();
+
+    let found_ids = |query| -> Vec<_> {
+        get_entries_by_query(&query, &db_config)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.id)
+            .collect()
+    };
+    assert_eq!(found_ids(Device::TYPE.eq("switch")), vec![device_id]);
+    assert_eq!(found_ids(Device::PORT_COUNT.eq(5)), vec![device_id]);
+    assert!(found_ids(Device::PORT_COUNT.eq("5")).is_empty());
+    assert_eq!(found_ids(Device::SERIAL_NUMBER.eq("5")), vec![device_id]);
+    assert!(found_ids(Device::SERIAL_NUMBER.eq(5)).is_empty());
+    assert_eq!(found_ids(Device::ONLINE.eq(true)), vec![device_id]);
+    assert_eq!(found_ids(Device::ONLINE.ne(true)).len(), 1);
+}
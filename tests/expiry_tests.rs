@@ -0,0 +1,117 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use rust_doc_db::doc_db::{
+    document::DocField,
+    expiry::{set_entity_expiry, sweep_expired, EXPIRES_AT_FIELD_NAME},
+    get_entries_by_query, get_entry_from_db, insert_entity_to_db,
+    links::link_entities,
+    traversal::{traverse, Traversal, TraversalEdge},
+    update_entity_in_db,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn expired_entities_are_invisible() {
+    setup_test();
+    let db_config = get_test_config();
+    const NAME: DocField = DocField::new("name");
+
+    let company_id = insert_entity_to_db(&json!({ "name": "ACME" }), &db_config).unwrap();
+    let expired_id = insert_entity_to_db(
+        &json!({ "name": "ACME", EXPIRES_AT_FIELD_NAME: "2000-01-01T00:00:00Z" }),
+        &db_config,
+    )
+    .unwrap();
+    let valid_id = insert_entity_to_db(
+        &json!({ "name": "ACME", EXPIRES_AT_FIELD_NAME: "2999-01-01T00:00:00Z" }),
+        &db_config,
+    )
+    .unwrap();
+    link_entities(&company_id, "owns", &valid_id, &db_config).unwrap();
+
+    assert!(get_entry_from_db(&expired_id, &db_config)
+        .unwrap()
+        .is_none());
+    assert!(get_entry_from_db(&valid_id, &db_config).unwrap().is_some());
+    let found_ids: Vec<_> = get_entries_by_query(&NAME.eq("ACME"), &db_config)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.id)
+        .collect();
+    assert_eq!(found_ids, vec![company_id, valid_id]);
+    let traversal = Traversal::new(vec![TraversalEdge::Link("owns".to_string())], 1).min_depth(1);
+    assert_eq!(
+        traverse(&company_id, &traversal, &db_config).unwrap().len(),
+        1
+    );
+
+    set_entity_expiry(
+        &valid_id,
+        Some(SystemTime::now() - Duration::from_secs(1)),
+        &db_config,
+    )
+    .unwrap();
+    assert!(get_entry_from_db(&valid_id, &db_config).unwrap().is_none());
+    assert!(traverse(&company_id, &traversal, &db_config)
+        .unwrap()
+        .is_empty());
+}
+
+#[serial]
+#[test]
+fn updating_expired_entity_keeps_its_fields_and_expiry() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let entity_id = insert_entity_to_db(
+        &json!({ "name": "ACME", EXPIRES_AT_FIELD_NAME: "2000-01-01T00:00:00Z" }),
+        &db_config,
+    )
+    .unwrap();
+
+    update_entity_in_db(&entity_id, &json!({ "city": "Warsaw" }), &db_config).unwrap();
+    assert!(get_entry_from_db(&entity_id, &db_config).unwrap().is_none());
+
+    update_entity_in_db(
+        &entity_id,
+        &json!({ EXPIRES_AT_FIELD_NAME: "2999-01-01T00:00:00Z" }),
+        &db_config,
+    )
+    .unwrap();
+    let entry = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
+    assert_eq!(entry.entity["name"], "ACME");
+    assert_eq!(entry.entity["city"], "Warsaw");
+}
+
+#[serial]
+#[test]
+fn sweep_deletes_expired_entities_and_files() {
+    setup_test();
+    let db_config = get_test_config();
+    let expired_id = insert_entity_to_db(&json!({ "name": "old" }), &db_config).unwrap();
+    let kept_id = insert_entity_to_db(&json!({ "name": "new" }), &db_config).unwrap();
+    set_entity_expiry(&expired_id, Some(SystemTime::now()), &db_config).unwrap();
+    set_entity_expiry(
+        &kept_id,
+        Some(SystemTime::now() + Duration::from_secs(60)),
+        &db_config,
+    )
+    .unwrap();
+    set_entity_expiry(&kept_id, None, &db_config).unwrap();
+
+    assert_eq!(sweep_expired(&db_config).unwrap(), 1);
+    assert!(!Path::new(&format!("{}{}.yaml", db_config.text_db_path, expired_id)).exists());
+    assert!(Path::new(&format!("{}{}.yaml", db_config.text_db_path, kept_id)).exists());
+    let kept_entity = get_entry_from_db(&kept_id, &db_config).unwrap().unwrap();
+    assert_eq!(kept_entity.entity, json!({ "name": "new" }));
+    assert_eq!(sweep_expired(&db_config).unwrap(), 0);
+}
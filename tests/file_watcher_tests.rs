@@ -0,0 +1,106 @@
+use std::{fs, time::Duration};
+
+use rust_doc_db::doc_db::{
+    file_watcher::FileWatcher, get_entry_from_db, insert_entity_to_db, sync::SyncReport,
+    update_entity_in_db, DocDbError,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+#[serial]
+#[test]
+fn watcher_imports_hand_edited_files_only() {
+    setup_test();
+    let db_config = get_test_config();
+    let changed_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+    let removed_id = insert_entity_to_db(&json!({ "title": "My week" }), &db_config).unwrap();
+    let mut watcher = FileWatcher::new(&db_config).unwrap();
+
+    let added_id = ulid::Ulid::new();
+    let file_path =
+        |entity_id: &ulid::Ulid| format!("{}{}.yaml", db_config.text_db_path, entity_id);
+    fs::write(file_path(&changed_id), "title: My whole day\n").unwrap();
+    fs::remove_file(file_path(&removed_id)).unwrap();
+    fs::write(file_path(&added_id), "title: My month\n").unwrap();
+    // written by the DB, so not imported back
+    let inserted_id = insert_entity_to_db(&json!({ "title": "My year" }), &db_config).unwrap();
+
+    let (report, failures) = watcher.sync_next_changes(DEBOUNCE).unwrap();
+    assert!(failures.is_empty());
+    assert_eq!(
+        report,
+        SyncReport {
+            inserted: 1,
+            updated: 1,
+            deleted: 1
+        }
+    );
+    let changed = get_entry_from_db(&changed_id, &db_config).unwrap().unwrap();
+    assert_eq!(changed.entity, json!({ "title": "My whole day" }));
+    let added = get_entry_from_db(&added_id, &db_config).unwrap().unwrap();
+    assert_eq!(added.entity, json!({ "title": "My month" }));
+    assert!(get_entry_from_db(&removed_id, &db_config)
+        .unwrap()
+        .is_none());
+    assert!(get_entry_from_db(&inserted_id, &db_config)
+        .unwrap()
+        .is_some());
+
+    // imported files are not rewritten, DB writes are still not imported
+    update_entity_in_db(&added_id, &json!({ "title": "My quarter" }), &db_config).unwrap();
+    let (report, failures) = watcher.sync_next_changes(DEBOUNCE).unwrap();
+    assert!(failures.is_empty());
+    assert_eq!(report, SyncReport::default());
+}
+
+#[serial]
+#[test]
+fn watcher_reports_unparseable_files() {
+    setup_test();
+    let db_config = get_test_config();
+    let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+    let mut watcher = FileWatcher::new(&db_config).unwrap();
+
+    let added_id = ulid::Ulid::new();
+    fs::write(
+        format!("{}{}.yaml", db_config.text_db_path, entity_id),
+        "title: [My day\n",
+    )
+    .unwrap();
+    fs::write(
+        format!("{}{}.yaml", db_config.text_db_path, added_id),
+        "title: My month\n",
+    )
+    .unwrap();
+
+    let (report, failures) = watcher.sync_next_changes(DEBOUNCE).unwrap();
+    assert_eq!(report.inserted, 1);
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, entity_id);
+    assert!(matches!(failures[0].1, DocDbError::Corruption { .. }));
+    let entry = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
+    assert_eq!(entry.entity, json!({ "title": "My day" }));
+}
+
+#[serial]
+#[test]
+fn writes_wait_for_lock_held_by_another_connection() {
+    setup_test();
+    let db_config = get_test_config();
+    insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+
+    let locking_connection = sqlite::open(&db_config.sqlite_db_full_filename).unwrap();
+    locking_connection.execute("BEGIN IMMEDIATE").unwrap();
+    let lock_holder = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        locking_connection.execute("COMMIT").unwrap();
+    });
+    insert_entity_to_db(&json!({ "title": "My week" }), &db_config).unwrap();
+    lock_holder.join().unwrap();
+}
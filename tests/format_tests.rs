@@ -0,0 +1,131 @@
+use std::{fs, path::Path};
+
+use rust_doc_db::doc_db::{
+    delete_entity_from_db,
+    formats::{JsonFormat, MarkdownFormat, TextFormat, TomlFormat, YamlFormat},
+    get_entry_from_db, insert_entity_to_db,
+    layout::relayout_files,
+    sync::{sync_from_files, SyncReport},
+    update_entity_in_db, DbConfig,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+fn file_path(db_config: &DbConfig, entity_id: &ulid::Ulid, extension: &str) -> String {
+    format!("{}{}.{}", db_config.text_db_path, entity_id, extension)
+}
+
+#[test]
+fn formats_read_what_they_write() {
+    let entity = json!({
+        "title": "My day",
+        "rating": 5,
+        "tags": ["sunny", "lazy"],
+        "place": { "city": "Warsaw" },
+        "description": "It was *sunny*.\n\nAnd lazy.\n"
+    });
+    let formats: Vec<Box<dyn TextFormat>> = vec![
+        Box::new(YamlFormat::default()),
+        Box::new(JsonFormat),
+        Box::new(TomlFormat),
+        Box::new(MarkdownFormat::new("description")),
+    ];
+    for format in formats {
+        let text = format.serialize(&entity).unwrap();
+        assert_eq!(
+            format.deserialize(&text).unwrap(),
+            entity,
+            "{} format",
+            format.extension()
+        );
+    }
+
+    let markdown = MarkdownFormat::new("description")
+        .serialize(&json!({ "title": "My day", "description": "It was *sunny*.\n" }))
+        .unwrap();
+    assert_eq!(markdown, "---\ntitle: My day\n---\n\nIt was *sunny*.\n");
+    let toml = TomlFormat
+        .serialize(&json!({ "title": "My day", "place": null }))
+        .unwrap();
+    assert_eq!(toml, "title = \"My day\"\n");
+}
+
+#[serial]
+#[test]
+fn entities_are_stored_in_configured_formats() {
+    setup_test();
+    let db_config = get_test_config();
+    db_config.file_formats.set_default(JsonFormat);
+    db_config
+        .file_formats
+        .set_for_collection("diary", MarkdownFormat::new("description"));
+    let note_id = insert_entity_to_db(&json!({ "title": "Notes" }), &db_config).unwrap();
+    let entry_id = insert_entity_to_db(
+        &json!({ "_collection": "diary", "title": "My day", "description": "Sunny.\n" }),
+        &db_config,
+    )
+    .unwrap();
+    assert!(Path::new(&file_path(&db_config, &note_id, "json")).exists());
+    assert_eq!(
+        fs::read_to_string(file_path(&db_config, &entry_id, "md")).unwrap(),
+        "---\n_collection: diary\ntitle: My day\n---\n\nSunny.\n"
+    );
+
+    fs::write(
+        file_path(&db_config, &entry_id, "md"),
+        "---\n_collection: diary\ntitle: My day\n---\n\nRainy.\n",
+    )
+    .unwrap();
+    let toml_id = ulid::Ulid::new();
+    fs::write(
+        file_path(&db_config, &toml_id, "toml"),
+        "title = \"Todo\"\n",
+    )
+    .unwrap();
+    assert_eq!(
+        sync_from_files(&db_config).unwrap(),
+        SyncReport {
+            inserted: 1,
+            updated: 1,
+            deleted: 0
+        }
+    );
+    let entry = get_entry_from_db(&entry_id, &db_config).unwrap().unwrap();
+    assert_eq!(entry.entity["description"], json!("Rainy.\n"));
+
+    // written again in the configured format
+    update_entity_in_db(&toml_id, &json!({ "title": "Done" }), &db_config).unwrap();
+    assert!(Path::new(&file_path(&db_config, &toml_id, "json")).exists());
+    assert!(!Path::new(&file_path(&db_config, &toml_id, "toml")).exists());
+    delete_entity_from_db(&entry_id, &db_config).unwrap();
+    assert!(!Path::new(&file_path(&db_config, &entry_id, "md")).exists());
+}
+
+#[serial]
+#[test]
+fn relayout_rewrites_files_in_configured_format() {
+    setup_test();
+    let yaml_config = get_test_config();
+    let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &yaml_config).unwrap();
+
+    let toml_config = get_test_config();
+    toml_config.file_formats.set_default(TomlFormat);
+    assert_eq!(relayout_files(&toml_config).unwrap(), 1);
+    assert!(!Path::new(&file_path(&toml_config, &entity_id, "yaml")).exists());
+    assert_eq!(
+        fs::read_to_string(file_path(&toml_config, &entity_id, "toml")).unwrap(),
+        "title = \"My day\"\n"
+    );
+    assert_eq!(
+        sync_from_files(&toml_config).unwrap(),
+        SyncReport::default()
+    );
+    let entry = get_entry_from_db(&entity_id, &toml_config)
+        .unwrap()
+        .unwrap();
+    assert_eq!(entry.entity, json!({ "title": "My day" }));
+}
@@ -0,0 +1,96 @@
+use std::fs;
+
+use rust_doc_db::doc_db::{
+    attachments::{delete_attachment, put_attachment},
+    clear_db,
+    git::{get_entity_history, GitSettings},
+    insert_entity_to_db,
+    layout::relayout_files,
+    update_entity_in_db, DbConfig,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_git_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn every_transaction_is_committed_to_git() {
+    let db_config = setup_git_test();
+    assert!(fs::metadata(format!("{}.git", db_config.text_db_path)).is_ok());
+
+    let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+    update_entity_in_db(&entity_id, &json!({ "title": "My week" }), &db_config).unwrap();
+    let (first_id, second_id) = db_config
+        .transaction(|tx| {
+            tx.delete(&entity_id)?;
+            Ok((
+                tx.insert(&json!({ "title": "Monday" }))?,
+                tx.insert(&json!({ "title": "Tuesday" }))?,
+            ))
+        })
+        .unwrap();
+
+    let subjects: Vec<String> = get_entity_history(&entity_id, &db_config)
+        .unwrap()
+        .into_iter()
+        .map(|revision| revision.subject)
+        .collect();
+    assert_eq!(subjects.len(), 3);
+    assert!(subjects[0].contains(&format!("delete {}", entity_id)));
+    assert!(subjects[0].contains(&format!("insert {}", first_id)));
+    assert_eq!(subjects[1], format!("update {}", entity_id));
+    assert_eq!(subjects[2], format!("insert {}", entity_id));
+
+    let first_history = get_entity_history(&first_id, &db_config).unwrap();
+    let second_history = get_entity_history(&second_id, &db_config).unwrap();
+    assert_eq!(first_history.len(), 1);
+    assert_eq!(first_history, second_history);
+
+    clear_db(&db_config).unwrap();
+    let first_history = get_entity_history(&first_id, &db_config).unwrap();
+    assert_eq!(first_history[0].subject, "clear DB");
+}
+
+#[serial]
+#[test]
+fn failed_transaction_is_not_committed_to_git() {
+    let db_config = setup_git_test();
+    let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+
+    let result = db_config.transaction(|tx| {
+        tx.update(&entity_id, &json!({ "title": "My week" }))?;
+        tx.delete(&ulid::Ulid::new())
+    });
+    assert!(result.is_err());
+    assert_eq!(get_entity_history(&entity_id, &db_config).unwrap().len(), 1);
+}
+
+#[serial]
+#[test]
+fn git_failure_does_not_fail_committed_write() {
+    let db_config = setup_git_test();
+    // git refuses to commit with an empty author name
+    let failing_config = DbConfig {
+        git: Some(GitSettings {
+            author_name: String::new(),
+            author_email: String::new(),
+        }),
+        ..get_test_config()
+    };
+    let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+    update_entity_in_db(&entity_id, &json!({ "title": "My week" }), &failing_config).unwrap();
+    assert_eq!(get_entity_history(&entity_id, &db_config).unwrap().len(), 1);
+
+    // file left uncommitted is committed with the next change
+    update_entity_in_db(&entity_id, &json!({ "rating": 5 }), &db_config).unwrap();
+    let history = get_entity_history(&entity_id, &db_config).unwrap();
+    assert_eq!(history.len(), 2);
+
+    put_attachment(&entity_id, "notes.txt", b"notes", &failing_config).unwrap();
+    assert!(delete_attachment(&entity_id, "notes.txt", &failing_config).unwrap());
+    relayout_files(&failing_config).unwrap();
+    clear_db(&failing_config).unwrap();
+}
@@ -0,0 +1,153 @@
+use std::sync::{Arc, Mutex};
+
+use rust_doc_db::{
+    doc_db::{
+        delete_entity_from_db, get_entry_from_db, insert_entity_to_db, model::ChangeOperation,
+        tags::TagVocabulary, DbConfig, DocDbError,
+    },
+    example_domains::{
+        admin::{self, mark_entity_as_important, IMPORTANT_TAG, UNREVIEWED_TAG},
+        pim::{add_person, model::Person},
+        register_write_hooks,
+    },
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn domain_hooks_change_written_entities() {
+    setup_test();
+    let db_config = get_test_config();
+    register_write_hooks(&db_config.write_hooks);
+
+    let person = Person {
+        firstname: "Piotr".to_string(),
+        lastname: "Nowak".to_string(),
+        phones: vec!["+48 123-456 789".to_string()],
+        addresses: Vec::new(),
+    };
+    let person_id = add_person(&person, &db_config).unwrap();
+
+    let stored_person = get_entry_from_db(&person_id, &db_config).unwrap().unwrap();
+    assert_eq!(stored_person.entity["phones"], json!(["+48123456789"]));
+    assert_eq!(stored_person.entity["tags"], json!([UNREVIEWED_TAG]));
+}
+
+#[serial]
+#[test]
+fn rejecting_hook_rolls_back_write() {
+    setup_test();
+    let db_config = get_test_config();
+    register_write_hooks(&db_config.write_hooks);
+
+    let entity_id = insert_entity_to_db(
+        &json!({ "_collection": "people", "lastname": "Nowak" }),
+        &db_config,
+    )
+    .unwrap();
+    mark_entity_as_important(&entity_id, &db_config).unwrap();
+
+    let result = delete_entity_from_db(&entity_id, &db_config);
+    assert!(matches!(
+        result,
+        Err(DocDbError::WriteHook { hook, .. }) if hook == "admin/protect-important"
+    ));
+    let entity = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
+    assert_eq!(
+        entity.entity["tags"],
+        json!([UNREVIEWED_TAG, IMPORTANT_TAG])
+    );
+
+    let invalid_phones = insert_entity_to_db(
+        &json!({ "_collection": "people", "phones": ["call me"] }),
+        &db_config,
+    );
+    assert!(matches!(invalid_phones, Err(DocDbError::WriteHook { .. })));
+}
+
+#[serial]
+#[test]
+fn domain_hooks_leave_other_collections_alone() {
+    setup_test();
+    let db_config = get_test_config();
+    register_write_hooks(&db_config.write_hooks);
+
+    let note_id = insert_entity_to_db(&json!({ "phones": ["call me"] }), &db_config).unwrap();
+    mark_entity_as_important(&note_id, &db_config).unwrap();
+    let note = get_entry_from_db(&note_id, &db_config).unwrap().unwrap();
+    assert_eq!(
+        note.entity,
+        json!({ "phones": ["call me"], "tags": [IMPORTANT_TAG] })
+    );
+    delete_entity_from_db(&note_id, &db_config).unwrap();
+
+    // stamped tag has to be allowed by the tag vocabulary
+    let strict_config = DbConfig {
+        tag_vocabulary: Some(TagVocabulary {
+            allow_plain_tags: false,
+            ..Default::default()
+        }),
+        ..get_test_config()
+    };
+    register_write_hooks(&strict_config.write_hooks);
+    let result = insert_entity_to_db(
+        &json!({ "_collection": "people", "lastname": "Nowak" }),
+        &strict_config,
+    );
+    assert!(matches!(
+        result,
+        Err(DocDbError::WriteHook { hook, .. }) if hook == "admin/stamp-unreviewed"
+    ));
+    let mut tag_vocabulary = TagVocabulary {
+        allow_plain_tags: false,
+        ..Default::default()
+    };
+    admin::allow_tags(&mut tag_vocabulary);
+    let admin_config = DbConfig {
+        tag_vocabulary: Some(tag_vocabulary),
+        ..get_test_config()
+    };
+    register_write_hooks(&admin_config.write_hooks);
+    let person_id = insert_entity_to_db(
+        &json!({ "_collection": "people", "lastname": "Nowak" }),
+        &admin_config,
+    )
+    .unwrap();
+    mark_entity_as_important(&person_id, &admin_config).unwrap();
+}
+
+#[serial]
+#[test]
+fn post_commit_hooks_see_committed_changes() {
+    setup_test();
+    let db_config = get_test_config();
+    let committed_operations: Arc<Mutex<Vec<ChangeOperation>>> = Arc::new(Mutex::new(Vec::new()));
+    let hook_operations = committed_operations.clone();
+    db_config
+        .write_hooks
+        .after_commit("test/record", move |change| {
+            hook_operations.lock().unwrap().push(change.operation);
+            Ok(())
+        });
+    db_config
+        .write_hooks
+        .after_commit("test/fail", |change| match change.operation {
+            ChangeOperation::Delete => Err("deletes are not synced".to_string()),
+            _ => Ok(()),
+        });
+
+    let entity_id = insert_entity_to_db(&json!({ "name": "ACME" }), &db_config).unwrap();
+    // committed write succeeds although post-commit hook failed
+    delete_entity_from_db(&entity_id, &db_config).unwrap();
+
+    assert!(get_entry_from_db(&entity_id, &db_config).unwrap().is_none());
+    assert_eq!(
+        *committed_operations.lock().unwrap(),
+        vec![ChangeOperation::Insert, ChangeOperation::Delete]
+    );
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use rust_doc_db::doc_db::{
+    clear_db, delete_entity_from_db, get_entry_from_db, insert_entity_to_db,
+    layout::{relayout_files, FileLayout},
+    sync::{sync_from_files, SyncReport},
+    update_entity_in_db, DbConfig,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+fn get_layout_config(file_layout: FileLayout) -> DbConfig {
+    DbConfig {
+        file_layout,
+        ..get_test_config()
+    }
+}
+
+fn file_path(db_config: &DbConfig, dir: &str, entity_id: &ulid::Ulid) -> String {
+    format!("{}{}{}.yaml", db_config.text_db_path, dir, entity_id)
+}
+
+#[serial]
+#[test]
+fn files_are_stored_in_layout_directories() {
+    setup_test();
+    for file_layout in [FileLayout::HashPrefix, FileLayout::UlidMonth] {
+        let db_config = get_layout_config(file_layout);
+        let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+        let dir = file_layout.entity_dir(&entity_id, &json!(null));
+        assert_eq!(dir.components().count(), 2);
+        let filename = Path::new(&db_config.text_db_path)
+            .join(&dir)
+            .join(format!("{}.yaml", entity_id));
+        assert!(filename.exists());
+
+        update_entity_in_db(&entity_id, &json!({ "title": "My week" }), &db_config).unwrap();
+        assert_eq!(sync_from_files(&db_config).unwrap(), SyncReport::default());
+        delete_entity_from_db(&entity_id, &db_config).unwrap();
+        assert!(!filename.exists());
+    }
+    let date = rust_doc_db::doc_db::expiry::format_rfc3339(std::time::SystemTime::now());
+    let entity_id = ulid::Ulid::new();
+    assert_eq!(
+        FileLayout::UlidMonth.entity_dir(&entity_id, &json!(null)),
+        Path::new(&date[0..4]).join(&date[5..7])
+    );
+
+    let db_config = get_layout_config(FileLayout::Collection);
+    let entity_id = insert_entity_to_db(
+        &json!({ "title": "Piotr", "_collection": "people" }),
+        &db_config,
+    )
+    .unwrap();
+    let other_id = insert_entity_to_db(&json!({ "title": "Notes" }), &db_config).unwrap();
+    assert!(Path::new(&file_path(&db_config, "people/", &entity_id)).exists());
+    assert!(Path::new(&file_path(&db_config, "", &other_id)).exists());
+
+    update_entity_in_db(
+        &entity_id,
+        &json!({ "title": "Acme", "_collection": "companies" }),
+        &db_config,
+    )
+    .unwrap();
+    assert!(Path::new(&file_path(&db_config, "companies/", &entity_id)).exists());
+    assert!(!Path::new(&file_path(&db_config, "people/", &entity_id)).exists());
+    let entry = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
+    assert_eq!(entry.entity["title"], json!("Acme"));
+
+    clear_db(&db_config).unwrap();
+    assert!(!Path::new(&format!("{}companies", db_config.text_db_path)).exists());
+    assert!(!Path::new(&file_path(&db_config, "", &other_id)).exists());
+}
+
+#[serial]
+#[test]
+fn relayout_moves_existing_files() {
+    setup_test();
+    let flat_config = get_test_config();
+    let person_id = insert_entity_to_db(
+        &json!({ "title": "Piotr", "_collection": "people" }),
+        &flat_config,
+    )
+    .unwrap();
+    let note_id = insert_entity_to_db(&json!({ "title": "Notes" }), &flat_config).unwrap();
+
+    let collection_config = get_layout_config(FileLayout::Collection);
+    assert_eq!(relayout_files(&collection_config).unwrap(), 1);
+    assert!(Path::new(&file_path(&collection_config, "people/", &person_id)).exists());
+    assert!(Path::new(&file_path(&collection_config, "", &note_id)).exists());
+
+    let hash_config = get_layout_config(FileLayout::HashPrefix);
+    assert_eq!(relayout_files(&hash_config).unwrap(), 2);
+    assert_eq!(relayout_files(&hash_config).unwrap(), 0);
+    assert!(!Path::new(&format!("{}people", hash_config.text_db_path)).exists());
+    // moved files keep their recorded state
+    assert_eq!(
+        sync_from_files(&hash_config).unwrap(),
+        SyncReport::default()
+    );
+    delete_entity_from_db(&note_id, &hash_config).unwrap();
+
+    assert_eq!(relayout_files(&flat_config).unwrap(), 1);
+    assert!(Path::new(&file_path(&flat_config, "", &person_id)).exists());
+    let entry = get_entry_from_db(&person_id, &flat_config)
+        .unwrap()
+        .unwrap();
+    assert_eq!(entry.entity["title"], json!("Piotr"));
+}
+
+#[serial]
+#[test]
+fn files_stored_with_other_layout_are_not_duplicated() {
+    setup_test();
+    let flat_config = get_test_config();
+    for file_layout in [FileLayout::HashPrefix, FileLayout::UlidMonth] {
+        let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &flat_config).unwrap();
+        let db_config = get_layout_config(file_layout);
+        let filename = Path::new(&db_config.text_db_path)
+            .join(file_layout.entity_dir(&entity_id, &json!(null)))
+            .join(format!("{}.yaml", entity_id));
+        assert!(!filename.exists());
+
+        update_entity_in_db(&entity_id, &json!({ "title": "My week" }), &db_config).unwrap();
+        assert!(filename.exists());
+        assert!(!Path::new(&file_path(&flat_config, "", &entity_id)).exists());
+        assert_eq!(sync_from_files(&db_config).unwrap(), SyncReport::default());
+
+        // e.g. layout changed back after restart
+        delete_entity_from_db(&entity_id, &get_test_config()).unwrap();
+        assert!(!filename.exists());
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use rust_doc_db::doc_db::{
+    delete_entity_from_db, get_entry_from_db, insert_entity_to_db,
+    links::{get_links_from, get_links_to, link_entities, unlink_entities, LinkDeleteRule},
+    model::DocDbLink,
+    DbConfig,
+};
+use serde_json::json;
+use serial_test::serial;
+use std::fs;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn can_lookup_links_in_both_directions() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let company_id = insert_entity_to_db(&json!({ "name": "ACME" }), &db_config).unwrap();
+    let person_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    link_entities(&person_id, "works_at", &company_id, &db_config).unwrap();
+
+    let expected_link = DocDbLink {
+        source_id: person_id,
+        link_type: "works_at".to_string(),
+        target_id: company_id,
+    };
+    assert_eq!(
+        get_links_from(&person_id, &db_config).unwrap(),
+        vec![expected_link.clone()]
+    );
+    assert_eq!(
+        get_links_to(&company_id, &db_config).unwrap(),
+        vec![expected_link]
+    );
+
+    let yaml_entity =
+        fs::read_to_string(format!("{}{}.yaml", db_config.text_db_path, person_id)).unwrap();
+    assert!(yaml_entity.contains(&format!("works_at:\n  - {}", company_id)));
+
+    unlink_entities(&person_id, "works_at", &company_id, &db_config).unwrap();
+    assert!(get_links_to(&company_id, &db_config).unwrap().is_empty());
+}
+
+#[serial]
+#[test]
+fn cannot_link_missing_entity() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let person_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    assert!(link_entities(&person_id, "works_at", &ulid::Ulid::new(), &db_config).is_err());
+}
+
+#[serial]
+#[test]
+fn deleting_linked_entity_follows_link_delete_rules() {
+    setup_test();
+    let db_config = DbConfig {
+        link_delete_rules: HashMap::from([
+            ("mentions".to_string(), LinkDeleteRule::Nullify),
+            ("attached_to".to_string(), LinkDeleteRule::Cascade),
+        ]),
+        ..get_test_config()
+    };
+
+    let company_id = insert_entity_to_db(&json!({ "name": "ACME" }), &db_config).unwrap();
+    let person_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    let diary_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+    let note_id = insert_entity_to_db(&json!({ "title": "Note" }), &db_config).unwrap();
+    link_entities(&person_id, "works_at", &company_id, &db_config).unwrap();
+    link_entities(&diary_id, "mentions", &person_id, &db_config).unwrap();
+    link_entities(&note_id, "attached_to", &person_id, &db_config).unwrap();
+
+    assert!(delete_entity_from_db(&company_id, &db_config).is_err());
+    assert!(get_entry_from_db(&company_id, &db_config)
+        .unwrap()
+        .is_some());
+
+    delete_entity_from_db(&person_id, &db_config).unwrap();
+    assert!(get_entry_from_db(&person_id, &db_config).unwrap().is_none());
+    assert!(get_entry_from_db(&note_id, &db_config).unwrap().is_none());
+    let diary_entry = get_entry_from_db(&diary_id, &db_config).unwrap().unwrap();
+    assert_eq!(diary_entry.entity["_links"], json!({}));
+    assert!(get_links_to(&company_id, &db_config).unwrap().is_empty());
+
+    delete_entity_from_db(&company_id, &db_config).unwrap();
+}
@@ -0,0 +1,173 @@
+use std::{fs, process::Command};
+
+use rust_doc_db::doc_db::{
+    get_entry_from_db, insert_entity_to_db,
+    merge::{merge_documents, merge_yaml_documents, FieldConflict},
+    sync::sync_from_files,
+    update_entity_in_db, DbConfig,
+};
+use serde_json::json;
+use serial_test::serial;
+use ulid::Ulid;
+
+use crate::test_helpers::setup_git_test;
+
+mod test_helpers;
+
+fn git(args: &[&str], db_config: &DbConfig) -> bool {
+    Command::new("git")
+        .args([
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@localhost",
+            "-c",
+        ])
+        .arg(format!(
+            "merge.rust-doc-db.driver={} %O %A %B %L",
+            env!("CARGO_BIN_EXE_rust_doc_db_merge")
+        ))
+        .args(args)
+        .current_dir(&db_config.text_db_path)
+        .output()
+        .unwrap()
+        .status
+        .success()
+}
+
+/// Changes entity file on `other` branch and the entity in DB on the current one, then merges
+fn merge_concurrent_edits(
+    db_config: &DbConfig,
+    entity_id: &Ulid,
+    their_yaml: &str,
+    our_entity: &serde_json::Value,
+) -> bool {
+    let file_path = format!("{}{}.yaml", db_config.text_db_path, entity_id);
+    assert!(git(&["checkout", "--quiet", "-b", "other"], db_config));
+    fs::write(&file_path, their_yaml).unwrap();
+    assert!(git(
+        &["commit", "--quiet", "--all", "-m", "their edit"],
+        db_config
+    ));
+    assert!(git(&["checkout", "--quiet", "-"], db_config));
+    update_entity_in_db(entity_id, our_entity, db_config).unwrap();
+    git(&["merge", "--quiet", "--no-edit", "other"], db_config)
+}
+
+#[test]
+fn documents_are_merged_field_by_field() {
+    let base = json!({ "title": "My day", "tags": ["a"], "address": { "city": "Warsaw", "zip": "00-001" } });
+    let ours = json!({ "title": "My week", "tags": ["a"], "address": { "city": "Cracow", "zip": "00-001" } });
+    let theirs = json!({ "title": "My day", "tags": ["b"], "address": { "city": "Warsaw", "zip": "30-001" }, "pulled": true });
+
+    let result = merge_documents(&base, &ours, &theirs);
+    assert!(result.conflicts.is_empty());
+    assert_eq!(
+        result.merged,
+        json!({ "title": "My week", "tags": ["b"], "address": { "city": "Cracow", "zip": "30-001" }, "pulled": true })
+    );
+
+    // fields missing in one version are kept, like when merging entities of different domains
+    let result = merge_documents(&base, &json!({ "title": "My day" }), &theirs);
+    assert_eq!(result.merged, theirs);
+    // unless the other version left them unchanged
+    let result = merge_documents(&base, &json!({ "title": "My week" }), &base);
+    assert_eq!(result.merged, json!({ "title": "My week" }));
+    let result = merge_documents(&base, &base, &json!({ "title": "My week" }));
+    assert_eq!(result.merged, json!({ "title": "My week" }));
+    let result = merge_documents(
+        &base,
+        &json!({ "title": "My day", "tags": ["c"], "address": { "city": "Warsaw", "zip": "00-001" } }),
+        &json!({ "title": "My day" }),
+    );
+    assert_eq!(result.merged, json!({ "title": "My day", "tags": ["c"] }));
+
+    let result = merge_documents(&base, &ours, &json!({ "address": { "city": "Gdansk" } }));
+    assert_eq!(
+        result.conflicts,
+        vec![FieldConflict {
+            field_path: "address.city".to_string(),
+            ours: json!("Cracow"),
+            theirs: json!("Gdansk"),
+        }]
+    );
+    assert_eq!(result.merged["address"]["city"], json!("Cracow"));
+
+    // merged YAML keeps our comments and formatting of fields
+    let (merged_yaml, clean) = merge_yaml_documents(
+        "title: My day\nplace: home\n",
+        "# reviewed\ntitle:   My day\nplace: home\n",
+        "title: My day\nplace: office\n",
+        7,
+    )
+    .unwrap();
+    assert!(clean);
+    assert_eq!(merged_yaml, "place: office\n# reviewed\ntitle:   My day\n");
+
+    let (merged_yaml, clean) = merge_yaml_documents(
+        "title: My day\naddress:\n  city: Warsaw\n",
+        "title: My week\naddress:\n  city: Cracow\n",
+        "title: My day\naddress:\n  city: Gdansk\n",
+        7,
+    )
+    .unwrap();
+    assert!(!clean);
+    assert_eq!(
+        merged_yaml,
+        "address:\n<<<<<<< ours\n  city: Cracow\n=======\n  city: Gdansk\n>>>>>>> theirs\ntitle: My week\n"
+    );
+}
+
+#[serial]
+#[test]
+fn git_merges_concurrent_edits_of_entity_files() {
+    let db_config = setup_git_test();
+    assert!(
+        fs::read_to_string(format!("{}.gitattributes", db_config.text_db_path))
+            .unwrap()
+            .contains("*.yaml merge=rust-doc-db")
+    );
+    let entity_id = insert_entity_to_db(
+        &json!({ "title": "My day", "place": "home", "note": "first" }),
+        &db_config,
+    )
+    .unwrap();
+
+    // lines next to each other would conflict in a line-based merge
+    let merged = merge_concurrent_edits(
+        &db_config,
+        &entity_id,
+        "note: first\nplace: office\ntitle: My day\n",
+        &json!({ "title": "My week", "place": "home", "note": "second" }),
+    );
+    assert!(merged);
+    sync_from_files(&db_config).unwrap();
+    let entry = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
+    assert_eq!(
+        entry.entity,
+        json!({ "title": "My week", "place": "office", "note": "second" })
+    );
+}
+
+#[serial]
+#[test]
+fn git_leaves_markers_only_for_conflicting_fields() {
+    let db_config = setup_git_test();
+    let entity_id =
+        insert_entity_to_db(&json!({ "title": "My day", "place": "home" }), &db_config).unwrap();
+
+    let merged = merge_concurrent_edits(
+        &db_config,
+        &entity_id,
+        "title: My month\nplace: office\n",
+        &json!({ "title": "My week", "place": "home" }),
+    );
+    assert!(!merged);
+    let file_content =
+        fs::read_to_string(format!("{}{}.yaml", db_config.text_db_path, entity_id)).unwrap();
+    assert_eq!(
+        file_content,
+        "place: office\n<<<<<<< ours\ntitle: My week\n=======\ntitle: My month\n>>>>>>> theirs\n"
+    );
+    assert!(git(&["merge", "--abort"], &db_config));
+}
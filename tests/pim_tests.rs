@@ -1,5 +1,9 @@
 use rust_doc_db::{
-    doc_db::insert_entity_to_db,
+    doc_db::{
+        create_document_indexes,
+        document::{DocDbDocument, DocDbSchema},
+        insert_entity_to_db,
+    },
     example_domains::pim::{get_phones_of_people_by_firstname, model::Person},
 };
 use serde_json::json;
@@ -47,3 +51,26 @@ fn can_query_phones_by_name() {
     assert_eq!(phones[1], "+48 789 123 456");
     assert_eq!(phones[2], "+48 345 345 345");
 }
+
+#[serial]
+#[test]
+fn person_document_declares_schema_and_indexes() {
+    setup_test();
+    let db_config = get_test_config();
+
+    assert_eq!(Person::COLLECTION, Some("people"));
+    assert_eq!(Person::INDEXED_FIELDS, &["firstname", "lastname"]);
+    assert_eq!(Person::FIRSTNAME.json_path(), "$.firstname");
+
+    let schema = Person::json_schema();
+    assert_eq!(schema["title"], "Person");
+    assert_eq!(schema["version"], 1);
+    assert_eq!(schema["properties"]["phones"]["items"]["type"], "string");
+    assert_eq!(
+        schema["properties"]["addresses"]["items"]["properties"]["home_number"]["type"],
+        "integer"
+    );
+
+    create_document_indexes::<Person>(&db_config).unwrap();
+    create_document_indexes::<Person>(&db_config).unwrap();
+}
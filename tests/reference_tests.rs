@@ -0,0 +1,140 @@
+use rust_doc_db::doc_db::{
+    document::DocDbDocument,
+    insert_entity_to_db,
+    references::{
+        get_entries_with_references, get_entry_with_references, EmbeddedReference, ReadOptions,
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[derive(Debug, Serialize, Deserialize, DocDbDocument)]
+#[doc_db(collection = "employees")]
+struct Employee {
+    firstname: String,
+    #[doc_db(reference)]
+    employer_id: String,
+    #[doc_db(reference)]
+    friend_ids: Vec<String>,
+}
+
+#[serial]
+#[test]
+fn can_embed_referenced_documents_on_read() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let company_id =
+        insert_entity_to_db(&json!({ "name": "ACME", "city": "Warsaw" }), &db_config).unwrap();
+    let friend_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    let employee = Employee {
+        firstname: "Piotr".to_string(),
+        employer_id: company_id.to_string(),
+        friend_ids: vec![friend_id.to_string()],
+    };
+    let employee_id = insert_entity_to_db(&json!(employee), &db_config).unwrap();
+
+    let read_options = ReadOptions::embedding_references_of::<Employee>();
+    let entry = get_entry_with_references(&employee_id, &read_options, &db_config)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        entry.entity["employer"],
+        json!({ "name": "ACME", "city": "Warsaw" })
+    );
+    assert_eq!(entry.entity["friends"], json!([{ "firstname": "Jan" }]));
+    assert_eq!(entry.entity["employer_id"], company_id.to_string());
+
+    let read_options = ReadOptions::default()
+        .embed(EmbeddedReference::new("employer_id").projection(&["city"]))
+        .embed(EmbeddedReference::new("manager_id"));
+    let entry = get_entry_with_references(&employee_id, &read_options, &db_config)
+        .unwrap()
+        .unwrap();
+    assert_eq!(entry.entity["employer"], json!({ "city": "Warsaw" }));
+    assert!(entry.entity.get("manager").is_none());
+}
+
+#[serial]
+#[test]
+fn can_query_by_referenced_document_fields() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let warsaw_company_id =
+        insert_entity_to_db(&json!({ "name": "ACME", "city": "Warsaw" }), &db_config).unwrap();
+    let cracow_company_id =
+        insert_entity_to_db(&json!({ "name": "Wawel", "city": "Cracow" }), &db_config).unwrap();
+    for (firstname, company_id) in [
+        ("Jan", warsaw_company_id),
+        ("Piotr", cracow_company_id),
+        ("Marek", warsaw_company_id),
+    ] {
+        let employee = Employee {
+            firstname: firstname.to_string(),
+            employer_id: company_id.to_string(),
+            friend_ids: Vec::new(),
+        };
+        insert_entity_to_db(&json!(employee), &db_config).unwrap();
+    }
+
+    let entries = get_entries_with_references(
+        &Employee::EMPLOYER_ID.referenced("city").eq("Warsaw"),
+        &ReadOptions::default().embed(EmbeddedReference::new("employer_id")),
+        &db_config,
+    )
+    .unwrap();
+    let mut firstnames: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry.entity["firstname"].as_str().unwrap())
+        .collect();
+    firstnames.sort();
+    assert_eq!(firstnames, vec!["Jan", "Marek"]);
+    assert!(entries
+        .iter()
+        .all(|entry| entry.entity["employer"]["city"] == "Warsaw"));
+}
+
+#[serial]
+#[test]
+fn expired_referenced_documents_are_neither_embedded_nor_matched() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let company_id = insert_entity_to_db(
+        &json!({ "name": "ACME", "city": "Warsaw", "_expires_at": "2000-01-01T00:00:00Z" }),
+        &db_config,
+    )
+    .unwrap();
+    let friend_id = insert_entity_to_db(
+        &json!({ "firstname": "Jan", "_expires_at": "2000-01-01T00:00:00Z" }),
+        &db_config,
+    )
+    .unwrap();
+    let employee = Employee {
+        firstname: "Piotr".to_string(),
+        employer_id: company_id.to_string(),
+        friend_ids: vec![friend_id.to_string()],
+    };
+    let employee_id = insert_entity_to_db(&json!(employee), &db_config).unwrap();
+
+    let read_options = ReadOptions::embedding_references_of::<Employee>();
+    let entry = get_entry_with_references(&employee_id, &read_options, &db_config)
+        .unwrap()
+        .unwrap();
+    assert!(entry.entity.get("employer").is_none());
+    assert_eq!(entry.entity["friends"], json!([]));
+
+    let entries = get_entries_with_references(
+        &Employee::EMPLOYER_ID.referenced("city").eq("Warsaw"),
+        &read_options,
+        &db_config,
+    )
+    .unwrap();
+    assert!(entries.is_empty());
+}
@@ -0,0 +1,105 @@
+use std::{fs, path::Path};
+
+use rust_doc_db::doc_db::{
+    delete_entity_from_db, get_entry_from_db, insert_entity_to_db,
+    sync::{sync_from_files, SyncReport},
+    update_entity_in_db, DbConfig, DocDbError,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+fn get_slug_config() -> DbConfig {
+    let db_config = get_test_config();
+    db_config
+        .file_slugs
+        .declare("people", "{lastname}-{firstname}-{id}")
+        .unwrap();
+    db_config
+}
+
+fn file_exists(db_config: &DbConfig, file_name: &str) -> bool {
+    Path::new(&format!("{}{}", db_config.text_db_path, file_name)).exists()
+}
+
+#[serial]
+#[test]
+fn files_are_named_by_slug_templates() {
+    setup_test();
+    let db_config = get_slug_config();
+    let person_id = insert_entity_to_db(
+        &json!({ "_collection": "people", "firstname": "Jan", "lastname": "Nowak Jeziorański" }),
+        &db_config,
+    )
+    .unwrap();
+    let note_id = insert_entity_to_db(&json!({ "title": "Notes" }), &db_config).unwrap();
+    assert!(file_exists(
+        &db_config,
+        &format!("nowak-jeziorański-jan-{}.yaml", person_id)
+    ));
+    assert!(file_exists(&db_config, &format!("{}.yaml", note_id)));
+
+    update_entity_in_db(
+        &person_id,
+        &json!({ "_collection": "people", "firstname": "Jan", "lastname": "Kowalski" }),
+        &db_config,
+    )
+    .unwrap();
+    assert!(file_exists(
+        &db_config,
+        &format!("kowalski-jan-{}.yaml", person_id)
+    ));
+    assert!(!file_exists(
+        &db_config,
+        &format!("nowak-jeziorański-jan-{}.yaml", person_id)
+    ));
+    assert_eq!(sync_from_files(&db_config).unwrap(), SyncReport::default());
+
+    // file is found by ULID also when the index has to be built
+    let other_config = get_slug_config();
+    delete_entity_from_db(&person_id, &other_config).unwrap();
+    assert!(!file_exists(
+        &db_config,
+        &format!("kowalski-jan-{}.yaml", person_id)
+    ));
+    assert!(get_entry_from_db(&person_id, &db_config).unwrap().is_none());
+
+    let result = db_config.file_slugs.declare("companies", "{name}");
+    assert!(matches!(result, Err(DocDbError::Validation { .. })));
+}
+
+#[serial]
+#[test]
+fn files_renamed_by_hand_are_found_by_ulid() {
+    setup_test();
+    let db_config = get_slug_config();
+    let person_id = insert_entity_to_db(
+        &json!({ "_collection": "people", "firstname": "Jan", "lastname": "Kowalski" }),
+        &db_config,
+    )
+    .unwrap();
+    fs::rename(
+        format!("{}kowalski-jan-{}.yaml", db_config.text_db_path, person_id),
+        format!("{}my-friend-{}.yaml", db_config.text_db_path, person_id),
+    )
+    .unwrap();
+    assert_eq!(sync_from_files(&db_config).unwrap(), SyncReport::default());
+
+    update_entity_in_db(
+        &person_id,
+        &json!({ "_collection": "people", "firstname": "Janek", "lastname": "Kowalski" }),
+        &db_config,
+    )
+    .unwrap();
+    assert!(file_exists(
+        &db_config,
+        &format!("kowalski-janek-{}.yaml", person_id)
+    ));
+    assert!(!file_exists(
+        &db_config,
+        &format!("my-friend-{}.yaml", person_id)
+    ));
+}
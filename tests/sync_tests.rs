@@ -0,0 +1,91 @@
+use std::fs;
+
+use rust_doc_db::doc_db::{
+    get_entry_from_db, insert_entity_to_db,
+    sync::{sync_from_files, SyncReport},
+    DocDbError,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn sync_imports_changed_added_and_removed_files() {
+    setup_test();
+    let db_config = get_test_config();
+    let changed_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+    let removed_id = insert_entity_to_db(&json!({ "title": "My week" }), &db_config).unwrap();
+    let kept_id = insert_entity_to_db(&json!({ "title": "My year" }), &db_config).unwrap();
+    assert_eq!(sync_from_files(&db_config).unwrap(), SyncReport::default());
+
+    let added_id = ulid::Ulid::new();
+    let file_path =
+        |entity_id: &ulid::Ulid| format!("{}{}.yaml", db_config.text_db_path, entity_id);
+    fs::write(
+        file_path(&changed_id),
+        "title: My whole day\npulled: true\n",
+    )
+    .unwrap();
+    fs::remove_file(file_path(&removed_id)).unwrap();
+    fs::write(file_path(&added_id), "title: My month\n").unwrap();
+    fs::write(
+        format!("{}notes.yaml", db_config.text_db_path),
+        "title: Notes\n",
+    )
+    .unwrap();
+
+    let report = sync_from_files(&db_config).unwrap();
+    assert_eq!(
+        report,
+        SyncReport {
+            inserted: 1,
+            updated: 1,
+            deleted: 1
+        }
+    );
+    let changed = get_entry_from_db(&changed_id, &db_config).unwrap().unwrap();
+    assert_eq!(
+        changed.entity,
+        json!({ "title": "My whole day", "pulled": true })
+    );
+    let added = get_entry_from_db(&added_id, &db_config).unwrap().unwrap();
+    assert_eq!(added.entity, json!({ "title": "My month" }));
+    assert!(get_entry_from_db(&removed_id, &db_config)
+        .unwrap()
+        .is_none());
+    assert!(get_entry_from_db(&kept_id, &db_config).unwrap().is_some());
+
+    assert_eq!(sync_from_files(&db_config).unwrap(), SyncReport::default());
+    fs::remove_file(format!("{}notes.yaml", db_config.text_db_path)).unwrap();
+}
+
+#[serial]
+#[test]
+fn sync_rejects_unparseable_files() {
+    setup_test();
+    let db_config = get_test_config();
+    let entity_id = insert_entity_to_db(&json!({ "title": "My day" }), &db_config).unwrap();
+    let added_id = ulid::Ulid::new();
+    fs::write(
+        format!("{}{}.yaml", db_config.text_db_path, added_id),
+        "title: My month\n",
+    )
+    .unwrap();
+    fs::write(
+        format!("{}{}.yaml", db_config.text_db_path, entity_id),
+        "title: [My day\n",
+    )
+    .unwrap();
+
+    let result = sync_from_files(&db_config);
+    assert!(matches!(
+        result,
+        Err(DocDbError::Corruption { path, .. }) if path.ends_with(&format!("{}.yaml", entity_id))
+    ));
+    // nothing is imported when any file can't be
+    assert!(get_entry_from_db(&added_id, &db_config).unwrap().is_none());
+}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use rust_doc_db::doc_db::{
+    document::{DocField, DocQuery},
+    get_entry_from_db, insert_entity_to_db, tag_entity,
+    tags::{
+        get_all_tags, get_entries_by_tags, rename_tag, tag_entries, untag_entries, TagCount,
+        TagMatch, TagVocabulary,
+    },
+    update_entity_in_db, DbConfig,
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn can_list_and_find_tagged_entities() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let first_id =
+        insert_entity_to_db(&json!({ "title": "First", "tags": ["a", "b"] }), &db_config).unwrap();
+    let second_id = insert_entity_to_db(&json!({ "title": "Second" }), &db_config).unwrap();
+    tag_entity(&second_id, "a", &db_config).unwrap();
+
+    assert_eq!(
+        get_all_tags(&db_config).unwrap(),
+        vec![
+            TagCount {
+                tag: "a".to_string(),
+                count: 2
+            },
+            TagCount {
+                tag: "b".to_string(),
+                count: 1
+            },
+        ]
+    );
+
+    let any_entries = get_entries_by_tags(&["a", "b"], TagMatch::Any, &db_config).unwrap();
+    assert_eq!(any_entries.len(), 2);
+
+    let all_entries = get_entries_by_tags(&["a", "b"], TagMatch::All, &db_config).unwrap();
+    assert_eq!(all_entries.len(), 1);
+    assert_eq!(all_entries[0].id, first_id);
+}
+
+#[serial]
+#[test]
+fn can_rename_and_merge_tags() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let first_id = insert_entity_to_db(&json!({ "tags": ["todo", "urgent"] }), &db_config).unwrap();
+    let second_id = insert_entity_to_db(&json!({ "tags": ["todo", "later"] }), &db_config).unwrap();
+
+    assert_eq!(rename_tag("todo", "urgent", &db_config).unwrap(), 2);
+
+    let first_entry = get_entry_from_db(&first_id, &db_config).unwrap().unwrap();
+    assert_eq!(first_entry.entity["tags"], json!(["urgent"]));
+    let second_entry = get_entry_from_db(&second_id, &db_config).unwrap().unwrap();
+    assert_eq!(second_entry.entity["tags"], json!(["urgent", "later"]));
+    assert!(get_entries_by_tags(&["todo"], TagMatch::Any, &db_config)
+        .unwrap()
+        .is_empty());
+}
+
+#[serial]
+#[test]
+fn can_tag_and_untag_entities_by_query() {
+    setup_test();
+    let db_config = get_test_config();
+    const CITY: DocField = DocField::new("city");
+
+    insert_entity_to_db(&json!({ "city": "Warsaw" }), &db_config).unwrap();
+    insert_entity_to_db(&json!({ "city": "Warsaw", "tags": ["local"] }), &db_config).unwrap();
+    insert_entity_to_db(&json!({ "city": "Cracow" }), &db_config).unwrap();
+
+    assert_eq!(
+        tag_entries(&CITY.eq("Warsaw"), "local", &db_config).unwrap(),
+        1
+    );
+    assert_eq!(
+        get_entries_by_tags(&["local"], TagMatch::Any, &db_config)
+            .unwrap()
+            .len(),
+        2
+    );
+
+    assert_eq!(
+        untag_entries(&CITY.ne("Cracow"), "local", &db_config).unwrap(),
+        2
+    );
+    assert!(get_all_tags(&db_config).unwrap().is_empty());
+
+    // field names given at runtime, e.g. `tag --filter city=Cracow`
+    assert_eq!(
+        tag_entries(&DocQuery::field_eq("city", "Cracow"), "far", &db_config).unwrap(),
+        1
+    );
+    assert_eq!(
+        untag_entries(&DocQuery::field_eq("city", "Warsaw"), "far", &db_config).unwrap(),
+        0
+    );
+    insert_entity_to_db(&json!({ "city": "Gdansk", "rating": 5 }), &db_config).unwrap();
+    assert_eq!(
+        tag_entries(&DocQuery::field_eq("rating", 5), "rated", &db_config).unwrap(),
+        1
+    );
+    assert_eq!(
+        tag_entries(&DocQuery::field_eq("rating", "5"), "rated", &db_config).unwrap(),
+        0
+    );
+}
+
+#[serial]
+#[test]
+fn tag_matches_its_descendants() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let urgent_id = insert_entity_to_db(&json!({}), &db_config).unwrap();
+    tag_entity(&urgent_id, "project/alpha/urgent", &db_config).unwrap();
+    let status_id = insert_entity_to_db(&json!({}), &db_config).unwrap();
+    tag_entity(&status_id, "project/alpha:done", &db_config).unwrap();
+    let other_id = insert_entity_to_db(&json!({}), &db_config).unwrap();
+    tag_entity(&other_id, "project/alphabet", &db_config).unwrap();
+
+    let entries = get_entries_by_tags(&["project/alpha"], TagMatch::Any, &db_config).unwrap();
+    let entry_ids: Vec<_> = entries.iter().map(|entry| entry.id).collect();
+    assert_eq!(entry_ids.len(), 2);
+    assert!(entry_ids.contains(&urgent_id));
+    assert!(entry_ids.contains(&status_id));
+
+    assert_eq!(
+        get_entries_by_tags(
+            &["project", "project/alpha/urgent"],
+            TagMatch::All,
+            &db_config
+        )
+        .unwrap()
+        .len(),
+        1
+    );
+}
+
+#[serial]
+#[test]
+fn tags_are_validated_against_vocabulary() {
+    setup_test();
+    let db_config = DbConfig {
+        tag_vocabulary: Some(TagVocabulary {
+            keys: HashMap::from([
+                (
+                    "status".to_string(),
+                    vec!["todo".to_string(), "done".to_string()],
+                ),
+                ("owner".to_string(), Vec::new()),
+            ]),
+            allow_plain_tags: false,
+        }),
+        ..get_test_config()
+    };
+
+    let entity_id = insert_entity_to_db(&json!({}), &db_config).unwrap();
+    tag_entity(&entity_id, "status:done", &db_config).unwrap();
+    tag_entity(&entity_id, "owner:anyone", &db_config).unwrap();
+    assert!(tag_entity(&entity_id, "status:unknown", &db_config).is_err());
+    assert!(tag_entity(&entity_id, "priority:high", &db_config).is_err());
+    assert!(tag_entity(&entity_id, "important", &db_config).is_err());
+    assert!(tag_entity(&entity_id, "status:", &db_config).is_err());
+    assert!(tag_entity(&entity_id, "project//alpha", &db_config).is_err());
+
+    let entry = get_entry_from_db(&entity_id, &db_config).unwrap().unwrap();
+    assert_eq!(entry.entity["tags"], json!(["status:done", "owner:anyone"]));
+
+    // tags written directly are validated too
+    assert!(
+        update_entity_in_db(&entity_id, &json!({ "tags": ["status:lost"] }), &db_config).is_err()
+    );
+    assert!(insert_entity_to_db(&json!({ "tags": ["important"] }), &db_config).is_err());
+    assert!(insert_entity_to_db(&json!({ "tags": [5] }), &db_config).is_err());
+    assert!(insert_entity_to_db(&json!({ "tags": "status:done" }), &db_config).is_err());
+    insert_entity_to_db(&json!({ "tags": ["status:todo"] }), &db_config).unwrap();
+}
@@ -1,14 +1,17 @@
-use std::sync::Once;
+use std::{fs, sync::Once};
 
-use rust_doc_db::doc_db::{clear_db, make_sure_db_exists, DbConfig};
+use rust_doc_db::{
+    doc_db::{clear_db, git::GitSettings, make_sure_db_exists, DbConfig},
+    example_domains::pim::model::Person,
+};
 
 static INIT: Once = Once::new();
 
 pub fn get_test_config() -> DbConfig {
-    
     DbConfig {
         sqlite_db_full_filename: "tmp/test_db/data.db".to_string(),
         text_db_path: "tmp/test_db/files/".to_string(),
+        ..Default::default()
     }
 }
 
@@ -24,3 +27,27 @@ pub fn setup_test() {
     make_sure_db_exists(&db_config).unwrap();
     let _ = clear_db(&db_config);
 }
+
+/// Test DB with a fresh repository
+#[allow(dead_code)]
+pub fn setup_git_test() -> DbConfig {
+    setup_test();
+    let db_config = DbConfig {
+        git: Some(GitSettings::default()),
+        ..get_test_config()
+    };
+    let _ = fs::remove_dir_all(format!("{}.git", db_config.text_db_path));
+    let _ = fs::remove_file(format!("{}.gitattributes", db_config.text_db_path));
+    make_sure_db_exists(&db_config).unwrap();
+    db_config
+}
+
+#[allow(dead_code)]
+pub fn person(firstname: &str, lastname: &str, phones: &[&str]) -> Person {
+    Person {
+        firstname: firstname.to_string(),
+        lastname: lastname.to_string(),
+        phones: phones.iter().map(|phone| phone.to_string()).collect(),
+        addresses: Vec::new(),
+    }
+}
@@ -0,0 +1,88 @@
+use rust_doc_db::doc_db::{
+    get_entry_from_db, insert_entity_to_db, tags::TAGS_FIELD_NAME, DocDbResult,
+};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::{fs, path::Path};
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn can_merge_duplicates_in_transaction() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let person_id = insert_entity_to_db(
+        &json!({ "firstname": "Jan", "tags": ["friend"] }),
+        &db_config,
+    )
+    .unwrap();
+    let duplicate_id = insert_entity_to_db(
+        &json!({ "firstname": "Jan", "tags": ["colleague"] }),
+        &db_config,
+    )
+    .unwrap();
+
+    db_config
+        .transaction(|tx| {
+            let duplicate = tx.get(&duplicate_id)?.unwrap();
+            let mut person = tx.get(&person_id)?.unwrap();
+            let tags = person.entity[TAGS_FIELD_NAME].as_array_mut().unwrap();
+            tags.extend(
+                duplicate.entity[TAGS_FIELD_NAME]
+                    .as_array()
+                    .unwrap()
+                    .clone(),
+            );
+            tx.update(&person_id, &person.entity)?;
+            tx.delete(&duplicate_id)
+        })
+        .unwrap();
+
+    let person = get_entry_from_db(&person_id, &db_config).unwrap().unwrap();
+    assert_eq!(person.entity["tags"], json!(["friend", "colleague"]));
+    assert!(get_entry_from_db(&duplicate_id, &db_config)
+        .unwrap()
+        .is_none());
+    let yaml_entity =
+        fs::read_to_string(format!("{}{}.yaml", db_config.text_db_path, person_id)).unwrap();
+    assert!(yaml_entity.contains("colleague"));
+    assert!(!Path::new(&format!("{}{}.yaml", db_config.text_db_path, duplicate_id)).exists());
+}
+
+#[serial]
+#[test]
+fn rollback_leaves_both_stores_untouched() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let person_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    let yaml_filename = format!("{}{}.yaml", db_config.text_db_path, person_id);
+    let original_yaml_entity = fs::read_to_string(&yaml_filename).unwrap();
+
+    let mut inserted_id = None;
+    let result: DocDbResult<()> = db_config.transaction(|tx| {
+        inserted_id = Some(tx.insert(&json!({ "firstname": "Eva" }))?);
+        tx.update(&person_id, &json!({ "lastname": "Novak" }))?;
+        // changes are visible inside the transaction
+        assert_eq!(tx.get(&person_id)?.unwrap().entity["lastname"], "Novak");
+        tx.delete(&ulid::Ulid::new())?;
+        tx.link(&person_id, "knows", &ulid::Ulid::new())
+    });
+    assert!(result.is_err());
+
+    let inserted_id = inserted_id.unwrap();
+    assert!(get_entry_from_db(&inserted_id, &db_config)
+        .unwrap()
+        .is_none());
+    assert!(!Path::new(&format!("{}{}.yaml", db_config.text_db_path, inserted_id)).exists());
+    let person = get_entry_from_db(&person_id, &db_config).unwrap().unwrap();
+    assert_eq!(person.entity["lastname"], Value::Null);
+    assert_eq!(
+        fs::read_to_string(&yaml_filename).unwrap(),
+        original_yaml_entity
+    );
+}
@@ -0,0 +1,97 @@
+use rust_doc_db::doc_db::{
+    document::DocField,
+    insert_entity_to_db,
+    links::link_entities,
+    traversal::{shortest_path, traverse, Traversal, TraversalDirection, TraversalEdge},
+};
+use serde_json::json;
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+#[serial]
+#[test]
+fn can_traverse_links_and_reference_fields() {
+    setup_test();
+    let db_config = get_test_config();
+    const FIRSTNAME: DocField = DocField::new("firstname");
+
+    let company_id = insert_entity_to_db(&json!({ "name": "ACME" }), &db_config).unwrap();
+    let jan_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    let piotr_id = insert_entity_to_db(&json!({ "firstname": "Piotr" }), &db_config).unwrap();
+    let marek_id = insert_entity_to_db(
+        &json!({ "firstname": "Marek", "friend_id": jan_id.to_string() }),
+        &db_config,
+    )
+    .unwrap();
+    link_entities(&jan_id, "works_at", &company_id, &db_config).unwrap();
+    link_entities(&piotr_id, "works_at", &company_id, &db_config).unwrap();
+
+    let traversal = Traversal::new(
+        vec![
+            TraversalEdge::Link("works_at".to_string()),
+            TraversalEdge::Field("friend_id".to_string()),
+        ],
+        2,
+    )
+    .direction(TraversalDirection::Incoming);
+
+    let results = traverse(&company_id, &traversal, &db_config).unwrap();
+    let reached: Vec<_> = results
+        .iter()
+        .map(|result| (result.entry.id, result.depth()))
+        .collect();
+    assert_eq!(reached.len(), 3);
+    assert!(reached.contains(&(jan_id, 1)));
+    assert!(reached.contains(&(piotr_id, 1)));
+    assert!(reached.contains(&(marek_id, 2)));
+
+    let two_hops = traverse(
+        &company_id,
+        &traversal.clone().min_depth(2).filter(FIRSTNAME.eq("Marek")),
+        &db_config,
+    )
+    .unwrap();
+    assert_eq!(two_hops.len(), 1);
+    assert_eq!(two_hops[0].path, vec![company_id, jan_id, marek_id]);
+    assert_eq!(two_hops[0].entry.entity["firstname"], "Marek");
+}
+
+#[serial]
+#[test]
+fn can_find_shortest_path_between_documents() {
+    setup_test();
+    let db_config = get_test_config();
+
+    let company_id = insert_entity_to_db(&json!({ "name": "ACME" }), &db_config).unwrap();
+    let jan_id = insert_entity_to_db(&json!({ "firstname": "Jan" }), &db_config).unwrap();
+    let piotr_id = insert_entity_to_db(&json!({ "firstname": "Piotr" }), &db_config).unwrap();
+    let marek_id = insert_entity_to_db(&json!({ "firstname": "Marek" }), &db_config).unwrap();
+    link_entities(&jan_id, "works_at", &company_id, &db_config).unwrap();
+    link_entities(&piotr_id, "works_at", &company_id, &db_config).unwrap();
+    link_entities(&piotr_id, "knows", &jan_id, &db_config).unwrap();
+
+    let traversal = Traversal::new(
+        vec![
+            TraversalEdge::Link("works_at".to_string()),
+            TraversalEdge::Link("knows".to_string()),
+        ],
+        5,
+    )
+    .direction(TraversalDirection::Both);
+
+    assert_eq!(
+        shortest_path(&jan_id, &piotr_id, &traversal, &db_config).unwrap(),
+        Some(vec![jan_id, piotr_id])
+    );
+    assert_eq!(
+        shortest_path(&company_id, &jan_id, &traversal, &db_config).unwrap(),
+        Some(vec![company_id, jan_id])
+    );
+    assert_eq!(
+        shortest_path(&jan_id, &marek_id, &traversal, &db_config).unwrap(),
+        None
+    );
+}
@@ -0,0 +1,94 @@
+use std::fs;
+
+use rust_doc_db::doc_db::{
+    formats::YamlFormat,
+    insert_entity_to_db,
+    sync::{sync_from_files, SyncReport},
+    update_entity_in_db,
+    yaml_formatter::KeyOrder,
+    DbConfig,
+};
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use crate::test_helpers::{get_test_config, setup_test};
+
+mod test_helpers;
+
+fn get_schema_order_config() -> DbConfig {
+    let db_config = get_test_config();
+    db_config
+        .file_formats
+        .set_default(YamlFormat::new(KeyOrder::schema(&["title", "date"])));
+    db_config
+}
+
+fn diary_entry() -> Value {
+    json!({
+        "date": "2021-01-01",
+        "description": "Today I went to the forest.\n\nIt was sunny.\n",
+        "place": { "country": "Poland", "city": "Warsaw" },
+        "rating": 5,
+        "tags": ["forest", "sunny"],
+        "title": "My day"
+    })
+}
+
+fn file_content(db_config: &DbConfig, entity_id: &ulid::Ulid) -> String {
+    fs::read_to_string(format!("{}{}.yaml", db_config.text_db_path, entity_id)).unwrap()
+}
+
+#[serial]
+#[test]
+fn files_are_written_in_key_order_with_block_scalars() {
+    setup_test();
+    let db_config = get_schema_order_config();
+    let entity_id = insert_entity_to_db(&diary_entry(), &db_config).unwrap();
+    assert_eq!(
+        file_content(&db_config, &entity_id),
+        include_str!("golden/diary_entry.yaml")
+    );
+
+    let alphabetical_config = get_test_config();
+    let entity_id = insert_entity_to_db(
+        &json!({ "title": "My day", "description": "Sunny.\nWarm." }),
+        &alphabetical_config,
+    )
+    .unwrap();
+    assert_eq!(
+        file_content(&alphabetical_config, &entity_id),
+        "description: |-\n  Sunny.\n  Warm.\ntitle: My day\n"
+    );
+}
+
+#[serial]
+#[test]
+fn comments_and_formatting_of_unchanged_fields_are_kept() {
+    setup_test();
+    let db_config = get_schema_order_config();
+    let entity_id = insert_entity_to_db(&diary_entry(), &db_config).unwrap();
+    let file_path = format!("{}{}.yaml", db_config.text_db_path, entity_id);
+    fs::write(&file_path, include_str!("golden/diary_entry_edited.yaml")).unwrap();
+    assert_eq!(sync_from_files(&db_config).unwrap(), SyncReport::default());
+
+    // same content in other formatting isn't rewritten
+    update_entity_in_db(&entity_id, &diary_entry(), &db_config).unwrap();
+    assert_eq!(
+        file_content(&db_config, &entity_id),
+        include_str!("golden/diary_entry_edited.yaml")
+    );
+
+    update_entity_in_db(
+        &entity_id,
+        &json!({
+            "description": "Today I went to the lake.\n\nIt was sunny.\n",
+            "rating": 9
+        }),
+        &db_config,
+    )
+    .unwrap();
+    assert_eq!(
+        file_content(&db_config, &entity_id),
+        include_str!("golden/diary_entry_updated.yaml")
+    );
+}